@@ -0,0 +1,337 @@
+use std::sync::Arc;
+
+use rig::{completion::CompletionModel, embeddings::EmbeddingModel};
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+use crate::agent::Agent;
+use crate::attention::Attention;
+use crate::character::Character;
+use crate::knowledge::{ChannelAttention, EngagementMode, ForgetSummary, Source};
+use crate::policy::{user_id, Admins, PolicyCommand};
+
+/// Name and description of every admin command, as registered with Discord
+/// and Telegram.
+pub const ADMIN_COMMANDS: &[(&str, &str)] = &[
+    ("mute", "Stay quiet in this channel for the given minutes or the default mute"),
+    ("unmute", "Lift a mute in this channel"),
+    ("mode", "Set the engagement mode of this channel"),
+    ("tweet", "Post a new tweet now"),
+    ("reload", "Reload the character file"),
+    ("stats", "Show knowledge base and channel statistics"),
+    ("forget", "Delete a user's messages and account by numeric id"),
+];
+
+/// Longest mute Discord offers for `/mute`
+pub const MAX_MUTE_MINUTES: u64 = 60 * 24 * 365;
+
+const MUTE_USAGE: &str = "Usage: /mute [minutes]";
+
+/// Messages are stored under platform ids, and usernames aren't recorded
+const FORGET_USAGE: &str =
+    "Usage: /forget <user id>. The numeric id is required, usernames can't be looked up";
+
+/// A command an admin can run from chat, as `/<name> [argument]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// Minutes, the character's mute duration when `None`
+    Mute(Option<u64>),
+    Unmute,
+    Mode(EngagementMode),
+    Tweet,
+    Reload,
+    Stats,
+    /// Platform id of the user, on the platform the command was sent from
+    Forget(String),
+}
+
+impl AdminCommand {
+    /// Builds a command from its name and arguments, describing the expected
+    /// usage when they don't fit.
+    pub fn from_parts(name: &str, args: &[&str]) -> Result<Self, String> {
+        match (name, args) {
+            ("mute", []) => Ok(AdminCommand::Mute(None)),
+            ("mute", [minutes]) => minutes
+                .parse()
+                .map(|minutes| AdminCommand::Mute(Some(minutes)))
+                .map_err(|_| MUTE_USAGE.to_string()),
+            ("unmute", []) => Ok(AdminCommand::Unmute),
+            ("mode", [mode]) => EngagementMode::from_str(mode)
+                .map(AdminCommand::Mode)
+                .ok_or_else(|| "Usage: /mode <default|always|mention-only|never>".to_string()),
+            ("tweet", []) => Ok(AdminCommand::Tweet),
+            ("reload", []) => Ok(AdminCommand::Reload),
+            ("stats", []) => Ok(AdminCommand::Stats),
+            ("forget", [user]) => {
+                let user_id = user_id(user);
+                if user_id.is_empty() || !user_id.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(FORGET_USAGE.to_string());
+                }
+                Ok(AdminCommand::Forget(user_id))
+            }
+            ("forget", _) => Err(FORGET_USAGE.to_string()),
+            (name, _) => Err(format!("Usage: /{}", name)),
+        }
+    }
+
+    /// `None` when `text` isn't an admin command. The bot's name may follow
+    /// the command, as in Telegram's `/stats@gihun_bot`.
+    pub fn parse(text: &str) -> Option<Result<Self, String>> {
+        let mut words = text.split_whitespace();
+        let name = words.next()?.strip_prefix('/')?;
+        let name = name.split_once('@').map_or(name, |(name, _)| name);
+        if !ADMIN_COMMANDS.iter().any(|(command, _)| *command == name) {
+            return None;
+        }
+        Some(Self::from_parts(name, &words.collect::<Vec<_>>()))
+    }
+}
+
+/// Asks the Twitter client to post a new tweet without waiting for its next
+/// turn. Requests made while it's busy are served once it's done.
+#[derive(Clone, Default)]
+pub struct TweetTrigger {
+    notify: Arc<Notify>,
+}
+
+impl TweetTrigger {
+    pub fn request(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Waits for the next request.
+    pub async fn requested(&self) {
+        self.notify.notified().await
+    }
+}
+
+type CharacterLoader = dyn Fn() -> Result<Character, String> + Send + Sync;
+
+/// Runs [`AdminCommand`]s and `!policy` commands for the users in
+/// [`Admins`].
+#[derive(Clone)]
+pub struct Admin<M: CompletionModel, E: EmbeddingModel + 'static> {
+    agent: Agent<M, E>,
+    attention: Attention<M>,
+    admins: Admins,
+    character_loader: Option<Arc<CharacterLoader>>,
+    tweet_trigger: Option<TweetTrigger>,
+}
+
+impl<M: CompletionModel, E: EmbeddingModel + 'static> Admin<M, E> {
+    pub fn new(agent: Agent<M, E>, attention: Attention<M>, admins: Admins) -> Self {
+        Self {
+            agent,
+            attention,
+            admins,
+            character_loader: None,
+            tweet_trigger: None,
+        }
+    }
+
+    /// How `/reload` reads the character again.
+    pub fn with_character_loader(
+        mut self,
+        loader: impl Fn() -> Result<Character, String> + Send + Sync + 'static,
+    ) -> Self {
+        self.character_loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Lets `/tweet` reach the running Twitter client.
+    pub fn with_tweet_trigger(mut self, tweet_trigger: TweetTrigger) -> Self {
+        self.tweet_trigger = Some(tweet_trigger);
+        self
+    }
+
+    pub fn is_admin(&self, source: &Source, user_id: &str) -> bool {
+        self.admins.contains(source, user_id)
+    }
+
+    /// Runs `command` sent in a channel of `source` and describes the outcome.
+    pub async fn run(&self, command: &AdminCommand, source: &Source, channel_id: &str) -> String {
+        info!(?command, source = source.as_str(), channel_id, "Running admin command");
+        let knowledge = self.agent.knowledge();
+
+        match command {
+            AdminCommand::Mute(minutes) => {
+                let Some(until) = mute_until(*minutes, self.attention.config().mute_duration) else {
+                    return MUTE_USAGE.to_string();
+                };
+                self.update_channel(source, channel_id, |state| state.muted_until = Some(until))
+                    .await
+                    .unwrap_or_else(|| {
+                        format!(
+                            "Muted until {}, unless addressed by name",
+                            until.format("%Y-%m-%d %H:%M UTC")
+                        )
+                    })
+            }
+            AdminCommand::Unmute => self
+                .update_channel(source, channel_id, |state| state.muted_until = None)
+                .await
+                .unwrap_or_else(|| "Unmuted".to_string()),
+            AdminCommand::Mode(mode) => PolicyCommand::Mode(*mode)
+                .run(knowledge, source, channel_id)
+                .await
+                .unwrap_or_else(|err| {
+                    error!(?err, "Failed to set engagement mode");
+                    "Failed to set the engagement mode".to_string()
+                }),
+            AdminCommand::Tweet => match &self.tweet_trigger {
+                Some(tweet_trigger) => {
+                    tweet_trigger.request();
+                    "Tweet requested".to_string()
+                }
+                None => "Twitter is not running".to_string(),
+            },
+            AdminCommand::Reload => {
+                let Some(loader) = &self.character_loader else {
+                    return "Reloading is not configured".to_string();
+                };
+                match loader() {
+                    Ok(character) => {
+                        let name = character.name.clone();
+                        self.attention.set_config(character.attention_config());
+                        self.agent.set_character(character);
+                        format!("Reloaded {}", name)
+                    }
+                    Err(err) => {
+                        error!(err, "Failed to reload character");
+                        format!("Failed to reload the character: {}", err)
+                    }
+                }
+            }
+            AdminCommand::Stats => self.stats(source, channel_id).await,
+            AdminCommand::Forget(user_id) => match knowledge.forget_user(source.clone(), user_id).await
+            {
+                Ok(summary) if summary == ForgetSummary::default() => {
+                    format!("Nothing is stored about user {}", user_id)
+                }
+                Ok(summary) => format!(
+                    "Deleted {} messages and {} accounts of user {}",
+                    summary.messages, summary.accounts, user_id
+                ),
+                Err(err) => {
+                    error!(?err, "Failed to forget user");
+                    "Failed to forget the user".to_string()
+                }
+            },
+        }
+    }
+
+    /// Changes the channel's attention state, returning an error message when
+    /// it couldn't.
+    async fn update_channel(
+        &self,
+        source: &Source,
+        channel_id: &str,
+        update: impl FnOnce(&mut ChannelAttention),
+    ) -> Option<String> {
+        let knowledge = self.agent.knowledge();
+        let result = async {
+            let mut state = knowledge.channel_attention(source, channel_id).await?;
+            update(&mut state);
+            knowledge.set_channel_attention(source, channel_id, &state).await
+        }
+        .await;
+
+        result.err().map(|err| {
+            error!(?err, "Failed to update channel attention state");
+            "Failed to update the channel".to_string()
+        })
+    }
+
+    async fn stats(&self, source: &Source, channel_id: &str) -> String {
+        let knowledge = self.agent.knowledge();
+        let (stats, state, policy) = match tokio::try_join!(
+            knowledge.stats(),
+            knowledge.channel_attention(source, channel_id),
+            knowledge.engagement_policy(source, channel_id),
+        ) {
+            Ok(stats) => stats,
+            Err(err) => {
+                error!(?err, "Failed to read stats");
+                return "Failed to read the stats".to_string();
+            }
+        };
+
+        let counts = |counts: &[(String, usize)]| {
+            counts
+                .iter()
+                .map(|(name, count)| format!("{} {}", count, name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let muted_until = state
+            .muted_until
+            .filter(|until| *until > chrono::Utc::now())
+            .map_or_else(|| "no".to_string(), |until| format!("until {}", until.format("%Y-%m-%d %H:%M UTC")));
+
+        format!(
+            "Character: {}\nDocuments: {}\nMessages: {}\nPending embeddings: {}\n\
+            Channel mode: {}\nMuted: {}\nMessages since last reply: {}",
+            self.agent.character().name,
+            counts(&stats.documents),
+            counts(&stats.messages),
+            stats.pending_embeddings,
+            policy.mode.as_str(),
+            muted_until,
+            state
+                .messages_since_reply
+                .map_or_else(|| "never replied".to_string(), |count| count.to_string()),
+        )
+    }
+}
+
+/// When a mute of `minutes`, or of `default` when `None`, ends. `None` when
+/// that's too far away to represent.
+fn mute_until(minutes: Option<u64>, default: std::time::Duration) -> Option<chrono::DateTime<chrono::Utc>> {
+    let duration = match minutes {
+        Some(minutes) => i64::try_from(minutes).ok().and_then(chrono::TimeDelta::try_minutes)?,
+        None => chrono::TimeDelta::from_std(default).unwrap_or(chrono::TimeDelta::hours(1)),
+    };
+    chrono::Utc::now().checked_add_signed(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_admin_commands() {
+        assert_eq!(AdminCommand::parse("hello"), None);
+        assert_eq!(AdminCommand::parse("/policy"), None);
+        assert_eq!(AdminCommand::parse("/mute"), Some(Ok(AdminCommand::Mute(None))));
+        assert_eq!(AdminCommand::parse("/mute 30"), Some(Ok(AdminCommand::Mute(Some(30)))));
+        assert_eq!(AdminCommand::parse("/stats@gihun_bot"), Some(Ok(AdminCommand::Stats)));
+        assert_eq!(
+            AdminCommand::parse("/mode never"),
+            Some(Ok(AdminCommand::Mode(EngagementMode::Never)))
+        );
+        assert_eq!(
+            AdminCommand::from_parts("forget", &["<@1234>"]),
+            Ok(AdminCommand::Forget("1234".to_string()))
+        );
+        assert!(matches!(AdminCommand::parse("/mute soon"), Some(Err(_))));
+        assert!(matches!(AdminCommand::parse("/forget"), Some(Err(_))));
+        assert!(matches!(AdminCommand::parse("/forget @seong_gihun"), Some(Err(_))));
+        assert!(matches!(AdminCommand::parse("/forget <@>"), Some(Err(_))));
+        assert_eq!(
+            AdminCommand::parse("/forget@gihun_bot 456"),
+            Some(Ok(AdminCommand::Forget("456".to_string())))
+        );
+        assert!(matches!(AdminCommand::parse("/tweet now"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_mute_until_rejects_overflow() {
+        let hour = std::time::Duration::from_secs(3600);
+        let now = chrono::Utc::now();
+        let until = mute_until(None, hour).unwrap();
+        assert!(until > now && until <= chrono::Utc::now() + chrono::TimeDelta::hours(1));
+        assert!(mute_until(Some(MAX_MUTE_MINUTES), hour).is_some());
+        assert_eq!(mute_until(Some(99_999_999_999_999), hour), None);
+        assert_eq!(mute_until(Some(u64::MAX), hour), None);
+    }
+}
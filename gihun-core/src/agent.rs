@@ -1,13 +1,24 @@
+use std::sync::{Arc, RwLock};
+
 use rig::{agent::AgentBuilder, completion::CompletionModel, embeddings::EmbeddingModel};
 use tracing::info;
 
-use crate::{character::Character, knowledge::KnowledgeBase};
+use crate::{
+    character::Character,
+    knowledge::{ContextConfig, ContextIndex, DocumentFilter, KnowledgeBase, Reranker},
+};
+
+/// Upper bound on documents injected as dynamic context per request
+const MAX_CONTEXT_DOCUMENTS: usize = 4;
 
 #[derive(Clone)]
 pub struct Agent<M: CompletionModel, E: EmbeddingModel + 'static> {
-    pub character: Character,
+    /// Shared by every clone, so reloading the character reaches all clients
+    character: Arc<RwLock<Character>>,
     completion_model: M,
     knowledge: KnowledgeBase<E>,
+    context_config: ContextConfig,
+    reranker: Option<Arc<dyn Reranker>>,
 }
 
 impl<M: CompletionModel, E: EmbeddingModel> Agent<M, E> {
@@ -15,13 +26,44 @@ impl<M: CompletionModel, E: EmbeddingModel> Agent<M, E> {
         info!(name = character.name, "Creating new agent");
 
         Self {
-            character,
+            character: Arc::new(RwLock::new(character)),
             completion_model,
             knowledge,
+            context_config: ContextConfig::default(),
+            reranker: None,
         }
     }
 
+    pub fn with_context_config(mut self, context_config: ContextConfig) -> Self {
+        self.context_config = context_config;
+        self
+    }
+
+    /// Reorders retrieved documents before they are added as dynamic context.
+    pub fn with_reranker(mut self, reranker: impl Reranker + 'static) -> Self {
+        self.reranker = Some(Arc::new(reranker));
+        self
+    }
+
+    pub fn character(&self) -> Character {
+        self.character.read().unwrap().clone()
+    }
+
+    /// Replaces the character of this agent and all its clones.
+    pub fn set_character(&self, character: Character) {
+        info!(name = character.name, "Replacing character");
+        *self.character.write().unwrap() = character;
+    }
+
     pub fn builder(&self) -> AgentBuilder<M> {
+        self.builder_with_filter(DocumentFilter::default())
+    }
+
+    /// Like [`Self::builder`], but only documents matching `filter` are
+    /// retrieved as dynamic context.
+    pub fn builder_with_filter(&self, filter: DocumentFilter) -> AgentBuilder<M> {
+        let character = self.character();
+
         // Build character context
         let character_context = format!(
             "Your name is: {}
@@ -31,9 +73,9 @@ impl<M: CompletionModel, E: EmbeddingModel> Agent<M, E> {
             
             Example messages for reference:
             {}",
-            self.character.name,
-            self.character.topics.join(", "),
-            self.character.message_examples.join("\n")
+            character.name,
+            character.topics.join(", "),
+            character.message_examples.join("\n")
         );
 
         // Build style context
@@ -54,22 +96,27 @@ impl<M: CompletionModel, E: EmbeddingModel> Agent<M, E> {
             Personal elements:
             - Key interests: {}
             - Meme-related phrases: {}",
-            self.character.style.all.join("\n"),
-            self.character.style.chat.join("\n"),
-            self.character.style.post.join("\n"),
-            self.character.style.adjectives.join(", "),
-            self.character.style.expressions.join("\n"),
-            self.character.style.interests.join("\n"),
-            self.character.style.meme_phrases.join("\n")
+            character.style.all.join("\n"),
+            character.style.chat.join("\n"),
+            character.style.post.join("\n"),
+            character.style.adjectives.join(", "),
+            character.style.expressions.join("\n"),
+            character.style.interests.join("\n"),
+            character.style.meme_phrases.join("\n")
         );
 
-        let builder = AgentBuilder::new(self.completion_model.clone())
-            .preamble(&self.character.preamble)
+        AgentBuilder::new(self.completion_model.clone())
+            .preamble(&character.preamble)
             .context(&character_context)
             .context(&style_context)
-            .dynamic_context(4, self.knowledge.clone().document_index());
-
-        builder
+            .dynamic_context(
+                MAX_CONTEXT_DOCUMENTS,
+                ContextIndex::new(
+                    self.knowledge.clone().filtered_document_index(filter),
+                    self.reranker.clone(),
+                    self.context_config.clone(),
+                ),
+            )
     }
 
     pub fn knowledge(&self) -> &KnowledgeBase<E> {
@@ -1,12 +1,18 @@
+use futures::future::BoxFuture;
 use rig::completion::{CompletionModel, ModelChoice};
-use tracing::debug;
+use rig::embeddings::EmbeddingModel;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
 
-use crate::knowledge::{ChannelType, Source};
+use crate::character::Character;
+use crate::knowledge::{
+    AttentionLog, ChannelAttention, ChannelType, EngagementMode, EngagementPolicy, KnowledgeBase,
+    Source,
+};
 use std::collections::HashSet;
-
-const RESPOND_COMMAND: &str = "[RESPOND]";
-const IGNORE_COMMAND: &str = "[IGNORE]";
-const STOP_COMMAND: &str = "[STOP]";
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 #[derive(Debug, PartialEq)]
 pub enum AttentionCommand {
@@ -15,113 +21,643 @@ pub enum AttentionCommand {
     Stop,
 }
 
+impl AttentionCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttentionCommand::Respond => "respond",
+            AttentionCommand::Ignore => "ignore",
+            AttentionCommand::Stop => "stop",
+        }
+    }
+}
+
+/// The outcome of [`Attention::should_reply`] and why it was reached.
+#[derive(Debug, PartialEq)]
+pub struct AttentionDecision {
+    pub command: AttentionCommand,
+    /// Relevance between 0 and 1 given by the classifier, `None` when a rule
+    /// decided without asking it
+    pub score: Option<f32>,
+    pub reason: String,
+}
+
+impl AttentionDecision {
+    fn rule(command: AttentionCommand, reason: impl Into<String>) -> Self {
+        Self {
+            command,
+            score: None,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Something the bot can do with a tweet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngagementAction {
+    Like,
+    Retweet,
+    Quote,
+    Reply,
+}
+
+impl EngagementAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngagementAction::Like => "like",
+            EngagementAction::Retweet => "retweet",
+            EngagementAction::Quote => "quote",
+            EngagementAction::Reply => "reply",
+        }
+    }
+}
+
+/// The outcome of [`Attention::decide_engagement`]. No actions means the
+/// tweet is ignored.
+#[derive(Debug, Default, PartialEq)]
+pub struct EngagementDecision {
+    pub actions: Vec<EngagementAction>,
+    /// Confidence between 0 and 1 given by the classifier
+    pub confidence: f32,
+    pub reason: String,
+}
+
+impl EngagementDecision {
+    pub fn contains(&self, action: EngagementAction) -> bool {
+        self.actions.contains(&action)
+    }
+
+    pub fn is_ignore(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The actions joined with commas, or `ignore`.
+    pub fn command(&self) -> String {
+        if self.is_ignore() {
+            return "ignore".to_string();
+        }
+        self.actions
+            .iter()
+            .map(EngagementAction::as_str)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Engagement reply the classifier is asked to give as JSON.
+#[derive(Debug, Deserialize)]
+struct EngagementClassification {
+    /// Action names, unknown ones such as "ignore" are skipped
+    #[serde(default)]
+    actions: Vec<String>,
+    confidence: f32,
+    #[serde(default)]
+    reason: String,
+}
+
+/// Reply the classifier is asked to give as JSON.
+#[derive(Debug, Deserialize)]
+struct Classification {
+    relevance: f32,
+    #[serde(default)]
+    stop: bool,
+    #[serde(default)]
+    reason: String,
+}
+
 #[derive(Debug)]
 pub struct AttentionContext {
+    /// Platform id of the message, used when logging the decision
+    pub message_id: String,
+    /// Platform id of the message's author, checked against the channel's
+    /// [`EngagementPolicy`]
+    pub author_id: String,
     pub message_content: String,
     pub mentioned_names: HashSet<String>,
     pub history: Vec<(String, String)>,
     pub channel_type: ChannelType,
+    pub channel_id: String,
     pub source: Source,
 }
 
-#[derive(Clone, Debug)]
+/// How the bot decides whether to speak, read from the `[attention]` table of
+/// the character TOML. Missing keys keep their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AttentionConfig {
+    /// Names and aliases the bot answers to, matched as whole words
     pub bot_names: Vec<String>,
+    /// Phrases that tell the bot to stop talking, matched as whole words
+    pub stop_phrases: Vec<String>,
+    /// Regular expressions for messages the bot never reacts to, such as
+    /// commands for other bots
+    #[serde(with = "patterns")]
+    pub ignore_patterns: Vec<Regex>,
     pub reply_threshold: f32,
     pub max_history_messages: i64,
+    /// Messages others must send after the bot's last reply before it joins
+    /// a conversation uninvited again
     pub cooldown_messages: i64,
+    /// How long a channel stays muted after the bot is told to stop
+    #[serde(rename = "mute_minutes", with = "minutes")]
+    pub mute_duration: Duration,
+    /// What the bot cares about when engaging with tweets; the character's
+    /// topics and interests are added when loaded
+    pub interests: Vec<String>,
+    /// Confidence from 0 to 1 below which engagement actions are dropped
+    pub engagement_threshold: f32,
+    /// Kinds of conversation the bot likes to join, e.g. "questions about
+    /// the games"
+    pub conversation_types: Vec<String>,
+    /// Cosine similarity to the closest topic below which the
+    /// [`TopicFilter`] ignores a message without asking the classifier
+    pub min_topic_similarity: f64,
 }
 
 impl Default for AttentionConfig {
     fn default() -> Self {
         Self {
-            bot_names: vec!["shinobai".to_string(), "shinobi".to_string()],
+            bot_names: Vec::new(),
+            stop_phrases: [
+                "shut up",
+                "stfu",
+                "be quiet",
+                "stop talking",
+                "stop responding",
+                "can you stop",
+                "please stop",
+                "dont talk",
+                "hush",
+                "stupid bot",
+                "dumb bot",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            ignore_patterns: Vec::new(),
             reply_threshold: 0.6,
             max_history_messages: 10,
             cooldown_messages: 3,
+            mute_duration: Duration::from_secs(60 * 60),
+            interests: Vec::new(),
+            engagement_threshold: 0.5,
+            conversation_types: Vec::new(),
+            min_topic_similarity: 0.2,
         }
     }
 }
 
+impl AttentionConfig {
+    /// What the [`TopicFilter`] compares messages with: the interests and the
+    /// preferred kinds of conversation.
+    pub fn topics(&self) -> Vec<String> {
+        self.interests
+            .iter()
+            .chain(&self.conversation_types)
+            .cloned()
+            .collect()
+    }
+
+    /// Who the classifier decides for, opening its prompts.
+    fn persona(&self) -> String {
+        let mut persona = match self.bot_names.first() {
+            Some(name) => format!("You are {}.", name),
+            None => "You are a chat bot.".to_string(),
+        };
+        if !self.interests.is_empty() {
+            persona.push_str(&format!(" Your topics and interests: {}.", self.interests.join(", ")));
+        }
+        if !self.conversation_types.is_empty() {
+            persona.push_str(&format!(
+                " Conversations you like to join: {}.",
+                self.conversation_types.join(", ")
+            ));
+        }
+        persona
+    }
+
+    /// Counts a new message in the channel before deciding on it.
+    fn observe(&self, state: &mut ChannelAttention) {
+        if let Some(count) = &mut state.messages_since_reply {
+            *count += 1;
+        }
+    }
+
+    /// Updates the channel's state with the decision taken on a message.
+    fn record(
+        &self,
+        state: &mut ChannelAttention,
+        command: &AttentionCommand,
+        now: chrono::DateTime<chrono::Utc>,
+    ) {
+        match command {
+            AttentionCommand::Respond => {
+                state.muted_until = None;
+                state.messages_since_reply = Some(0);
+                state.last_replied_at = Some(now);
+            }
+            AttentionCommand::Stop => {
+                let mute = chrono::Duration::from_std(self.mute_duration)
+                    .unwrap_or(chrono::Duration::hours(1));
+                state.muted_until = Some(now + mute);
+            }
+            AttentionCommand::Ignore => {}
+        }
+    }
+
+    /// The name the bot is mentioned or called by in the message, if any.
+    fn addressed_as(&self, context: &AttentionContext, words: &[String]) -> Option<&str> {
+        self.bot_names
+            .iter()
+            .find(|name| {
+                let mentioned = context
+                    .mentioned_names
+                    .iter()
+                    .any(|mention| fold(mention) == fold(name));
+                let name_in_content = contains_phrase(words, name);
+
+                debug!(
+                    name = name,
+                    mentioned = mentioned,
+                    name_in_content = name_in_content,
+                    "Checking if bot name was mentioned"
+                );
+
+                mentioned || name_in_content
+            })
+            .map(String::as_str)
+    }
+
+    /// Decides by the channel's `policy` where it leaves no choice to the
+    /// rules or the classifier. Being told to stop and a mute still win over
+    /// `always`.
+    fn decide_by_policy(
+        &self,
+        policy: &EngagementPolicy,
+        context: &AttentionContext,
+        state: &ChannelAttention,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<AttentionDecision> {
+        if let Some(reason) = policy.excludes(&context.author_id) {
+            return Some(AttentionDecision::rule(AttentionCommand::Ignore, reason));
+        }
+
+        match policy.mode {
+            // `never` is already excluded above
+            EngagementMode::Default | EngagementMode::Never => None,
+            EngagementMode::Always => {
+                let words = words(&context.message_content);
+                let stopped = self
+                    .stop_phrases
+                    .iter()
+                    .any(|phrase| contains_phrase(&words, phrase));
+                let muted = state.muted_until.is_some_and(|until| until > now);
+                (!stopped && !muted).then(|| {
+                    AttentionDecision::rule(AttentionCommand::Respond, "channel policy is always")
+                })
+            }
+            EngagementMode::MentionOnly => {
+                let words = words(&context.message_content);
+                self.addressed_as(context, &words).is_none().then(|| {
+                    AttentionDecision::rule(
+                        AttentionCommand::Ignore,
+                        "channel policy is mention-only",
+                    )
+                })
+            }
+        }
+    }
+
+    /// Decides without the classifier where a rule applies.
+    fn decide_by_rules(
+        &self,
+        context: &AttentionContext,
+        state: &ChannelAttention,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<AttentionDecision> {
+        if let Some(pattern) = self
+            .ignore_patterns
+            .iter()
+            .find(|pattern| pattern.is_match(&context.message_content))
+        {
+            return Some(AttentionDecision::rule(
+                AttentionCommand::Ignore,
+                format!("matches ignore pattern {}", pattern),
+            ));
+        }
+
+        let words = words(&context.message_content);
+
+        // Check for stop/disengage phrases
+        if let Some(phrase) = self
+            .stop_phrases
+            .iter()
+            .find(|phrase| contains_phrase(&words, phrase))
+        {
+            return Some(AttentionDecision::rule(
+                AttentionCommand::Stop,
+                format!("stop phrase \"{}\"", phrase),
+            ));
+        }
+
+        // Check for mentions or name references, which also lift a mute
+        if let Some(name) = self.addressed_as(context, &words) {
+            debug!("Bot name {} was mentioned, will reply", name);
+            return Some(AttentionDecision::rule(
+                AttentionCommand::Respond,
+                format!("mentioned as {}", name),
+            ));
+        }
+
+        if let Some(until) = state.muted_until.filter(|until| *until > now) {
+            return Some(AttentionDecision::rule(
+                AttentionCommand::Ignore,
+                format!("told to stop, muted until {}", until.to_rfc3339()),
+            ));
+        }
+
+        // Always reply to DMs
+        if context.channel_type == ChannelType::DirectMessage {
+            return Some(AttentionDecision::rule(AttentionCommand::Respond, "direct message"));
+        }
+
+        // Ignore very short messages
+        if context.message_content.trim().chars().count() < 4 {
+            return Some(AttentionDecision::rule(AttentionCommand::Ignore, "message too short"));
+        }
+
+        // Give others room to talk before joining in uninvited again
+        if let Some(count) = state
+            .messages_since_reply
+            .filter(|count| *count < self.cooldown_messages)
+        {
+            return Some(AttentionDecision::rule(
+                AttentionCommand::Ignore,
+                format!("cooling down, {} of {} messages since last reply", count, self.cooldown_messages),
+            ));
+        }
+
+        None
+    }
+}
+
+/// Scores how close a text is to what the bot cares about, from -1 to 1.
+/// Used to skip the classifier for messages that are clearly off-topic.
+pub trait TopicFilter: Send + Sync {
+    fn similarity<'a>(&'a self, text: &'a str) -> BoxFuture<'a, anyhow::Result<f64>>;
+}
+
+/// Compares a text with each topic by the cosine similarity of their
+/// embeddings, the topics being embedded once up front.
+#[derive(Clone)]
+pub struct EmbeddingTopicFilter<E: EmbeddingModel> {
+    embedding_model: E,
+    topics: Vec<Vec<f64>>,
+}
+
+impl<E: EmbeddingModel> EmbeddingTopicFilter<E> {
+    pub async fn new(embedding_model: E, topics: Vec<String>) -> anyhow::Result<Self> {
+        let mut embeddings = Vec::with_capacity(topics.len());
+        for batch in topics.chunks(E::MAX_DOCUMENTS.max(1)) {
+            embeddings.extend(
+                embedding_model
+                    .embed_texts(batch.to_vec())
+                    .await?
+                    .into_iter()
+                    .map(|embedding| embedding.vec),
+            );
+        }
+
+        Ok(Self {
+            embedding_model,
+            topics: embeddings,
+        })
+    }
+}
+
+impl<E: EmbeddingModel> TopicFilter for EmbeddingTopicFilter<E> {
+    fn similarity<'a>(&'a self, text: &'a str) -> BoxFuture<'a, anyhow::Result<f64>> {
+        Box::pin(async move {
+            let embedding = self
+                .embedding_model
+                .embed_texts(vec![text.to_string()])
+                .await?
+                .pop()
+                .ok_or_else(|| anyhow::anyhow!("No embedding returned"))?;
+
+            Ok(self
+                .topics
+                .iter()
+                .map(|topic| cosine(&embedding.vec, topic))
+                .fold(-1.0, f64::max))
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct Attention<M: CompletionModel> {
-    config: AttentionConfig,
+    /// Shared by every clone, so a reloaded character reaches all clients
+    config: Arc<RwLock<AttentionConfig>>,
     completion_model: M,
+    /// Logged with every decision
+    model_name: String,
+    topic_filter: Option<Arc<dyn TopicFilter>>,
 }
 
 impl<M: CompletionModel> Attention<M> {
     pub fn new(config: AttentionConfig, completion_model: M) -> Self {
         Self {
-            config,
+            config: Arc::new(RwLock::new(config)),
             completion_model,
+            model_name: String::new(),
+            topic_filter: None,
         }
     }
 
-    pub async fn should_reply(&self, context: &AttentionContext) -> AttentionCommand {
-        let content = context.message_content.to_lowercase();
+    /// Names the classifier model in logged decisions.
+    pub fn with_model_name(mut self, model_name: impl Into<String>) -> Self {
+        self.model_name = model_name.into();
+        self
+    }
 
-        // Always reply to DMs
-        if context.channel_type == ChannelType::DirectMessage {
-            return AttentionCommand::Respond;
-        }
+    /// Decides as `character`, answering to its name and aliases and judging
+    /// relevance against its topics, interests and conversation types.
+    pub fn from_character(character: &Character, completion_model: M) -> Self {
+        Self::new(character.attention_config(), completion_model)
+    }
 
-        // Check for mentions or name references
-        for name in &self.config.bot_names {
-            let mentioned = context.mentioned_names.contains(name);
-            let name_in_content = content.contains(&name.to_lowercase());
+    /// Ignores messages less similar to every topic than
+    /// [`AttentionConfig::min_topic_similarity`] before asking the classifier.
+    pub fn with_topic_filter(mut self, topic_filter: impl TopicFilter + 'static) -> Self {
+        self.topic_filter = Some(Arc::new(topic_filter));
+        self
+    }
 
-            debug!(
-                name = name,
-                mentioned = mentioned,
-                name_in_content = name_in_content,
-                "Checking if bot name was mentioned"
-            );
+    pub fn config(&self) -> AttentionConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces the settings of this attention and all its clones. The topic
+    /// filter keeps comparing with the topics it was built with.
+    pub fn set_config(&self, config: AttentionConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Decides whether to reply to a message and updates the channel's
+    /// `state` with the outcome.
+    ///
+    /// Stop phrases, mentions, muted channels, direct messages, very short
+    /// messages and the cooldown after the bot's last reply are decided by
+    /// rules, and off-topic messages are dropped by the topic filter; anything
+    /// else is scored by the classifier and answered when the score reaches
+    /// [`AttentionConfig::reply_threshold`]. Being told to stop
+    /// mutes the channel for [`AttentionConfig::mute_duration`] unless the bot
+    /// is addressed by name again.
+    pub async fn should_reply(
+        &self,
+        context: &AttentionContext,
+        state: &mut ChannelAttention,
+    ) -> AttentionDecision {
+        let config = self.config();
+        let now = chrono::Utc::now();
+        config.observe(state);
+
+        let decision = match config.decide_by_rules(context, state, now) {
+            Some(decision) => decision,
+            None => match self.off_topic(&context.message_content).await {
+                Some(reason) => AttentionDecision::rule(AttentionCommand::Ignore, reason),
+                None => self.classify(context).await,
+            },
+        };
+
+        config.record(state, &decision.command, now);
+        decision
+    }
+
+    /// [`Self::should_reply`] with the state of the message's channel loaded
+    /// from and saved to `knowledge`, where the decision is also logged.
+    ///
+    /// The channel's [`EngagementPolicy`] is checked first: blocked authors,
+    /// authors missing from an allowlist and channels set to `never` are
+    /// ignored, channels set to `always` are answered unless told to stop or
+    /// muted, and `mention-only` channels are ignored unless the bot is
+    /// addressed.
+    pub async fn should_reply_in_channel<E: EmbeddingModel>(
+        &self,
+        knowledge: &KnowledgeBase<E>,
+        context: &AttentionContext,
+    ) -> AttentionDecision {
+        let mut state = knowledge
+            .channel_attention(&context.source, &context.channel_id)
+            .await
+            .unwrap_or_else(|err| {
+                error!(?err, "Failed to load channel attention state");
+                ChannelAttention::default()
+            });
+        let policy = knowledge
+            .engagement_policy(&context.source, &context.channel_id)
+            .await
+            .unwrap_or_else(|err| {
+                error!(?err, "Failed to load engagement policy");
+                EngagementPolicy::default()
+            });
 
-            if mentioned || name_in_content {
-                debug!("Bot name {} was mentioned, will reply", name);
-                return AttentionCommand::Respond;
+        let config = self.config();
+        let now = chrono::Utc::now();
+        let decision = match config.decide_by_policy(&policy, context, &state, now) {
+            Some(decision) => {
+                config.observe(&mut state);
+                config.record(&mut state, &decision.command, now);
+                decision
             }
+            None => self.should_reply(context, &mut state).await,
+        };
+
+        if let Err(err) = knowledge
+            .set_channel_attention(&context.source, &context.channel_id, &state)
+            .await
+        {
+            error!(?err, "Failed to save channel attention state");
         }
 
-        // Check for stop/disengage phrases
-        let stop_phrases = [
-            "shut up",
-            "stop",
-            "please shut up",
-            "shut up please",
-            "dont talk",
-            "silence",
-            "stop talking",
-            "be quiet",
-            "hush",
-            "wtf",
-            "stfu",
-            "stupid bot",
-            "dumb bot",
-            "stop responding",
-            "can you not",
-            "can you stop",
-            "be quiet",
-        ];
-
-        if stop_phrases.iter().any(|phrase| content.contains(phrase)) {
-            return AttentionCommand::Stop;
+        self.log(
+            knowledge,
+            AttentionLog {
+                source: context.source.clone(),
+                channel_id: context.channel_id.clone(),
+                message_id: context.message_id.clone(),
+                kind: "reply".to_string(),
+                command: decision.command.as_str().to_string(),
+                score: decision.score,
+                reason: decision.reason.clone(),
+                model: self.model_name.clone(),
+                created_at: chrono::Utc::now(),
+            },
+        )
+        .await;
+
+        decision
+    }
+
+    /// Logs an engagement decision on the tweet `message_id` to `knowledge`.
+    pub async fn log_engagement<E: EmbeddingModel>(
+        &self,
+        knowledge: &KnowledgeBase<E>,
+        channel_id: &str,
+        message_id: &str,
+        decision: &EngagementDecision,
+    ) {
+        self.log(
+            knowledge,
+            AttentionLog {
+                source: Source::Twitter,
+                channel_id: channel_id.to_string(),
+                message_id: message_id.to_string(),
+                kind: "engagement".to_string(),
+                command: decision.command(),
+                score: Some(decision.confidence),
+                reason: decision.reason.clone(),
+                model: self.model_name.clone(),
+                created_at: chrono::Utc::now(),
+            },
+        )
+        .await;
+    }
+
+    async fn log<E: EmbeddingModel>(&self, knowledge: &KnowledgeBase<E>, log: AttentionLog) {
+        if let Err(err) = knowledge.log_attention_decision(&log).await {
+            error!(?err, "Failed to log attention decision");
         }
+    }
 
-        // Ignore very short messages
-        if content.len() < 4 {
-            return AttentionCommand::Ignore;
+    /// Why `text` is too far from every topic to bother the classifier, or
+    /// `None` when it may be relevant or there is no topic filter.
+    async fn off_topic(&self, text: &str) -> Option<String> {
+        let topic_filter = self.topic_filter.as_ref()?;
+        match topic_filter.similarity(text).await {
+            Ok(similarity) if similarity < self.config().min_topic_similarity => {
+                Some(format!("off-topic, similarity {:.2}", similarity))
+            }
+            Ok(_) => None,
+            // Let the classifier decide when the filter can't
+            Err(err) => {
+                warn!(?err, "Topic filter failed");
+                None
+            }
         }
+    }
 
-        // Use LLM to decide if we should respond
+    /// Asks the classifier how relevant the message is.
+    async fn classify(&self, context: &AttentionContext) -> AttentionDecision {
+        let config = self.config();
         let prompt = format!(
-            "You are in a room with other users. You should only respond when addressed or when the conversation is relevant to you.\n\n\
-            Response options:\n\
-            {RESPOND_COMMAND} - Message is directed at you or conversation is relevant\n\
-            {IGNORE_COMMAND} - Message is not interesting or not directed at you\n\
-            {STOP_COMMAND} - User wants you to stop or conversation has concluded\n\n\
+            "{} You are in a room with other users. You should only respond when addressed or when the conversation is relevant to you.\n\n\
             Recent messages:\n{}\n\nLatest message: {}\n\n\
-            Choose one response option:",
+            Rate how relevant the latest message is for you to reply to, from 0 (not directed at you, \
+            not interesting) to 1 (directed at you or clearly relevant). Set \"stop\" to true if the user \
+            wants you to stop or the conversation has concluded.\n\n\
+            Respond with only a JSON object like \
+            {{\"relevance\": 0.2, \"stop\": false, \"reason\": \"one short sentence\"}}:",
+            config.persona(),
             context.history.iter()
                 .map(|(_, msg)| format!("- {}", msg))
                 .collect::<Vec<_>>()
@@ -131,79 +667,517 @@ impl<M: CompletionModel> Attention<M> {
 
         let builder = self.completion_model.completion_request(&prompt);
 
-        match self.completion_model.completion(builder.build()).await {
+        let decision = match self.completion_model.completion(builder.build()).await {
             Ok(response) => match response.choice {
-                ModelChoice::Message(text) => {
-                    if text.contains(RESPOND_COMMAND) {
-                        AttentionCommand::Respond
-                    } else if text.contains(STOP_COMMAND) {
-                        AttentionCommand::Stop
-                    } else {
-                        AttentionCommand::Ignore
-                    }
-                }
-                ModelChoice::ToolCall(_, _) => AttentionCommand::Ignore,
+                ModelChoice::Message(text) => decide(&text, config.reply_threshold),
+                ModelChoice::ToolCall(name, _) => Err(format!("classifier called tool {}", name)),
             },
-            Err(_) => AttentionCommand::Ignore,
-        }
+            Err(err) => Err(format!("classifier failed: {}", err)),
+        };
+
+        // Staying quiet is the safe choice when the classifier can't be read
+        decision.unwrap_or_else(|reason| {
+            warn!(reason, "Could not classify message, ignoring it");
+            AttentionDecision::rule(AttentionCommand::Ignore, reason)
+        })
     }
 
-    pub async fn should_like(&self, tweet_content: &str) -> bool {
+    /// Decides in a single classifier call how to engage with a tweet by
+    /// `author`, given `context` such as the tweet it quotes. Actions are
+    /// dropped when the classifier's confidence is below
+    /// [`AttentionConfig::engagement_threshold`], and nothing is done when
+    /// its reply can't be read or the topic filter finds the tweet off-topic.
+    pub async fn decide_engagement(
+        &self,
+        tweet: &str,
+        author: &str,
+        context: &[String],
+    ) -> EngagementDecision {
+        if let Some(reason) = self.off_topic(tweet).await {
+            return EngagementDecision {
+                reason,
+                ..Default::default()
+            };
+        }
+
+        let config = self.config();
         let prompt = format!(
-            "You are deciding whether to like a tweet. Consider if the content is positive, interesting, or relevant.\n\n\
-            Tweet: {}\n\n\
-            Respond with only 'true' or 'false':",
-            tweet_content
+            "{} You are deciding how to engage with a tweet on your timeline.\n\n\
+            {}Tweet by @{}: {}\n\n\
+            Choose any of these actions, or none to ignore the tweet:\n\
+            - \"like\": the tweet is positive, interesting or relevant to your interests\n\
+            - \"retweet\": the tweet is highly valuable or aligns with your values\n\
+            - \"quote\": the tweet deserves commentary to your own followers\n\
+            - \"reply\": you have something to say to the author directly\n\n\
+            Quote or reply, not both. Give your confidence in the choice from 0 to 1.\n\n\
+            Respond with only a JSON object like \
+            {{\"actions\": [\"like\"], \"confidence\": 0.8, \"reason\": \"one short sentence\"}}:",
+            config.persona(),
+            if context.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "Context:\n{}\n\n",
+                    context
+                        .iter()
+                        .map(|line| format!("- {}", line))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )
+            },
+            author,
+            tweet
         );
 
         let builder = self.completion_model.completion_request(&prompt);
 
-        match self.completion_model.completion(builder.build()).await {
+        let decision = match self.completion_model.completion(builder.build()).await {
             Ok(response) => match response.choice {
-                ModelChoice::Message(text) => text.trim().to_lowercase() == "true",
-                ModelChoice::ToolCall(_, _) => false,
+                ModelChoice::Message(text) => {
+                    decide_engagement(&text, config.engagement_threshold)
+                }
+                ModelChoice::ToolCall(name, _) => Err(format!("classifier called tool {}", name)),
             },
-            Err(_) => false,
+            Err(err) => Err(format!("classifier failed: {}", err)),
+        };
+
+        decision.unwrap_or_else(|reason| {
+            warn!(reason, "Could not decide on engagement, ignoring tweet");
+            EngagementDecision {
+                reason,
+                ..Default::default()
+            }
+        })
+    }
+}
+
+/// Lowercases with Unicode case mapping and normalizes typographic
+/// apostrophes, so "DON’T" and "don't" compare equal.
+fn fold(text: &str) -> String {
+    text.to_lowercase().replace(['\u{2019}', '\u{2018}'], "'")
+}
+
+/// The words of `text`, folded, with apostrophes dropped so "don't" and
+/// "dont" match. Anything but letters and digits separates words.
+fn words(text: &str) -> Vec<String> {
+    fold(text)
+        .replace('\'', "")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Whether the words of `phrase` appear consecutively in `words`, so "stop"
+/// matches "please stop" but not "nonstop" or "stopwatch".
+fn contains_phrase(words: &[String], phrase: &str) -> bool {
+    let phrase = self::words(phrase);
+    !phrase.is_empty() && words.windows(phrase.len()).any(|window| window == phrase.as_slice())
+}
+
+/// Cosine similarity, 0 when either vector is all zeros.
+fn cosine(a: &[f64], b: &[f64]) -> f64 {
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>() / norms
+}
+
+/// (De)serializes regular expressions as their source strings.
+mod patterns {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(patterns: &[Regex], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(patterns.iter().map(Regex::as_str))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Regex>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+/// (De)serializes a duration as whole minutes.
+mod minutes {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs() / 60)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_secs(u64::deserialize(deserializer)? * 60))
+    }
+}
+
+/// Turns the classifier's JSON reply, which may be wrapped in prose or a code
+/// fence, into a decision against `threshold`.
+fn decide(text: &str, threshold: f32) -> Result<AttentionDecision, String> {
+    let classification = text
+        .find('{')
+        .zip(text.rfind('}'))
+        .filter(|(start, end)| start < end)
+        .and_then(|(start, end)| serde_json::from_str::<Classification>(&text[start..=end]).ok())
+        .ok_or_else(|| format!("unreadable classifier reply: {}", text.trim()))?;
+
+    let score = classification.relevance.clamp(0.0, 1.0);
+    let command = if classification.stop {
+        AttentionCommand::Stop
+    } else if score >= threshold {
+        AttentionCommand::Respond
+    } else {
+        AttentionCommand::Ignore
+    };
+
+    Ok(AttentionDecision {
+        command,
+        score: Some(score),
+        reason: classification.reason,
+    })
+}
+
+/// Turns the classifier's JSON engagement reply into a decision, keeping the
+/// actions only when its confidence reaches `threshold`. A tweet is either
+/// quoted or replied to, the quote winning.
+fn decide_engagement(text: &str, threshold: f32) -> Result<EngagementDecision, String> {
+    let classification = text
+        .find('{')
+        .zip(text.rfind('}'))
+        .filter(|(start, end)| start < end)
+        .and_then(|(start, end)| {
+            serde_json::from_str::<EngagementClassification>(&text[start..=end]).ok()
+        })
+        .ok_or_else(|| format!("unreadable classifier reply: {}", text.trim()))?;
+
+    let confidence = classification.confidence.clamp(0.0, 1.0);
+    let mut actions: Vec<EngagementAction> = Vec::new();
+    if confidence >= threshold {
+        for name in &classification.actions {
+            let action = match name.to_lowercase().as_str() {
+                "like" => Some(EngagementAction::Like),
+                "retweet" => Some(EngagementAction::Retweet),
+                "quote" => Some(EngagementAction::Quote),
+                "reply" => Some(EngagementAction::Reply),
+                _ => None,
+            };
+            if let Some(action) = action.filter(|action| !actions.contains(action)) {
+                actions.push(action);
+            }
         }
     }
+    if actions.contains(&EngagementAction::Quote) {
+        actions.retain(|action| *action != EngagementAction::Reply);
+    }
 
-    pub async fn should_retweet(&self, tweet_content: &str) -> bool {
-        let prompt = format!(
-            "You are deciding whether to retweet. Only retweet if the content is highly valuable, interesting, or aligns with your values.\n\n\
-            Tweet: {}\n\n\
-            Respond with only 'true' or 'false':",
-            tweet_content
+    Ok(EngagementDecision {
+        actions,
+        confidence,
+        reason: classification.reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::StaticCompletionModel;
+    use crate::knowledge::testing::test_knowledge_base;
+
+    #[test]
+    fn test_decide_compares_score_with_threshold() {
+        let reply = "```json\n{\"relevance\": 0.7, \"stop\": false, \"reason\": \"asks about the games\"}\n```";
+        let decision = decide(reply, 0.6).unwrap();
+        assert_eq!(decision.command, AttentionCommand::Respond);
+        assert_eq!(decision.score, Some(0.7));
+        assert_eq!(decision.reason, "asks about the games");
+
+        assert_eq!(decide(reply, 0.8).unwrap().command, AttentionCommand::Ignore);
+        assert_eq!(
+            decide(r#"Sure! {"relevance": 0.9, "stop": true}"#, 0.6).unwrap().command,
+            AttentionCommand::Stop
         );
+        assert_eq!(decide(r#"{"relevance": 4}"#, 0.6).unwrap().score, Some(1.0));
+    }
 
-        let builder = self.completion_model.completion_request(&prompt);
+    #[test]
+    fn test_stop_mutes_until_mentioned_and_cooldown() {
+        let config = AttentionConfig {
+            bot_names: vec!["Gi-hun".to_string()],
+            cooldown_messages: 2,
+            ..Default::default()
+        };
+        let now = chrono::Utc::now();
+        let mut state = ChannelAttention::default();
+        let mut decide = |content: &str, now| {
+            let context = AttentionContext {
+                message_id: "message-1".to_string(),
+                author_id: "user-1".to_string(),
+                message_content: content.to_string(),
+                mentioned_names: HashSet::new(),
+                history: vec![],
+                channel_type: ChannelType::Text,
+                channel_id: "channel-1".to_string(),
+                source: Source::Discord,
+            };
+            config.observe(&mut state);
+            let decision = config.decide_by_rules(&context, &state, now);
+            if let Some(decision) = &decision {
+                config.record(&mut state, &decision.command, now);
+            }
+            decision.map(|decision| decision.command)
+        };
 
-        match self.completion_model.completion(builder.build()).await {
-            Ok(response) => match response.choice {
-                ModelChoice::Message(text) => text.trim().to_lowercase() == "true",
-                ModelChoice::ToolCall(_, _) => false,
-            },
-            Err(_) => false,
+        assert_eq!(decide("Please stop talking", now), Some(AttentionCommand::Stop));
+        assert_eq!(decide("What's for dinner tonight?", now), Some(AttentionCommand::Ignore));
+        // The mute wears off
+        assert_eq!(decide("What's for dinner tonight?", now + chrono::Duration::hours(2)), None);
+
+        assert_eq!(decide("Gi-hun, are you there?", now), Some(AttentionCommand::Respond));
+        assert_eq!(decide("Anyone else playing tonight?", now), Some(AttentionCommand::Ignore));
+        assert_eq!(decide("Anyone else playing tonight?", now), None);
+    }
+
+    fn rule(config: &AttentionConfig, content: &str) -> Option<AttentionCommand> {
+        let context = AttentionContext {
+            message_id: "message-1".to_string(),
+            author_id: "user-1".to_string(),
+            message_content: content.to_string(),
+            mentioned_names: HashSet::new(),
+            history: vec![],
+            channel_type: ChannelType::Text,
+            channel_id: "channel-1".to_string(),
+            source: Source::Discord,
+        };
+        config
+            .decide_by_rules(&context, &ChannelAttention::default(), chrono::Utc::now())
+            .map(|decision| decision.command)
+    }
+
+    #[test]
+    fn test_stop_phrases_match_whole_words() {
+        let config = AttentionConfig {
+            stop_phrases: vec!["stop".to_string(), "wtf".to_string(), "don't talk".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(rule(&config, "Stop."), Some(AttentionCommand::Stop));
+        assert_eq!(rule(&config, "ok STOP!!"), Some(AttentionCommand::Stop));
+        assert_eq!(rule(&config, "DON\u{2019}T TALK to me"), Some(AttentionCommand::Stop));
+        assert_eq!(rule(&config, "please dont talk"), Some(AttentionCommand::Stop));
+
+        for content in [
+            "Meet me at the bus-stopover tonight",
+            "We played nonstop for hours",
+            "Where did I leave my stopwatch?",
+            "That game was wtfffff amazing",
+            "The donkey can't talk, can it?",
+        ] {
+            assert_ne!(rule(&config, content), Some(AttentionCommand::Stop), "{}", content);
         }
+
+        // The default list leaves ordinary chatter alone
+        let defaults = AttentionConfig::default();
+        assert_eq!(rule(&defaults, "wtf is this game"), None);
+        assert_eq!(rule(&defaults, "Meet me at the bus stop"), None);
+        assert_eq!(rule(&defaults, "Please stop talking"), Some(AttentionCommand::Stop));
     }
 
-    pub async fn should_quote(&self, tweet_content: &str) -> bool {
-        let prompt = format!(
-            "You are deciding whether to quote tweet. Quote tweet if the content aligns with your interests, \
-            deserves commentary, or warrants a thoughtful response.\n\n\
-            Tweet: {}\n\n\
-            Respond with only 'true' or 'false':",
-            tweet_content
-        );
+    #[test]
+    fn test_aliases_and_ignore_patterns_from_toml() {
+        let config: AttentionConfig = toml::from_str(
+            r#"
+            bot_names = ["Gi-hun", "Player 456"]
+            ignore_patterns = ["^!"]
+            mute_minutes = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.mute_duration, Duration::from_secs(300));
+        assert_eq!(config.stop_phrases, AttentionConfig::default().stop_phrases);
 
-        let builder = self.completion_model.completion_request(&prompt);
+        assert_eq!(rule(&config, "Hey GI-HUN, you there?"), Some(AttentionCommand::Respond));
+        assert_eq!(rule(&config, "player   456 wins again"), Some(AttentionCommand::Respond));
+        assert_eq!(rule(&config, "Player 4567 is out"), None);
+        assert_eq!(rule(&config, "Gi-hunter is a strange username"), None);
+        assert_eq!(rule(&config, "!stop the music"), Some(AttentionCommand::Ignore));
 
-        match self.completion_model.completion(builder.build()).await {
-            Ok(response) => match response.choice {
-                ModelChoice::Message(text) => text.trim().to_lowercase() == "true",
-                ModelChoice::ToolCall(_, _) => false,
+        assert!(toml::from_str::<AttentionConfig>(r#"ignore_patterns = ["("]"#).is_err());
+    }
+
+    #[test]
+    fn test_policy_decides_before_rules() {
+        let config = AttentionConfig {
+            bot_names: vec!["Gi-hun".to_string()],
+            ..Default::default()
+        };
+        let now = chrono::Utc::now();
+        let mut state = ChannelAttention::default();
+        let decide = |policy: &EngagementPolicy, state: &ChannelAttention, content: &str| {
+            let context = AttentionContext {
+                message_id: "message-1".to_string(),
+                author_id: "user-1".to_string(),
+                message_content: content.to_string(),
+                mentioned_names: HashSet::new(),
+                history: vec![],
+                channel_type: ChannelType::Text,
+                channel_id: "channel-1".to_string(),
+                source: Source::Discord,
+            };
+            config
+                .decide_by_policy(policy, &context, state, now)
+                .map(|decision| decision.command)
+        };
+
+        let mut policy = EngagementPolicy::default();
+        assert_eq!(decide(&policy, &state, "Anyone around?"), None);
+
+        policy.mode = EngagementMode::MentionOnly;
+        assert_eq!(decide(&policy, &state, "Anyone around?"), Some(AttentionCommand::Ignore));
+        assert_eq!(decide(&policy, &state, "Gi-hun, you around?"), None);
+
+        policy.mode = EngagementMode::Never;
+        assert_eq!(decide(&policy, &state, "Gi-hun, you around?"), Some(AttentionCommand::Ignore));
+        assert_eq!(policy.excludes("user-1"), Some("channel policy is never"));
+
+        policy.mode = EngagementMode::Always;
+        assert_eq!(decide(&policy, &state, "Anyone around?"), Some(AttentionCommand::Respond));
+        // Left to the rules, which stop and stay quiet
+        assert_eq!(decide(&policy, &state, "please stop talking"), None);
+        state.muted_until = Some(now + chrono::TimeDelta::minutes(10));
+        assert_eq!(decide(&policy, &state, "Anyone around?"), None);
+        state.muted_until = None;
+
+        policy.allowed_users = vec!["user-2".to_string()];
+        assert_eq!(decide(&policy, &state, "Anyone around?"), Some(AttentionCommand::Ignore));
+        policy.allowed_users.push("user-1".to_string());
+        policy.blocked_users = vec!["user-1".to_string()];
+        assert_eq!(decide(&policy, &state, "Gi-hun, you around?"), Some(AttentionCommand::Ignore));
+        // Also what Twitter checks before deciding how to engage with a tweet
+        assert_eq!(policy.excludes("user-1"), Some("author is blocked"));
+        assert_eq!(policy.excludes("user-2"), None);
+        assert_eq!(policy.excludes("user-3"), Some("author is not on the allowlist"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_and_mute_win_over_always() {
+        let knowledge = test_knowledge_base(16).await;
+        let policy = EngagementPolicy {
+            mode: EngagementMode::Always,
+            ..Default::default()
+        };
+        knowledge
+            .set_engagement_policy(&Source::Discord, Some("channel-1"), &policy)
+            .await
+            .unwrap();
+
+        let attention = Attention::new(
+            AttentionConfig {
+                bot_names: vec!["Gi-hun".to_string()],
+                ..Default::default()
             },
-            Err(_) => false,
-        }
+            StaticCompletionModel::relevance(0.0),
+        );
+        let context = |content: &str| AttentionContext {
+            message_id: "message-1".to_string(),
+            author_id: "user-1".to_string(),
+            message_content: content.to_string(),
+            mentioned_names: HashSet::new(),
+            history: vec![],
+            channel_type: ChannelType::Text,
+            channel_id: "channel-1".to_string(),
+            source: Source::Discord,
+        };
+        let decide = |content: &'static str| {
+            let attention = &attention;
+            let knowledge = &knowledge;
+            async move {
+                attention
+                    .should_reply_in_channel(knowledge, &context(content))
+                    .await
+                    .command
+            }
+        };
+
+        assert_eq!(decide("Anyone around?").await, AttentionCommand::Respond);
+        assert_eq!(decide("shut up").await, AttentionCommand::Stop);
+        assert_eq!(decide("Anyone around?").await, AttentionCommand::Ignore);
+        assert_eq!(decide("Gi-hun, are you there?").await, AttentionCommand::Respond);
+
+        // Muted by an admin
+        let mut state = knowledge
+            .channel_attention(&Source::Discord, "channel-1")
+            .await
+            .unwrap();
+        state.muted_until = Some(chrono::Utc::now() + chrono::TimeDelta::minutes(30));
+        knowledge
+            .set_channel_attention(&Source::Discord, "channel-1", &state)
+            .await
+            .unwrap();
+        assert_eq!(decide("Anyone around?").await, AttentionCommand::Ignore);
+        let state = knowledge
+            .channel_attention(&Source::Discord, "channel-1")
+            .await
+            .unwrap();
+        assert!(state.muted_until.is_some());
     }
 
+    #[test]
+    fn test_engagement_actions() {
+        let reply = r#"{"actions": ["like", "Reply", "quote", "like", "ignore"], "confidence": 0.9, "reason": "about the games"}"#;
+        let decision = decide_engagement(reply, 0.5).unwrap();
+        assert_eq!(decision.actions, vec![EngagementAction::Like, EngagementAction::Quote]);
+        assert!(decision.contains(EngagementAction::Quote));
+        assert_eq!(decision.reason, "about the games");
+
+        // Too unsure to act
+        assert!(decide_engagement(reply, 0.95).unwrap().is_ignore());
+        assert!(decide_engagement(r#"{"actions": ["ignore"], "confidence": 1}"#, 0.5)
+            .unwrap()
+            .is_ignore());
+        assert!(decide_engagement("true", 0.5).is_err());
+    }
+
+    #[test]
+    fn test_decide_rejects_unreadable_replies() {
+        assert!(decide("[RESPOND]", 0.6).is_err());
+        assert!(decide(r#"{"reason": "no score"}"#, 0.6).is_err());
+        assert!(decide("} {", 0.6).is_err());
+    }
+
+    #[test]
+    fn test_persona_names_topics_and_conversation_types() {
+        let config = AttentionConfig {
+            bot_names: vec!["Gi-hun".to_string()],
+            interests: vec!["Squid Game".to_string(), "Debt".to_string()],
+            conversation_types: vec!["questions about the games".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.persona(),
+            "You are Gi-hun. Your topics and interests: Squid Game, Debt. \
+            Conversations you like to join: questions about the games."
+        );
+        assert_eq!(config.topics().len(), 3);
+        assert_eq!(AttentionConfig::default().persona(), "You are a chat bot.");
+    }
+
+    #[tokio::test]
+    async fn test_topic_filter_prefers_related_messages() {
+        let filter = EmbeddingTopicFilter::new(
+            crate::embeddings::HashingEmbeddingModel::default(),
+            vec!["The Squid Game and its players".to_string(), "Paying back debt".to_string()],
+        )
+        .await
+        .unwrap();
+
+        let related = filter.similarity("Who survived the last squid game?").await.unwrap();
+        let unrelated = filter.similarity("Best pizza toppings in Naples").await.unwrap();
+        assert!(related > unrelated, "{} <= {}", related, unrelated);
+        assert_eq!(filter.similarity("").await.unwrap(), 0.0);
+    }
 }
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
+use crate::attention::AttentionConfig;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Character {
     pub name: String,
@@ -8,6 +10,12 @@ pub struct Character {
     pub message_examples: Vec<String>,
     pub topics: Vec<String>,
     pub style: Style,
+    /// Names the character goes by in transcripts, e.g. `Gi-hun`
+    #[serde(default)]
+    pub speakers: Vec<String>,
+    /// Aliases, stop phrases and other attention settings
+    #[serde(default)]
+    pub attention: AttentionConfig,
 }
 
 
@@ -30,4 +38,29 @@ impl Character {
         debug!(name = character.name, "Character loaded successfully");
         Ok(character)
     }
+
+    /// The `[attention]` settings, answering to the character's name as well
+    /// as its aliases and engaging with its topics and interests.
+    pub fn attention_config(&self) -> AttentionConfig {
+        let mut config = self.attention.clone();
+        if !config.bot_names.contains(&self.name) {
+            config.bot_names.insert(0, self.name.clone());
+        }
+        for interest in self.topics.iter().chain(&self.style.interests) {
+            if !config.interests.contains(interest) {
+                config.interests.push(interest.clone());
+            }
+        }
+        config
+    }
+
+    /// Speaker names whose lines are loaded from transcripts, falling back
+    /// to the character's name.
+    pub fn speakers(&self) -> Vec<String> {
+        if self.speakers.is_empty() {
+            vec![self.name.clone()]
+        } else {
+            self.speakers.clone()
+        }
+    }
 }
\ No newline at end of file
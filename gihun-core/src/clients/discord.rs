@@ -3,6 +3,8 @@ use rig::{
     embeddings::EmbeddingModel,
 };
 use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateCommandOption, EditInteractionResponse};
+use serenity::model::application::{Command, CommandDataOptionValue, CommandOptionType, Interaction};
 use serenity::model::channel::Message;
 use serenity::model::gateway::GatewayIntents;
 use serenity::model::gateway::Ready;
@@ -12,8 +14,10 @@ use tracing::{debug, error, info};
 
 use crate::{agent::Agent, attention::AttentionCommand};
 use crate::{
+    admin::{Admin, AdminCommand, ADMIN_COMMANDS, MAX_MUTE_MINUTES},
     attention::{Attention, AttentionContext},
     knowledge,
+    policy::{Admins, PolicyCommand},
 };
 
 const MIN_CHUNK_LENGTH: usize = 100;
@@ -24,11 +28,23 @@ const MAX_HISTORY_MESSAGES: i64 = 10;
 pub struct DiscordClient<M: CompletionModel, E: EmbeddingModel + 'static> {
     agent: Agent<M, E>,
     attention: Attention<M>,
+    admin: Admin<M, E>,
 }
 
 impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> DiscordClient<M, E> {
     pub fn new(agent: Agent<M, E>, attention: Attention<M>) -> Self {
-        Self { agent, attention }
+        let admin = Admin::new(agent.clone(), attention.clone(), Admins::default());
+        Self {
+            agent,
+            attention,
+            admin,
+        }
+    }
+
+    /// Runs application commands and `!policy` commands of admins.
+    pub fn with_admin(mut self, admin: Admin<M, E>) -> Self {
+        self.admin = admin;
+        self
     }
 
     pub async fn start(&self, token: &str) -> Result<(), serenity::Error> {
@@ -75,6 +91,30 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> EventHandler
         }
 
         let knowledge = self.agent.knowledge();
+
+        if let Some(command) = PolicyCommand::parse(&msg.content) {
+            if !self
+                .admin
+                .is_admin(&knowledge::Source::Discord, &msg.author.id.to_string())
+            {
+                debug!(author = %msg.author.id, "Ignoring policy command from non-admin");
+                return;
+            }
+            let reply = match command {
+                Ok(command) => command
+                    .run(knowledge, &knowledge::Source::Discord, &msg.channel_id.to_string())
+                    .await
+                    .unwrap_or_else(|err| {
+                        error!(?err, "Failed to run policy command");
+                        "Failed to update the policy".to_string()
+                    }),
+                Err(usage) => usage,
+            };
+            if let Err(why) = msg.channel_id.say(&ctx.http, reply).await {
+                error!(?why, "Failed to send message");
+            }
+            return;
+        }
         let knowledge_msg = knowledge::Message::from(msg.clone());
 
         if let Err(err) = knowledge
@@ -109,16 +149,29 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> EventHandler
         );
 
         let context = AttentionContext {
+            message_id: knowledge_msg.id.clone(),
+            author_id: knowledge_msg.account_id.clone(),
             message_content: msg.content.clone(),
             mentioned_names,
             history,
             channel_type: knowledge_msg.channel_type,
+            channel_id: knowledge_msg.channel_id.clone(),
             source: knowledge_msg.source,
         };
 
         debug!(?context, "Attention context");
 
-        match self.attention.should_reply(&context).await {
+        let decision = self
+            .attention
+            .should_reply_in_channel(knowledge, &context)
+            .await;
+        debug!(
+            command = ?decision.command,
+            score = ?decision.score,
+            reason = %decision.reason,
+            "Attention decision"
+        );
+        match decision.command {
             AttentionCommand::Respond => {}
             _ => {
                 debug!("Bot decided not to reply to message");
@@ -126,9 +179,12 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> EventHandler
             }
         }
 
+        // Answer from lore and documentation rather than the character's lines
         let agent = self
             .agent
-            .builder()
+            .builder_with_filter(
+                knowledge::DocumentFilter::default().without_source(knowledge::DIALOGUE_SOURCE),
+            )
             .context(&format!(
                 "Current time: {}",
                 chrono::Local::now().format("%I:%M:%S %p, %Y-%m-%d")
@@ -155,10 +211,96 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> EventHandler
         }
     }
 
-    async fn ready(&self, _: Context, ready: Ready) {
-        info!(name = self.agent.character.name, "Bot connected");
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!(name = self.agent.character().name, "Bot connected");
         info!(guild_count = ready.guilds.len(), "Serving guilds");
+
+        if let Err(err) = Command::set_global_commands(&ctx.http, admin_commands()).await {
+            error!(?err, "Failed to register admin commands");
+        }
     }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Interaction::Command(command) = interaction else {
+            return;
+        };
+
+        if let Err(err) = command.defer_ephemeral(&ctx.http).await {
+            error!(?err, "Failed to acknowledge command");
+            return;
+        }
+
+        let reply = if !self
+            .admin
+            .is_admin(&knowledge::Source::Discord, &command.user.id.to_string())
+        {
+            debug!(user = %command.user.id, "Refusing admin command from non-admin");
+            "Only admins can use this command".to_string()
+        } else {
+            let args: Vec<String> = command
+                .data
+                .options
+                .iter()
+                .map(|option| match &option.value {
+                    CommandDataOptionValue::Integer(value) => value.to_string(),
+                    CommandDataOptionValue::String(value) => value.clone(),
+                    CommandDataOptionValue::User(user) => user.to_string(),
+                    _ => String::new(),
+                })
+                .collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+            match AdminCommand::from_parts(&command.data.name, &args) {
+                Ok(admin_command) => {
+                    self.admin
+                        .run(
+                            &admin_command,
+                            &knowledge::Source::Discord,
+                            &command.channel_id.to_string(),
+                        )
+                        .await
+                }
+                Err(usage) => usage,
+            }
+        };
+
+        if let Err(err) = command
+            .edit_response(&ctx.http, EditInteractionResponse::new().content(reply))
+            .await
+        {
+            error!(?err, "Failed to reply to command");
+        }
+    }
+}
+
+/// Application commands for [`ADMIN_COMMANDS`], with their options.
+fn admin_commands() -> Vec<CreateCommand> {
+    ADMIN_COMMANDS
+        .iter()
+        .map(|(name, description)| {
+            let command = CreateCommand::new(*name).description(*description);
+            match *name {
+                "mute" => command.add_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "minutes", "How long to stay quiet")
+                        .min_int_value(1)
+                        .max_int_value(MAX_MUTE_MINUTES),
+                ),
+                "mode" => command.add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "mode", "When to reply")
+                        .required(true)
+                        .add_string_choice("default", "default")
+                        .add_string_choice("always", "always")
+                        .add_string_choice("mention-only", "mention-only")
+                        .add_string_choice("never", "never"),
+                ),
+                "forget" => command.add_option(
+                    CreateCommandOption::new(CommandOptionType::User, "user", "User to forget")
+                        .required(true),
+                ),
+                _ => command,
+            }
+        })
+        .collect()
 }
 
 pub fn chunk_message(text: &str, max_length: usize, min_chunk_length: usize) -> Vec<String> {
@@ -180,12 +322,10 @@ pub fn chunk_message(text: &str, max_length: usize, min_chunk_length: usize) ->
         }
 
         // Start new chunk on headings
-        if line.starts_with('#') {
-            if i > 0 {
-                split_index = text.find(line).unwrap_or(text.len());
-                in_heading = true;
-                break;
-            }
+        if line.starts_with('#') && i > 0 {
+            split_index = text.find(line).unwrap_or(text.len());
+            in_heading = true;
+            break;
         }
 
         // Check if adding this line would exceed max_length
@@ -1,13 +1,15 @@
 use crate::{
+    admin::{Admin, AdminCommand, ADMIN_COMMANDS},
     agent::Agent,
     attention::{Attention, AttentionCommand, AttentionContext},
     knowledge::{self, ChannelType, Source},
+    policy::{Admins, PolicyCommand},
 };
 use rig::{completion::{CompletionModel, Prompt}, embeddings::EmbeddingModel};
 use std::collections::HashSet;
 use teloxide::{
     prelude::*,
-    types::MessageKind,
+    types::{BotCommand, MessageKind},
 };
 use tracing::{debug, error, info};
 
@@ -17,20 +19,36 @@ pub struct TelegramClient<M: CompletionModel, E: EmbeddingModel + 'static> {
     agent: Agent<M, E>,
     attention: Attention<M>,
     bot: Bot,
+    admin: Admin<M, E>,
 }
 
 impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M, E> {
     pub fn new(agent: Agent<M, E>, attention: Attention<M>, token: String) -> Self {
         let bot = Bot::new(token);
+        let admin = Admin::new(agent.clone(), attention.clone(), Admins::default());
         Self {
             agent,
             attention,
             bot,
+            admin,
         }
     }
 
+    /// Runs admin `/commands` and `/policy` commands of admins.
+    pub fn with_admin(mut self, admin: Admin<M, E>) -> Self {
+        self.admin = admin;
+        self
+    }
+
     pub async fn start(&self) {
         info!("Starting Telegram bot");
+        let commands: Vec<BotCommand> = ADMIN_COMMANDS
+            .iter()
+            .map(|(name, description)| BotCommand::new(*name, *description))
+            .collect();
+        if let Err(err) = self.bot.set_my_commands(commands).send().await {
+            error!(?err, "Failed to register admin commands");
+        }
         let this = self.clone();
         let handler = Update::filter_message().branch(
             dptree::filter(|msg: teloxide::types::Message| matches!(msg.kind, MessageKind::Common(_)))
@@ -48,7 +66,7 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M
     }
 
     async fn handle_message(&self, msg: teloxide::types::Message) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if msg.from().map_or(true, |user| user.is_bot) {
+        if msg.from().is_none_or(|user| user.is_bot) {
             return Ok(());
         }
 
@@ -59,6 +77,34 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M
         };
 
         let knowledge = self.agent.knowledge();
+
+        let policy_command = PolicyCommand::parse(&text);
+        let admin_command = AdminCommand::parse(&text);
+        if policy_command.is_some() || admin_command.is_some() {
+            let user_id = msg.from().map_or_else(String::new, |user| user.id.to_string());
+            if !self.admin.is_admin(&Source::Telegram, &user_id) {
+                debug!(user_id, "Ignoring admin command from non-admin");
+                return Ok(());
+            }
+            let chat_id = msg.chat.id.to_string();
+            let reply = match (policy_command, admin_command) {
+                (Some(Ok(command)), _) => command
+                    .run(knowledge, &Source::Telegram, &chat_id)
+                    .await
+                    .unwrap_or_else(|err| {
+                        error!(?err, "Failed to run policy command");
+                        "Failed to update the policy".to_string()
+                    }),
+                (_, Some(Ok(command))) => self.admin.run(&command, &Source::Telegram, &chat_id).await,
+                (Some(Err(usage)), _) | (_, Some(Err(usage))) => usage,
+                (None, None) => unreachable!(),
+            };
+            if let Err(why) = self.bot.send_message(msg.chat.id, reply).send().await {
+                error!(?why, "Failed to send message");
+            }
+            return Ok(());
+        }
+
         let knowledge_msg = self.convert_to_knowledge_message(msg.clone());
 
         if let Err(err) = knowledge.clone().create_message(knowledge_msg.clone()).await {
@@ -85,6 +131,8 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M
         debug!(mentioned_names = ?mentioned_names, "Mentioned names in message");
 
         let context = AttentionContext {
+            message_id: msg.id.to_string(),
+            author_id: knowledge_msg.account_id.clone(),
             message_content: text.clone(),
             mentioned_names,
             history,
@@ -93,12 +141,23 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M
             } else {
                 ChannelType::Text
             },
+            channel_id: msg.chat.id.to_string(),
             source: Source::Telegram,
         };
 
         debug!(?context, "Attention context");
 
-        match self.attention.should_reply(&context).await {
+        let decision = self
+            .attention
+            .should_reply_in_channel(knowledge, &context)
+            .await;
+        debug!(
+            command = ?decision.command,
+            score = ?decision.score,
+            reason = %decision.reason,
+            "Attention decision"
+        );
+        match decision.command {
             AttentionCommand::Respond => {}
             _ => {
                 debug!("Bot decided not to reply to message");
@@ -106,9 +165,12 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M
             }
         }
 
+        // Answer from lore and documentation rather than the character's lines
         let agent = self
             .agent
-            .builder()
+            .builder_with_filter(
+                knowledge::DocumentFilter::default().without_source(knowledge::DIALOGUE_SOURCE),
+            )
             .context(&format!(
                 "Current time: {}",
                 chrono::Local::now().format("%I:%M:%S %p, %Y-%m-%d")
@@ -146,7 +208,7 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TelegramClient<M
             account_id: msg.from().map_or_else(String::new, |user| user.id.to_string()),
             role: "user".to_string(),
             content: msg.text().unwrap_or_default().to_string(),
-            created_at: msg.date.into(),
+            created_at: msg.date,
         }
     }
 }
@@ -164,6 +226,7 @@ impl<M: CompletionModel, E: EmbeddingModel> Clone for TelegramClient<M, E> {
             agent: self.agent.clone(),
             attention: self.attention.clone(),
             bot: self.bot.clone(),
+            admin: self.admin.clone(),
         }
     }
 }
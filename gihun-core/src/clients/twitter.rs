@@ -1,7 +1,10 @@
 use crate::{
+    admin::TweetTrigger,
     agent::Agent,
-    attention::{Attention, AttentionCommand, AttentionContext},
-    knowledge::{ChannelType, KnowledgeBase, Message, Source},
+    attention::{Attention, AttentionCommand, AttentionContext, EngagementAction, EngagementDecision},
+    knowledge::{
+        ChannelType, DocumentFilter, EngagementPolicy, Message, Source, DIALOGUE_SOURCE, WEB_SOURCE,
+    },
 };
 use rand::Rng;
 use rig::{
@@ -12,10 +15,10 @@ use agent_twitter_client::scraper::Scraper;
 use std::collections::HashSet;
 use std::sync::Arc;
 use tracing::{debug, error, info};
-use std::collections::VecDeque;
 
 const MAX_TWEET_LENGTH: usize = 280;
 const MAX_HISTORY_TWEETS: i64 = 10;
+const MAX_NEWS_ITEMS: usize = 3;
 
 
 pub struct TwitterClient<M: CompletionModel, E: EmbeddingModel + 'static> {
@@ -23,6 +26,7 @@ pub struct TwitterClient<M: CompletionModel, E: EmbeddingModel + 'static> {
     attention: Attention<M>,
     scraper: Scraper,
     username: String,
+    tweet_trigger: TweetTrigger,
 }
 
 // rand chance between posting a new tweet
@@ -75,10 +79,18 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
             agent,
             attention,
             scraper,
-            username
+            username,
+            tweet_trigger: TweetTrigger::default(),
         })
     }
 
+    /// Posts a new tweet whenever `tweet_trigger` is requested, e.g. by the
+    /// `/tweet` admin command.
+    pub fn with_tweet_trigger(mut self, tweet_trigger: TweetTrigger) -> Self {
+        self.tweet_trigger = tweet_trigger;
+        self
+    }
+
 
     pub async fn start(&self) {
         info!("Starting Twitter bot");
@@ -111,18 +123,25 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
                                 if seen_tweet_ids.len() >= 5 && !seen_tweet_ids.contains(&tweet_id) {
                                     seen_tweet_ids.remove(0);
                                 }
-                                match self.random_number(0, 3){
-                                    0 | 1 => {
-                                        self.handle_quote(&tweet_content, &tweet_id).await;
-                                    }
-                                    2 => {
-                                        self.handle_retweet(&tweet_content, &tweet_id).await;
-
-                                    }
-                                    3 => {
-                                        self.handle_like(&tweet_content, &tweet_id).await;
-                                    }
-                                    _ => unreachable!(),
+                                let conversation_id = tweet["legacy"]["conversation_id_str"]
+                                    .as_str()
+                                    .unwrap_or(&tweet_id)
+                                    .to_string();
+                                let author_id = tweet["legacy"]["user_id_str"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .to_string();
+                                let author = tweet["core"]["user_results"]["result"]["legacy"]["screen_name"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .to_string();
+                                let quoted = tweet["quoted_status_result"]["result"]["legacy"]["full_text"]
+                                    .as_str()
+                                    .map(|text| format!("The tweet quotes: {}", text));
+                                if author.eq_ignore_ascii_case(&self.username) {
+                                    debug!("Not engaging with bot's own tweet");
+                                } else {
+                                    self.engage(&tweet_content, &tweet_id, &conversation_id, &author_id, &author, quoted.as_slice()).await;
                                 }
                                 seen_tweet_ids.push(tweet_id.clone());
                                 tokio::time::sleep(tokio::time::Duration::from_secs(self.random_number(60, 180))).await;
@@ -136,26 +155,60 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
                 _ => unreachable!(),
             }
 
-            // Sleep between tasks
-            tokio::time::sleep(tokio::time::Duration::from_secs(
-                self.random_number(30 * 60, 60 * 60),
-            )).await;
+            // Sleep between tasks, tweeting when asked to
+            let next_task = tokio::time::Instant::now()
+                + tokio::time::Duration::from_secs(self.random_number(30 * 60, 60 * 60));
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(next_task) => break,
+                    _ = self.tweet_trigger.requested() => {
+                        debug!("Post new tweet on request");
+                        if let Err(err) = self.post_new_tweet().await {
+                            error!(?err, "Failed to post new tweet");
+                        }
+                    }
+                }
+            }
         }
     }
 
     async fn post_new_tweet(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let agent = self
+        let since = chrono::Utc::now() - chrono::Duration::days(1);
+        let news = match self
             .agent
-            .builder()
+            .knowledge()
+            .recent_documents(WEB_SOURCE, since, MAX_NEWS_ITEMS)
+            .await
+        {
+            Ok(news) => news,
+            Err(err) => {
+                error!(?err, "Failed to fetch recent news");
+                Vec::new()
+            }
+        };
+
+        let mut builder = self
+            .agent
+            .builder_with_filter(DocumentFilter::source(DIALOGUE_SOURCE))
             .context(&format!(
                 "Current time: {}",
                 chrono::Local::now().format("%I:%M:%S %p, %Y-%m-%d")
             ))
-            .context("Please keep your responses concise and under 280 characters. Use the provided document to draw inspiriation from lines that you, Gi-hun, would say.")
-            .dynamic_context(4, self.agent.knowledge().clone().document_index())
-            .build();
+            .context("Please keep your responses concise and under 280 characters. Use the provided document to draw inspiriation from lines that you, Gi-hun, would say.");
+        if !news.is_empty() {
+            let news = news
+                .iter()
+                .map(|document| document.content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n---\n\n");
+            builder = builder.context(&format!(
+                "Recent news you may comment on, in your own words:\n\n{}",
+                news
+            ));
+        }
+        let agent = builder.build();
         let tweet_prompt = "Share brief thoughts or observation in one or two short sentences.";
-        let response = match agent.prompt(&tweet_prompt).await {
+        let response = match agent.prompt(tweet_prompt).await {
             Ok(response) => response,
             Err(err) => {
                 error!(?err, "Failed to generate response for tweet");
@@ -172,6 +225,7 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
     }
 
 
+    #[allow(dead_code)]
     async fn handle_mention(
         &self,
         tweet: agent_twitter_client::models::Tweet,
@@ -211,10 +265,13 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
             .collect();
         debug!(history = ?history, "History");
         let context = AttentionContext {
+            message_id: knowledge_msg.id.clone(),
+            author_id: knowledge_msg.account_id.clone(),
             message_content: tweet_text.as_str().to_string(),
             mentioned_names,
             history,
             channel_type: knowledge_msg.channel_type,
+            channel_id: knowledge_msg.channel_id.clone(),
             source: knowledge_msg.source,
         };
 
@@ -223,7 +280,17 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
             return Ok(());
         }
 
-        match self.attention.should_reply(&context).await {
+        let decision = self
+            .attention
+            .should_reply_in_channel(knowledge, &context)
+            .await;
+        debug!(
+            command = ?decision.command,
+            score = ?decision.score,
+            reason = %decision.reason,
+            "Attention decision"
+        );
+        match decision.command {
             AttentionCommand::Respond => {}
             _ => {
                 debug!("Bot decided not to reply to tweet");
@@ -245,7 +312,7 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
             .context("If the tweet contains images, read it and incorporate them into your response.")
             .build();
 
-        let response = match agent.prompt(&tweet_text.as_str().to_string()).await {
+        let response = match agent.prompt(tweet_text.as_str()).await {
             Ok(response) => response,
             Err(err) => {
                 error!(?err, "Failed to generate response");
@@ -256,7 +323,7 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
         debug!(response = %response, "Generated response for reply");
 
         // Split response into tweet-sized chunks if necessary
-        let chunks: Vec<String> = response
+        let _chunks: Vec<String> = response
             .chars()
             .collect::<Vec<char>>()
             .chunks(MAX_TWEET_LENGTH)
@@ -269,6 +336,7 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
         Ok(())
     }
 
+    #[allow(dead_code)]
     async fn build_conversation_thread(
         &self,
         tweet: &agent_twitter_client::models::Tweet,
@@ -325,59 +393,101 @@ impl<M: CompletionModel + 'static, E: EmbeddingModel + 'static> TwitterClient<M,
         rng.gen_range(min..=max)
     }
 
-    async fn handle_like(&self, tweet_content: &str, tweet_id: &str) {
-        if self.attention.should_like(tweet_content).await {
-            debug!(tweet_content = %tweet_content, "Agent decided to like tweet");
+    /// Likes, retweets, quotes or replies to a tweet as decided by a single
+    /// [`Attention::decide_engagement`] call, which is logged. Tweets the
+    /// conversation's [`EngagementPolicy`] excludes are skipped without one.
+    async fn engage(
+        &self,
+        tweet_content: &str,
+        tweet_id: &str,
+        conversation_id: &str,
+        author_id: &str,
+        author: &str,
+        context: &[String],
+    ) {
+        let policy = self
+            .agent
+            .knowledge()
+            .engagement_policy(&Source::Twitter, conversation_id)
+            .await
+            .unwrap_or_else(|err| {
+                error!(?err, "Failed to load engagement policy");
+                EngagementPolicy::default()
+            });
+
+        let decision = match policy.excludes(author_id) {
+            Some(reason) => {
+                debug!(author, reason, "Not engaging with tweet because of the engagement policy");
+                EngagementDecision {
+                    reason: reason.to_string(),
+                    ..Default::default()
+                }
+            }
+            None => {
+                self.attention
+                    .decide_engagement(tweet_content, author, context)
+                    .await
+            }
+        };
+        self.attention
+            .log_engagement(self.agent.knowledge(), conversation_id, tweet_id, &decision)
+            .await;
+        debug!(
+            tweet_content = %tweet_content,
+            actions = ?decision.actions,
+            confidence = decision.confidence,
+            reason = %decision.reason,
+            "Engagement decision"
+        );
+
+        if decision.contains(EngagementAction::Like) {
             if let Err(err) = self.scraper.like_tweet(tweet_id).await {
                 error!(?err, "Failed to like tweet");
             }
-        } else {
-            debug!(tweet_content = %tweet_content, "Agent decided not to like tweet");
         }
-    }
 
-    async fn handle_retweet(&self, tweet_content: &str, tweet_id: &str) {
-        if self.attention.should_retweet(tweet_content).await {
-            debug!(tweet_content = %tweet_content, "Agent decided to retweet");
+        if decision.contains(EngagementAction::Retweet) {
             if let Err(err) = self.scraper.retweet(tweet_id).await {
                 error!(?err, "Failed to retweet");
             }
-        } else {
-            debug!(tweet_content = %tweet_content, "Agent decided not to retweet");
         }
-    }
-    //store quoted tweets in messages
-    async fn handle_quote(&self, tweet_content: &str, tweet_id: &str) {
-        
-        if self.attention.should_quote(tweet_content).await {
-            debug!(tweet_content = %tweet_content, "Agent decided to quote tweet");
-
-            let agent = self
-                .agent
-                .builder()
-                .context(&format!(
-                    "Current time: {}",
-                    chrono::Local::now().format("%I:%M:%S %p, %Y-%m-%d")
-                ))
-                .context("Please keep your responses concise and under 280 characters.")
-                .context("Write a natural reply to the quoted tweet in 1-2 short sentences. Use the provided document to find similar lines that you, Gi-hun, would say. Keep it conversational and relevant.")
-                .dynamic_context(4, self.agent.knowledge().clone().document_index())
-                .build();
-
-            let response = match agent.prompt(&tweet_content).await {
-                Ok(response) => response,
-                Err(err) => {
-                    error!(?err, "Failed to generate response");
-                    return;
+
+        if decision.contains(EngagementAction::Quote) {
+            let instruction = "Write a natural reply to the quoted tweet in 1-2 short sentences. Use the provided document to find similar lines that you, Gi-hun, would say. Keep it conversational and relevant.";
+            if let Some(response) = self.compose(tweet_content, instruction).await {
+                if let Err(err) = self.scraper.send_quote_tweet(&response, tweet_id, None).await {
+                    error!(?err, "Failed to quote tweet");
+                }
+            }
+        } else if decision.contains(EngagementAction::Reply) {
+            let instruction = "Reply to the author of this tweet in 1-2 short sentences. Use the provided document to find similar lines that you, Gi-hun, would say. Keep it conversational and relevant.";
+            if let Some(response) = self.compose(tweet_content, instruction).await {
+                if let Err(err) = self.scraper.send_tweet(&response, Some(tweet_id), None).await {
+                    error!(?err, "Failed to reply to tweet");
                 }
-            };
-            if let Err(err) = self.scraper.send_quote_tweet(&response, tweet_id, None).await {
-                error!(?err, "Failed to quote tweet");
             }
-        } else {
-            debug!(tweet_content = %tweet_content, "Agent decided not to quote tweet");
         }
     }
 
+    /// Writes a tweet about `tweet_content` in the character's voice.
+    async fn compose(&self, tweet_content: &str, instruction: &str) -> Option<String> {
+        let agent = self
+            .agent
+            .builder_with_filter(DocumentFilter::source(DIALOGUE_SOURCE))
+            .context(&format!(
+                "Current time: {}",
+                chrono::Local::now().format("%I:%M:%S %p, %Y-%m-%d")
+            ))
+            .context("Please keep your responses concise and under 280 characters.")
+            .context(instruction)
+            .build();
 
-}
\ No newline at end of file
+        match agent.prompt(tweet_content).await {
+            Ok(response) => Some(response),
+            Err(err) => {
+                error!(?err, "Failed to generate response");
+                None
+            }
+        }
+    }
+}
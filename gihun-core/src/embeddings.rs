@@ -0,0 +1,135 @@
+use rig::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+
+pub const HASHING_EMBEDDING_DIMS: usize = 384;
+
+/// Deterministic feature-hashing embedder that runs locally on CPU.
+///
+/// Words and character trigrams are hashed into a fixed number of signed
+/// buckets and the result is L2-normalised, so texts sharing vocabulary end up
+/// close together. It needs no network access or API key, which makes it
+/// suitable for offline runs and for tests of the [`KnowledgeBase`].
+///
+/// [`KnowledgeBase`]: crate::knowledge::KnowledgeBase
+#[derive(Clone, Debug)]
+pub struct HashingEmbeddingModel {
+    ndims: usize,
+}
+
+impl HashingEmbeddingModel {
+    pub fn new(ndims: usize) -> Self {
+        Self { ndims: ndims.max(1) }
+    }
+
+    /// Name recorded in the knowledge base so switching models is detected.
+    pub fn model_name(&self) -> String {
+        format!("hashing-{}", self.ndims)
+    }
+
+    pub fn embed(&self, text: &str) -> Vec<f64> {
+        let mut vec = vec![0.0; self.ndims];
+        let text = text.to_lowercase();
+
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            self.add_feature(&mut vec, word.as_bytes(), 1.0);
+
+            let chars: Vec<char> = format!(" {} ", word).chars().collect();
+            for trigram in chars.windows(3) {
+                let trigram: String = trigram.iter().collect();
+                self.add_feature(&mut vec, trigram.as_bytes(), 0.5);
+            }
+        }
+
+        let norm = vec.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vec.iter_mut().for_each(|x| *x /= norm);
+        }
+
+        vec
+    }
+
+    fn add_feature(&self, vec: &mut [f64], feature: &[u8], weight: f64) {
+        let hash = fnv1a(feature);
+        let bucket = (hash % self.ndims as u64) as usize;
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vec[bucket] += sign * weight;
+    }
+}
+
+impl Default for HashingEmbeddingModel {
+    fn default() -> Self {
+        Self::new(HASHING_EMBEDDING_DIMS)
+    }
+}
+
+impl EmbeddingModel for HashingEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .into_iter()
+            .map(|document| Embedding {
+                vec: self.embed(&document),
+                document,
+            })
+            .collect())
+    }
+}
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` for anything stored, such
+/// as vectors and page fingerprints, which must stay comparable across Rust
+/// releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cosine(a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn test_embedding_is_deterministic_and_normalised() {
+        let model = HashingEmbeddingModel::new(64);
+        let a = model.embed("Red light, green light");
+        let b = model.embed("Red light, green light");
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 64);
+        assert!((cosine(&a, &a) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_shared_vocabulary_is_closer() {
+        let model = HashingEmbeddingModel::default();
+        let query = model.embed("Where is the Front Man hiding?");
+        let related = model.embed("The Front Man is hiding behind the mask");
+        let unrelated = model.embed("Memecoins on Solana pumped overnight");
+        assert!(cosine(&query, &related) > cosine(&query, &unrelated));
+    }
+
+    #[test]
+    fn test_fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_empty_text_embeds_to_zero_vector() {
+        let model = HashingEmbeddingModel::new(8);
+        assert_eq!(model.embed("  ...  "), vec![0.0; 8]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::BufRead;
+
+use anyhow::Context;
+use rig::completion::{
+    CompletionError, CompletionModel, CompletionRequest, CompletionResponse, ModelChoice,
+};
+use serde::Deserialize;
+
+use crate::attention::{Attention, AttentionCommand, AttentionContext};
+use crate::knowledge::{ChannelAttention, ChannelType, Source};
+
+/// A line of an evaluation dataset: a message and whether the bot should
+/// reply to it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LabelledMessage {
+    /// Shown in the report when the message is misjudged
+    #[serde(default)]
+    pub id: String,
+    pub content: String,
+    #[serde(default)]
+    pub mentioned_names: Vec<String>,
+    /// Earlier messages in the channel, oldest first
+    #[serde(default)]
+    pub history: Vec<String>,
+    #[serde(default)]
+    pub direct_message: bool,
+    pub respond: bool,
+}
+
+/// A message the bot would have answered differently than labelled.
+#[derive(Clone, Debug)]
+pub struct Mistake {
+    pub id: String,
+    pub content: String,
+    pub expected: bool,
+    pub reason: String,
+}
+
+/// How replying compares with the labels, replying being the positive class.
+#[derive(Clone, Debug, Default)]
+pub struct EvaluationReport {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub true_negatives: usize,
+    pub false_negatives: usize,
+    pub mistakes: Vec<Mistake>,
+}
+
+impl EvaluationReport {
+    pub fn total(&self) -> usize {
+        self.true_positives + self.false_positives + self.true_negatives + self.false_negatives
+    }
+
+    /// Share of replies that were wanted, `None` without replies.
+    pub fn precision(&self) -> Option<f64> {
+        ratio(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    /// Share of wanted replies that were given, `None` when none were wanted.
+    pub fn recall(&self) -> Option<f64> {
+        ratio(self.true_positives, self.true_positives + self.false_negatives)
+    }
+
+    fn record(&mut self, message: &LabelledMessage, respond: bool, reason: String) {
+        match (message.respond, respond) {
+            (true, true) => self.true_positives += 1,
+            (false, false) => self.true_negatives += 1,
+            (false, true) => self.false_positives += 1,
+            (true, false) => self.false_negatives += 1,
+        }
+        if message.respond != respond {
+            self.mistakes.push(Mistake {
+                id: message.id.clone(),
+                content: message.content.clone(),
+                expected: message.respond,
+                reason,
+            });
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |value: Option<f64>| {
+            value.map_or_else(|| "n/a".to_string(), |value| format!("{:.1}%", value * 100.0))
+        };
+        writeln!(f, "Messages:  {}", self.total())?;
+        writeln!(
+            f,
+            "Replied:   {} correctly, {} wrongly",
+            self.true_positives, self.false_positives
+        )?;
+        writeln!(
+            f,
+            "Ignored:   {} correctly, {} wrongly",
+            self.true_negatives, self.false_negatives
+        )?;
+        writeln!(f, "Precision: {}", percent(self.precision()))?;
+        write!(f, "Recall:    {}", percent(self.recall()))
+    }
+}
+
+/// Replays every message of a JSON lines `dataset` through `attention` and
+/// compares its decisions with the labels. Each message is judged on its own,
+/// in a channel with no prior state.
+pub async fn evaluate<M: CompletionModel>(
+    attention: &Attention<M>,
+    dataset: impl BufRead,
+) -> anyhow::Result<EvaluationReport> {
+    let mut report = EvaluationReport::default();
+
+    for (number, line) in dataset.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: LabelledMessage = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid labelled message on line {}", number + 1))?;
+
+        let context = AttentionContext {
+            message_id: message.id.clone(),
+            author_id: String::new(),
+            message_content: message.content.clone(),
+            mentioned_names: message.mentioned_names.iter().cloned().collect::<HashSet<_>>(),
+            history: message
+                .history
+                .iter()
+                .map(|content| (String::new(), content.clone()))
+                .collect(),
+            channel_type: if message.direct_message {
+                ChannelType::DirectMessage
+            } else {
+                ChannelType::Text
+            },
+            channel_id: "evaluation".to_string(),
+            source: Source::Discord,
+        };
+
+        let decision = attention
+            .should_reply(&context, &mut ChannelAttention::default())
+            .await;
+        report.record(
+            &message,
+            decision.command == AttentionCommand::Respond,
+            decision.reason,
+        );
+    }
+
+    Ok(report)
+}
+
+/// Completion model answering every request with the same text, for
+/// evaluating rules and thresholds without calling a provider.
+#[derive(Clone, Debug)]
+pub struct StaticCompletionModel {
+    reply: String,
+}
+
+impl StaticCompletionModel {
+    pub fn new(reply: impl Into<String>) -> Self {
+        Self {
+            reply: reply.into(),
+        }
+    }
+
+    /// Classifies every message with the same relevance.
+    pub fn relevance(relevance: f32) -> Self {
+        Self::new(format!(
+            r#"{{"relevance": {}, "stop": false, "reason": "static relevance"}}"#,
+            relevance
+        ))
+    }
+}
+
+impl CompletionModel for StaticCompletionModel {
+    type Response = ();
+
+    async fn completion(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        Ok(CompletionResponse {
+            choice: ModelChoice::Message(self.reply.clone()),
+            raw_response: (),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attention::AttentionConfig;
+
+    #[tokio::test]
+    async fn test_precision_and_recall() {
+        let attention = Attention::new(
+            AttentionConfig {
+                bot_names: vec!["Gi-hun".to_string()],
+                ..Default::default()
+            },
+            StaticCompletionModel::relevance(0.7),
+        );
+        let dataset = r#"
+            {"id": "mention", "content": "Gi-hun, what happened in the last game?", "respond": true}
+            {"id": "dm", "content": "hey", "direct_message": true, "respond": true}
+            {"id": "stop", "content": "please stop talking", "respond": false}
+            {"id": "chatter", "content": "Anyone watching the match tonight?", "respond": false}
+            {"id": "short", "content": "ok", "respond": true}
+        "#;
+
+        let report = evaluate(&attention, dataset.as_bytes()).await.unwrap();
+        assert_eq!(report.total(), 5);
+        assert_eq!(report.true_positives, 2);
+        assert_eq!(report.false_positives, 1);
+        assert_eq!(report.false_negatives, 1);
+        assert_eq!(report.precision(), Some(2.0 / 3.0));
+        assert_eq!(report.recall(), Some(2.0 / 3.0));
+
+        let mistakes: Vec<_> = report.mistakes.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(mistakes, vec!["chatter", "short"]);
+        assert_eq!(report.mistakes[1].reason, "message too short");
+
+        assert!(evaluate(&attention, r#"{"content": "no label"}"#.as_bytes())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use super::models::{Document, Message};
+
+/// Version of the export format written by [`KnowledgeBase::export`](super::KnowledgeBase::export).
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// A line of a knowledge base export.
+///
+/// An export is JSON lines starting with a [`ExportRecord::Header`], followed
+/// by accounts, channels, documents and messages. Rows are keyed by their
+/// platform ids rather than database ids, so an export can be imported into
+/// any database.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportRecord {
+    Header(ExportHeader),
+    Account(AccountRecord),
+    Channel(ChannelRecord),
+    Document {
+        #[serde(flatten)]
+        document: Document,
+        /// Only present in exports made with vectors
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        embedding: Option<Vec<f32>>,
+    },
+    Message {
+        #[serde(flatten)]
+        message: Message,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        embedding: Option<Vec<f32>>,
+    },
+}
+
+/// The embedding model the vectors of an export were built with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportHeader {
+    pub version: u32,
+    pub embedding_model: String,
+    pub embedding_dims: usize,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccountRecord {
+    pub name: String,
+    pub source_id: String,
+    pub source: String,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChannelRecord {
+    pub channel_id: String,
+    pub channel_type: String,
+    pub source: String,
+    pub name: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// What to include in an export.
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    /// Include document and message vectors, which lets an import into a
+    /// database using the same model skip embedding
+    pub include_vectors: bool,
+    /// Only export the documents of this source, without messages, accounts
+    /// or channels
+    pub source_id: Option<String>,
+}
+
+/// Number of rows written by an export or read by an import.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BackupSummary {
+    pub accounts: usize,
+    pub channels: usize,
+    pub documents: usize,
+    pub messages: usize,
+    /// Documents and messages imported without a usable vector, which were
+    /// embedded with the current model or queued for embedding
+    pub reembedded: usize,
+}
+
+/// Encodes a vector the way sqlite-vec stores `float[N]` columns.
+pub(crate) fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub(crate) fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use rig::{
+    completion::{CompletionModel, ModelChoice},
+    embeddings::EmbeddingModel,
+    vector_store::{VectorStoreError, VectorStoreIndex},
+};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use super::search::DocumentIndex;
+use crate::loaders::Tokenizer;
+
+#[derive(Clone, Debug)]
+pub struct ContextConfig {
+    /// Documents less similar to the query than this are never injected,
+    /// unless they match its names or all of its other non-stop words in
+    /// keyword or hybrid retrieval
+    pub min_similarity: f64,
+    /// How many documents are retrieved before filtering and reranking
+    pub candidates: usize,
+    /// Upper bound on the tokens of all injected documents
+    pub token_budget: usize,
+    /// Counts tokens towards the budget, that of the completion model
+    pub tokenizer: Tokenizer,
+    /// Word overlap (Jaccard) above which two chunks count as duplicates
+    pub duplicate_threshold: f64,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            min_similarity: 0.25,
+            candidates: 12,
+            token_budget: 800,
+            tokenizer: Tokenizer::cl100k(),
+            duplicate_threshold: 0.85,
+        }
+    }
+}
+
+/// Scores how relevant each document is to a query, higher being more
+/// relevant. Implementations may wrap a cross-encoder or an LLM.
+pub trait Reranker: Send + Sync {
+    fn rerank<'a>(
+        &'a self,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<f64>>>;
+}
+
+/// Reranks by asking a completion model to rate every candidate in one call.
+#[derive(Clone)]
+pub struct LlmReranker<M: CompletionModel> {
+    completion_model: M,
+}
+
+impl<M: CompletionModel> LlmReranker<M> {
+    pub fn new(completion_model: M) -> Self {
+        Self { completion_model }
+    }
+}
+
+impl<M: CompletionModel> Reranker for LlmReranker<M> {
+    fn rerank<'a>(
+        &'a self,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<f64>>> {
+        Box::pin(async move {
+            let prompt = format!(
+                "Rate how useful each passage is for answering the message, from 0 (unrelated) to 10 (directly relevant).\n\n\
+                Message: {}\n\n\
+                Passages:\n{}\n\n\
+                Respond with only a JSON array of {} numbers, one per passage, in order:",
+                query,
+                documents
+                    .iter()
+                    .enumerate()
+                    .map(|(i, doc)| format!("[{}] {}", i, doc))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                documents.len()
+            );
+
+            let request = self.completion_model.completion_request(&prompt).build();
+            let response = self.completion_model.completion(request).await?;
+
+            let text = match response.choice {
+                ModelChoice::Message(text) => text,
+                ModelChoice::ToolCall(name, _) => {
+                    anyhow::bail!("Reranker returned a tool call to {}", name)
+                }
+            };
+
+            let scores = parse_scores(&text)?;
+            if scores.len() != documents.len() {
+                anyhow::bail!(
+                    "Reranker returned {} scores for {} passages",
+                    scores.len(),
+                    documents.len()
+                );
+            }
+            Ok(scores)
+        })
+    }
+}
+
+/// Wraps a [`DocumentIndex`] so dynamic context only contains documents that
+/// are actually relevant: candidates below [`ContextConfig::min_similarity`]
+/// that don't match the query's keywords are dropped, the rest are optionally
+/// reranked, near-duplicates removed and documents added until the token
+/// budget is spent. The `n` passed by the agent is an upper bound rather than
+/// a fixed count.
+pub struct ContextIndex<E: EmbeddingModel + 'static> {
+    index: DocumentIndex<E>,
+    reranker: Option<Arc<dyn Reranker>>,
+    config: ContextConfig,
+}
+
+impl<E: EmbeddingModel + 'static> ContextIndex<E> {
+    pub fn new(
+        index: DocumentIndex<E>,
+        reranker: Option<Arc<dyn Reranker>>,
+        config: ContextConfig,
+    ) -> Self {
+        Self {
+            index,
+            reranker,
+            config,
+        }
+    }
+
+    async fn select(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, serde_json::Value)>, VectorStoreError> {
+        let candidates = self
+            .index
+            .top_n_with_similarity(query, self.config.candidates.max(n))
+            .await?;
+        // An exact name the embedding doesn't capture is still relevant
+        let keyword_matches = self
+            .index
+            .keyword_matches(query, candidates.iter().map(|(_, id, _)| id.clone()).collect())
+            .await?;
+        let mut candidates: Vec<(f64, String, serde_json::Value)> = candidates
+            .into_iter()
+            .filter(|(similarity, id, _)| {
+                *similarity >= self.config.min_similarity || keyword_matches.contains(id)
+            })
+            .collect();
+
+        if let Some(reranker) = &self.reranker {
+            if candidates.len() > 1 {
+                let contents: Vec<String> = candidates.iter().map(|(_, _, doc)| content(doc)).collect();
+                match reranker.rerank(query, &contents).await {
+                    Ok(scores) => {
+                        for (candidate, score) in candidates.iter_mut().zip(scores) {
+                            candidate.0 = score;
+                        }
+                        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+                    }
+                    Err(err) => warn!(?err, "Reranking failed, keeping retrieval order"),
+                }
+            }
+        }
+
+        let mut selected: Vec<(f64, String, serde_json::Value)> = Vec::new();
+        let mut selected_words: Vec<HashSet<String>> = Vec::new();
+        let mut tokens = 0;
+
+        for (score, id, doc) in candidates {
+            if selected.len() >= n {
+                break;
+            }
+
+            let text = content(&doc);
+            let words = word_set(&text);
+            if selected_words
+                .iter()
+                .any(|other| jaccard(&words, other) >= self.config.duplicate_threshold)
+            {
+                debug!(id, "Skipping near-duplicate context document");
+                continue;
+            }
+
+            let cost = self.config.tokenizer.count(&text);
+            if tokens + cost > self.config.token_budget {
+                // A smaller document further down may still fit
+                continue;
+            }
+
+            tokens += cost;
+            selected_words.push(words);
+            selected.push((score, id, doc));
+        }
+
+        debug!(
+            documents = selected.len(),
+            tokens, "Selected dynamic context documents"
+        );
+        Ok(selected)
+    }
+}
+
+impl<E: EmbeddingModel + Sync + 'static> VectorStoreIndex for ContextIndex<E> {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.select(query, n)
+            .await?
+            .into_iter()
+            .map(|(score, id, doc)| Ok((score, id, serde_json::from_value(doc)?)))
+            .collect()
+    }
+
+    async fn top_n_ids(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        Ok(self
+            .select(query, n)
+            .await?
+            .into_iter()
+            .map(|(score, id, _)| (score, id))
+            .collect())
+    }
+}
+
+fn content(document: &serde_json::Value) -> String {
+    document["content"].as_str().unwrap_or_default().to_string()
+}
+
+fn word_set(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / a.union(b).count() as f64
+}
+
+/// Extracts the first JSON array of numbers from a model reply, which may be
+/// wrapped in prose or a code fence.
+fn parse_scores(text: &str) -> anyhow::Result<Vec<f64>> {
+    let start = text
+        .find('[')
+        .ok_or_else(|| anyhow::anyhow!("No scores in reranker reply: {}", text))?;
+    let end = text[start..]
+        .find(']')
+        .map(|end| start + end + 1)
+        .ok_or_else(|| anyhow::anyhow!("Unterminated scores in reranker reply: {}", text))?;
+    Ok(serde_json::from_str(&text[start..end])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashingEmbeddingModel;
+    use crate::knowledge::testing::test_knowledge_base;
+    use crate::knowledge::{Document, KnowledgeBase, RetrievalMode, DIALOGUE_SOURCE};
+
+    async fn knowledge(documents: &[(&str, &str)]) -> KnowledgeBase<HashingEmbeddingModel> {
+        let mut knowledge = test_knowledge_base(64).await;
+        knowledge
+            .add_documents(
+                documents
+                    .iter()
+                    .map(|(id, content)| Document {
+                        id: id.to_string(),
+                        source_id: DIALOGUE_SOURCE.to_string(),
+                        content: content.to_string(),
+                        created_at: chrono::Utc::now(),
+                        tags: vec![],
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .await
+            .unwrap();
+        knowledge
+    }
+
+    fn ids(selected: Vec<(f64, String, serde_json::Value)>) -> Vec<String> {
+        let mut ids: Vec<String> = selected.into_iter().map(|(_, id, _)| id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_parse_scores_from_fenced_reply() {
+        let scores = parse_scores("```json\n[7, 0.5, 10]\n```").unwrap();
+        assert_eq!(scores, vec![7.0, 0.5, 10.0]);
+        assert!(parse_scores("I can't rate these").is_err());
+    }
+
+    #[test]
+    fn test_jaccard_detects_near_duplicates() {
+        let a = word_set("Gi-hun: I'm not a horse. I'm a person.");
+        let b = word_set("Gi-hun: I'm not a horse, I'm a person!");
+        let c = word_set("Sang-woo: We have to win.");
+        assert_eq!(jaccard(&a, &b), 1.0);
+        assert!(jaccard(&a, &c) < 0.2);
+    }
+
+    #[tokio::test]
+    async fn test_select_keeps_keyword_matches_below_threshold() {
+        let knowledge = knowledge(&[
+            ("sae-byeok", "Sae-byeok: I don't trust anyone here."),
+            ("marbles", "Il-nam: We're friends, aren't we?"),
+        ])
+        .await;
+        let config = ContextConfig {
+            min_similarity: 0.99,
+            ..Default::default()
+        };
+        let context = |mode| {
+            ContextIndex::new(
+                knowledge.clone().with_retrieval_mode(mode).document_index(),
+                None,
+                config.clone(),
+            )
+        };
+
+        let query = "Where did Sae-byeok go?";
+        assert!(context(RetrievalMode::Vector).select(query, 5).await.unwrap().is_empty());
+        assert_eq!(ids(context(RetrievalMode::Hybrid).select(query, 5).await.unwrap()), ["sae-byeok"]);
+        assert_eq!(ids(context(RetrievalMode::Keyword).select(query, 5).await.unwrap()), ["sae-byeok"]);
+    }
+
+    #[tokio::test]
+    async fn test_select_drops_documents_sharing_only_stop_words() {
+        let knowledge = knowledge(&[
+            ("games", "Gi-hun: I think the games are rigged."),
+            ("off-topic", "Sang-woo: You do what you have to do."),
+        ])
+        .await;
+        let context = ContextIndex::new(
+            knowledge.with_retrieval_mode(RetrievalMode::Keyword).document_index(),
+            None,
+            ContextConfig {
+                min_similarity: 0.99,
+                ..Default::default()
+            },
+        );
+
+        let query = "What do you think about the games?";
+        assert_eq!(ids(context.select(query, 5).await.unwrap()), ["games"]);
+    }
+
+    #[tokio::test]
+    async fn test_select_skips_duplicates_and_respects_token_budget() {
+        let knowledge = knowledge(&[
+            ("horse-1", "Gi-hun: I'm not a horse. I'm a person."),
+            ("horse-2", "Gi-hun: I'm not a horse, I'm a person!"),
+            ("long", &"Gi-hun remembers every game he played, ".repeat(20)),
+        ])
+        .await;
+        let context = |token_budget| {
+            ContextIndex::new(
+                knowledge.clone().with_retrieval_mode(RetrievalMode::Keyword).document_index(),
+                None,
+                ContextConfig {
+                    min_similarity: 0.0,
+                    token_budget,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let selected = ids(context(800).select("Gi-hun", 5).await.unwrap());
+        assert_eq!(selected.len(), 2);
+        assert!(selected.contains(&"long".to_string()));
+
+        // The long document doesn't fit, a near-duplicate is still skipped
+        let selected = ids(context(20).select("Gi-hun", 5).await.unwrap());
+        assert_eq!(selected.len(), 1);
+        assert!(selected[0].starts_with("horse-"));
+
+        assert_eq!(context(800).select("Gi-hun", 1).await.unwrap().len(), 1);
+    }
+}
@@ -2,8 +2,25 @@ mod types;
 mod store;
 mod models;
 mod error;
+mod search;
+mod context;
+mod worker;
+mod backup;
+mod retention;
+#[cfg(test)]
+pub(crate) mod testing;
 
-pub use types::{Source, ChannelType, MessageMetadata, MessageContent};
-pub use store::KnowledgeBase;
-pub use models::{Document, Message, Account, Channel, Conversation};
-pub use error::ConversionError; 
\ No newline at end of file
+pub use types::{Source, ChannelType, EngagementMode, MessageMetadata, MessageContent};
+pub use store::{stored_embedding_model, KnowledgeBase, DIALOGUE_SOURCE, DOCS_SOURCE, TRANSCRIPT_SOURCE, WEB_SOURCE};
+pub use search::{DocumentFilter, DocumentIndex, RetrievalMode};
+pub use models::{Document, Message, Account, AttentionLog, Channel, ChannelAttention, EngagementPolicy, Conversation, KnowledgeStats};
+pub use error::ConversionError;
+pub use backup::{
+    AccountRecord, BackupSummary, ChannelRecord, ExportHeader, ExportOptions, ExportRecord,
+    EXPORT_FORMAT_VERSION,
+};
+pub use retention::{
+    parse_age, ForgetSummary, RetentionPolicy, RetentionRule, RetentionWorker, RetentionWorkerConfig,
+};
+pub use worker::{EmbeddingWorker, EmbeddingWorkerConfig};
+pub use context::{ContextConfig, ContextIndex, LlmReranker, Reranker}; 
\ No newline at end of file
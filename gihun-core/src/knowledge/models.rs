@@ -1,15 +1,120 @@
-use super::types::{ChannelType, Source};
+use super::types::{ChannelType, EngagementMode, Source};
 use rig_sqlite::{Column, ColumnValue, SqliteVectorStoreTable};
 use rig::Embed;
 use rusqlite::Row;
 
-#[derive(Embed, Clone, Debug)]
+#[derive(Embed, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Document {
     pub id: String,
     pub source_id: String,
     #[embed]
     pub content: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub tags: Vec<String>,
+}
+
+/// Summary of what a knowledge base holds.
+#[derive(Clone, Debug, Default)]
+pub struct KnowledgeStats {
+    /// Number of documents per `source_id`
+    pub documents: Vec<(String, usize)>,
+    /// Number of messages per platform
+    pub messages: Vec<(String, usize)>,
+    pub pending_embeddings: i64,
+    pub embedding_model: String,
+    pub embedding_dims: usize,
+    /// Size of the database file, or of the in-memory database
+    pub size_bytes: u64,
+}
+
+/// Whether the bot may speak in a channel, kept across restarts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelAttention {
+    /// Set when the bot was told to stop; it stays quiet until then unless
+    /// addressed by name
+    pub muted_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Messages seen since the bot last replied, `None` if it never has
+    pub messages_since_reply: Option<i64>,
+    pub last_replied_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Who the bot may reply to in a channel, or in every channel of a source.
+/// Checked before attention decides.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EngagementPolicy {
+    #[serde(default)]
+    pub mode: EngagementMode,
+    /// When not empty, only these users are replied to
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+    /// Users never replied to
+    #[serde(default)]
+    pub blocked_users: Vec<String>,
+}
+
+impl EngagementPolicy {
+    /// Why nothing `author_id` writes may be engaged with: the author is
+    /// blocked or missing from the allowlist, or the mode is `never`.
+    pub fn excludes(&self, author_id: &str) -> Option<&'static str> {
+        if self.blocked_users.iter().any(|user| user == author_id) {
+            Some("author is blocked")
+        } else if !self.allowed_users.is_empty()
+            && !self.allowed_users.iter().any(|user| user == author_id)
+        {
+            Some("author is not on the allowlist")
+        } else if self.mode == EngagementMode::Never {
+            Some("channel policy is never")
+        } else {
+            None
+        }
+    }
+
+    /// This source-wide policy narrowed by a `channel` policy, which sets the
+    /// mode and allowlist when it has them. Blocked users of both apply.
+    pub fn merge(&self, channel: &EngagementPolicy) -> EngagementPolicy {
+        let mut blocked_users = self.blocked_users.clone();
+        for user in &channel.blocked_users {
+            if !blocked_users.contains(user) {
+                blocked_users.push(user.clone());
+            }
+        }
+
+        EngagementPolicy {
+            mode: if channel.mode == EngagementMode::Default {
+                self.mode
+            } else {
+                channel.mode
+            },
+            allowed_users: if channel.allowed_users.is_empty() {
+                self.allowed_users.clone()
+            } else {
+                channel.allowed_users.clone()
+            },
+            blocked_users,
+        }
+    }
+}
+
+/// A decision taken by [`Attention`](crate::attention::Attention), logged so
+/// thresholds can be tuned from what the bot actually did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttentionLog {
+    pub source: Source,
+    pub channel_id: String,
+    pub message_id: String,
+    /// `reply` for whether to answer a message, `engagement` for how to
+    /// engage with a tweet
+    pub kind: String,
+    /// `respond`, `ignore` or `stop` for replies; the actions taken, e.g.
+    /// `like,quote`, or `ignore` for engagement
+    pub command: String,
+    /// Relevance or confidence given by the classifier, `None` when a rule
+    /// decided without asking it
+    pub score: Option<f32>,
+    pub reason: String,
+    /// Classifier model the decision was made with
+    pub model: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -30,7 +135,7 @@ pub struct Conversation {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Embed, Clone, Debug, serde::Deserialize)]
+#[derive(Embed, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Message {
     pub id: String,
     pub source: Source,
@@ -65,6 +170,7 @@ impl SqliteVectorStoreTable for Document {
             Column::new("source_id", "TEXT").indexed(),
             Column::new("content", "TEXT"),
             Column::new("created_at", "TIMESTAMP DEFAULT CURRENT_TIMESTAMP"),
+            Column::new("tags", "TEXT DEFAULT '[]'"),
         ]
     }
 
@@ -78,6 +184,10 @@ impl SqliteVectorStoreTable for Document {
             ("source_id", Box::new(self.source_id.clone())),
             ("content", Box::new(self.content.clone())),
             ("created_at", Box::new(self.created_at.to_rfc3339())),
+            (
+                "tags",
+                Box::new(serde_json::to_string(&self.tags).unwrap_or_default()),
+            ),
         ]
     }
 }
@@ -132,6 +242,9 @@ impl TryFrom<&Row<'_>> for Document {
             source_id: row.get(1)?,
             content: row.get(2)?,
             created_at: row.get(3)?,
+            tags: serde_json::from_str(&row.get::<_, String>(4)?).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e))
+            })?,
         })
     }
 }
@@ -164,6 +277,30 @@ impl TryFrom<&Row<'_>> for Conversation {
     }
 }
 
+impl TryFrom<&Row<'_>> for AttentionLog {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(AttentionLog {
+            source: Source::from_str(&row.get::<_, String>(0)?).ok_or(
+                rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    rusqlite::types::Type::Text,
+                    Box::new(super::error::ConversionError("Invalid source".to_string())),
+                ),
+            )?,
+            channel_id: row.get(1)?,
+            message_id: row.get(2)?,
+            kind: row.get(3)?,
+            command: row.get(4)?,
+            score: row.get(5)?,
+            reason: row.get(6)?,
+            model: row.get(7)?,
+            created_at: row.get(8)?,
+        })
+    }
+}
+
 impl TryFrom<&Row<'_>> for Message {
     type Error = rusqlite::Error;
 
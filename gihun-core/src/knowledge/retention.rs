@@ -0,0 +1,210 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use rig::embeddings::EmbeddingModel;
+use tracing::{debug, error, info};
+
+use super::store::KnowledgeBase;
+use super::types::{ChannelType, Source};
+
+/// How long messages of a source, optionally narrowed to a channel type, are
+/// kept.
+///
+/// Parsed from `<source>[:<channel type>]=<age>`, where the source may be
+/// `*` for every platform and the age is a number of hours, days or weeks,
+/// e.g. `discord:direct_message=7d` or `*=52w`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetentionRule {
+    /// `None` for every platform
+    pub source: Option<Source>,
+    /// `None` for every channel type
+    pub channel_type: Option<ChannelType>,
+    pub max_age: chrono::Duration,
+}
+
+impl RetentionRule {
+    fn matches(&self, source: &Source, channel_type: &ChannelType) -> bool {
+        self.source.as_ref().is_none_or(|s| s == source)
+            && self.channel_type.as_ref().is_none_or(|c| c == channel_type)
+    }
+
+    /// Rules naming a source win over rules naming only a channel type,
+    /// which win over catch-all rules.
+    fn specificity(&self) -> u8 {
+        2 * self.source.is_some() as u8 + self.channel_type.is_some() as u8
+    }
+}
+
+impl FromStr for RetentionRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scope, age) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <source>[:<channel type>]=<age>, got {:?}", s))?;
+        let (source, channel_type) = match scope.split_once(':') {
+            Some((source, channel_type)) => (source.trim(), Some(channel_type.trim())),
+            None => (scope.trim(), None),
+        };
+
+        let source = match source {
+            "*" => None,
+            source => Some(Source::from_str(source).ok_or_else(|| format!("Unknown source {:?}", source))?),
+        };
+        let channel_type = channel_type
+            .map(|channel_type| {
+                ChannelType::from_str(channel_type)
+                    .ok_or_else(|| format!("Unknown channel type {:?}", channel_type))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            source,
+            channel_type,
+            max_age: parse_age(age.trim())?,
+        })
+    }
+}
+
+/// Parses `12h`, `30d` or `4w`.
+pub fn parse_age(age: &str) -> Result<chrono::Duration, String> {
+    let unit_at = age
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("Age {:?} is missing a unit (h, d or w)", age))?;
+    let (count, unit) = age.split_at(unit_at);
+    let count: i64 = count
+        .parse()
+        .map_err(|_| format!("Invalid age {:?}", age))?;
+
+    let max_age = match unit {
+        "h" => chrono::Duration::try_hours(count),
+        "d" => chrono::Duration::try_days(count),
+        "w" => chrono::Duration::try_weeks(count),
+        _ => return Err(format!("Unknown unit {:?} in age {:?}, expected h, d or w", unit, age)),
+    };
+    max_age.ok_or_else(|| format!("Age {:?} is too long", age))
+}
+
+/// Retention windows for stored messages. Messages no rule matches are kept
+/// forever.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    pub rules: Vec<RetentionRule>,
+    /// How long logged attention decisions are kept, forever when `None`.
+    /// Decisions about a message also go when the message does.
+    pub attention_log_max_age: Option<chrono::Duration>,
+}
+
+impl RetentionPolicy {
+    pub fn new(rules: Vec<RetentionRule>) -> Self {
+        Self {
+            rules,
+            attention_log_max_age: None,
+        }
+    }
+
+    pub fn with_attention_log_max_age(mut self, max_age: chrono::Duration) -> Self {
+        self.attention_log_max_age = Some(max_age);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.attention_log_max_age.is_none()
+    }
+
+    /// How long messages of `source` in channels of `channel_type` are kept.
+    /// The most specific matching rule applies, and the last one among equally
+    /// specific rules.
+    pub fn max_age(&self, source: &Source, channel_type: &ChannelType) -> Option<chrono::Duration> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(source, channel_type))
+            .max_by_key(|rule| rule.specificity())
+            .map(|rule| rule.max_age)
+    }
+}
+
+/// What [`KnowledgeBase::forget_user`] deleted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForgetSummary {
+    pub messages: usize,
+    pub accounts: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetentionWorkerConfig {
+    pub policy: RetentionPolicy,
+    /// Time between purges
+    pub interval: Duration,
+}
+
+/// Background task that periodically deletes messages older than the
+/// [`RetentionPolicy`] allows, along with their vectors.
+pub struct RetentionWorker<E: EmbeddingModel + 'static> {
+    knowledge: KnowledgeBase<E>,
+    config: RetentionWorkerConfig,
+}
+
+impl<E: EmbeddingModel + 'static> RetentionWorker<E> {
+    pub fn new(knowledge: KnowledgeBase<E>, config: RetentionWorkerConfig) -> Self {
+        Self { knowledge, config }
+    }
+
+    pub async fn start(&self) {
+        info!(
+            rules = self.config.policy.rules.len(),
+            "Starting message retention worker"
+        );
+
+        loop {
+            match self
+                .knowledge
+                .purge_expired_messages(&self.config.policy, chrono::Utc::now())
+                .await
+            {
+                Ok(0) => debug!("No expired messages"),
+                Ok(count) => info!(count, "Purged expired messages"),
+                Err(err) => error!(?err, "Failed to purge expired messages"),
+            }
+            tokio::time::sleep(self.config.interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_parsing_and_precedence() {
+        let rule: RetentionRule = "discord:direct_message=7d".parse().unwrap();
+        assert_eq!(
+            rule,
+            RetentionRule {
+                source: Some(Source::Discord),
+                channel_type: Some(ChannelType::DirectMessage),
+                max_age: chrono::Duration::days(7),
+            }
+        );
+        assert_eq!("*=2w".parse::<RetentionRule>().unwrap().max_age, chrono::Duration::weeks(2));
+        assert!("discord=7".parse::<RetentionRule>().is_err());
+        assert!("myspace=7d".parse::<RetentionRule>().is_err());
+        assert!("discord:forum=7d".parse::<RetentionRule>().is_err());
+        assert!("*=99999999999999w".parse::<RetentionRule>().is_err());
+        assert!("*=9223372036854775807h".parse::<RetentionRule>().is_err());
+
+        let policy = RetentionPolicy::new(
+            ["*=52w", "*:direct_message=30d", "telegram=12h", "discord:direct_message=7d"]
+                .iter()
+                .map(|rule| rule.parse().unwrap())
+                .collect(),
+        );
+        let hours = |source, channel_type| policy.max_age(&source, &channel_type).map(|age| age.num_hours());
+
+        assert_eq!(hours(Source::Discord, ChannelType::DirectMessage), Some(7 * 24));
+        assert_eq!(hours(Source::Discord, ChannelType::Text), Some(52 * 7 * 24));
+        assert_eq!(hours(Source::Twitter, ChannelType::DirectMessage), Some(30 * 24));
+        assert_eq!(hours(Source::Telegram, ChannelType::DirectMessage), Some(12));
+        assert_eq!(RetentionPolicy::default().max_age(&Source::X, &ChannelType::Text), None);
+    }
+}
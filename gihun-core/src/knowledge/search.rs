@@ -0,0 +1,593 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use rig::{
+    embeddings::EmbeddingModel,
+    vector_store::{VectorStoreError, VectorStoreIndex},
+};
+use rusqlite::types::Value;
+use serde::Deserialize;
+use tokio_rusqlite::Connection;
+use tracing::debug;
+
+/// Constant from the original reciprocal rank fusion paper; dampens the
+/// influence of the very top ranks so both result lists get a say.
+const RRF_K: f64 = 60.0;
+
+/// How many candidates each retriever contributes per requested result when
+/// fusing.
+const HYBRID_CANDIDATE_FACTOR: usize = 3;
+
+/// Words too common to say anything about what a message is about.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "all", "am", "an", "and", "any", "are", "as", "at", "be", "been", "but", "by",
+    "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he", "her", "him",
+    "his", "how", "i", "if", "in", "is", "it", "its", "just", "me", "my", "no", "not", "of", "on",
+    "or", "our", "she", "so", "that", "the", "their", "them", "then", "there", "they", "this",
+    "to", "us", "was", "we", "were", "what", "when", "where", "which", "who", "why", "will",
+    "with", "would", "you", "your",
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RetrievalMode {
+    /// Embedding similarity only
+    Vector,
+    /// SQLite FTS5 BM25 keyword search only
+    Keyword,
+    /// Both, fused with reciprocal rank fusion
+    #[default]
+    Hybrid,
+}
+
+/// Restricts which documents an index can return. Empty fields don't filter.
+#[derive(Clone, Debug, Default)]
+pub struct DocumentFilter {
+    /// Only documents from one of these sources
+    pub source_ids: Vec<String>,
+    /// Never documents from these sources
+    pub exclude_source_ids: Vec<String>,
+    /// Only documents carrying at least one of these tags
+    pub tags: Vec<String>,
+    /// Only documents created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only documents created before this time
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl DocumentFilter {
+    pub fn source(source_id: impl Into<String>) -> Self {
+        Self::default().with_source(source_id)
+    }
+
+    pub fn with_source(mut self, source_id: impl Into<String>) -> Self {
+        self.source_ids.push(source_id.into());
+        self
+    }
+
+    pub fn without_source(mut self, source_id: impl Into<String>) -> Self {
+        self.exclude_source_ids.push(source_id.into());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source_ids.is_empty()
+            && self.exclude_source_ids.is_empty()
+            && self.tags.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    /// Builds `AND ...` conditions on the `documents` table aliased as `d`,
+    /// numbering placeholders from `first_param`.
+    fn to_sql(&self, first_param: usize) -> (String, Vec<Value>) {
+        let mut sql = String::new();
+        let mut params: Vec<Value> = Vec::new();
+
+        let placeholders = |values: &[String], params: &mut Vec<Value>| {
+            values
+                .iter()
+                .map(|value| {
+                    params.push(Value::Text(value.clone()));
+                    format!("?{}", first_param + params.len() - 1)
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        if !self.source_ids.is_empty() {
+            let list = placeholders(&self.source_ids, &mut params);
+            sql.push_str(&format!(" AND d.source_id IN ({})", list));
+        }
+        if !self.exclude_source_ids.is_empty() {
+            let list = placeholders(&self.exclude_source_ids, &mut params);
+            sql.push_str(&format!(" AND d.source_id NOT IN ({})", list));
+        }
+        if !self.tags.is_empty() {
+            let list = placeholders(&self.tags, &mut params);
+            sql.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM json_each(d.tags) WHERE json_each.value IN ({}))",
+                list
+            ));
+        }
+        if let Some(since) = self.since {
+            let param = placeholders(&[since.to_rfc3339()], &mut params);
+            sql.push_str(&format!(" AND julianday(d.created_at) >= julianday({})", param));
+        }
+        if let Some(until) = self.until {
+            let param = placeholders(&[until.to_rfc3339()], &mut params);
+            sql.push_str(&format!(" AND julianday(d.created_at) < julianday({})", param));
+        }
+
+        (sql, params)
+    }
+}
+
+/// Document index combining the `sqlite-vec` similarity search with the FTS5
+/// keyword index over `documents`, so exact names like "Sae-byeok" are found
+/// even when the embedding doesn't rank them highly.
+///
+/// Scores depend on the mode: vector search returns the embedding distance
+/// (lower is closer), keyword search the negated BM25 rank and hybrid the fused
+/// RRF score (higher is better for both). Use [`Self::top_n_with_similarity`]
+/// for a score that is comparable across modes.
+pub struct DocumentIndex<E: EmbeddingModel + 'static> {
+    conn: Connection,
+    embedding_model: E,
+    mode: RetrievalMode,
+    filter: DocumentFilter,
+}
+
+impl<E: EmbeddingModel + 'static> DocumentIndex<E> {
+    pub(crate) fn new(
+        conn: Connection,
+        embedding_model: E,
+        mode: RetrievalMode,
+        filter: DocumentFilter,
+    ) -> Self {
+        Self {
+            conn,
+            embedding_model,
+            mode,
+            filter,
+        }
+    }
+
+    /// Retrieves documents like [`VectorStoreIndex::top_n`], in the same
+    /// order, but scored by the cosine similarity between the query and each
+    /// document's embedding.
+    pub async fn top_n_with_similarity(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, serde_json::Value)>, VectorStoreError> {
+        let query_vec = self.embed_query(query).await?;
+        let documents = self.retrieve(query, Some(&query_vec), n).await?;
+
+        let ids: Vec<String> = documents.iter().map(|(_, id, _)| id.clone()).collect();
+        let similarities = self.similarities(query_vec, ids).await?;
+
+        Ok(documents
+            .into_iter()
+            .map(|(_, id, doc)| {
+                let similarity = similarities.get(&id).copied().unwrap_or_default();
+                (similarity, id, doc)
+            })
+            .collect())
+    }
+
+    /// Which of `ids` match the query's keywords strongly enough to count as
+    /// relevant on that alone, see [`strong_fts_query`]. Always empty in
+    /// [`RetrievalMode::Vector`], where keywords play no part in retrieval.
+    pub async fn keyword_matches(
+        &self,
+        query: &str,
+        ids: Vec<String>,
+    ) -> Result<HashSet<String>, VectorStoreError> {
+        let Some(fts_query) = strong_fts_query(query) else {
+            return Ok(HashSet::new());
+        };
+        if self.mode == RetrievalMode::Vector || ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        self.conn
+            .call(move |conn| {
+                let placeholders = (2..ids.len() + 2)
+                    .map(|i| format!("?{}", i))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT d.id
+                     FROM documents_fts
+                     JOIN documents d ON d.rowid = documents_fts.rowid
+                     WHERE documents_fts MATCH ?1 AND d.id IN ({})",
+                    placeholders
+                ))?;
+
+                let params = std::iter::once(Value::Text(fts_query))
+                    .chain(ids.into_iter().map(Value::Text));
+
+                let matches = stmt
+                    .query_map(rusqlite::params_from_iter(params), |row| row.get(0))?
+                    .collect::<Result<HashSet<String>, _>>()?;
+                Ok(matches)
+            })
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Vec<u8>, VectorStoreError> {
+        let embedding = self.embedding_model.embed_text(query).await?;
+        Ok(embedding
+            .vec
+            .iter()
+            .flat_map(|x| (*x as f32).to_le_bytes())
+            .collect())
+    }
+
+    async fn retrieve(
+        &self,
+        query: &str,
+        query_vec: Option<&[u8]>,
+        n: usize,
+    ) -> Result<Vec<(f64, String, serde_json::Value)>, VectorStoreError> {
+        let query_vec = match (self.mode, query_vec) {
+            (RetrievalMode::Keyword, _) => return self.keyword_top_n(query, n).await,
+            (_, Some(query_vec)) => query_vec.to_vec(),
+            (_, None) => self.embed_query(query).await?,
+        };
+
+        match self.mode {
+            RetrievalMode::Vector => self.vector_top_n(query_vec, n).await,
+            _ => self.hybrid_top_n(query, query_vec, n).await,
+        }
+    }
+
+    async fn vector_top_n(
+        &self,
+        query_vec: Vec<u8>,
+        n: usize,
+    ) -> Result<Vec<(f64, String, serde_json::Value)>, VectorStoreError> {
+        let (filter_sql, filter_params) = self.filter.to_sql(3);
+
+        // The vec0 KNN query can't be combined with column filters, so a
+        // filtered search computes distances over the matching documents only
+        let sql = if self.filter.is_empty() {
+            "SELECT d.id, d.source_id, d.content, d.created_at, d.tags, e.distance
+             FROM documents_embeddings e
+             JOIN documents d ON d.rowid = e.rowid
+             WHERE e.embedding MATCH ?1 AND k = ?2
+             ORDER BY e.distance"
+                .to_string()
+        } else {
+            format!(
+                "SELECT d.id, d.source_id, d.content, d.created_at, d.tags,
+                        vec_distance_l2(e.embedding, ?1) AS distance
+                 FROM documents d
+                 JOIN documents_embeddings e ON e.rowid = d.rowid
+                 WHERE 1 = 1{}
+                 ORDER BY distance
+                 LIMIT ?2",
+                filter_sql
+            )
+        };
+
+        self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&sql)?;
+
+                let mut params = vec![Value::Blob(query_vec), Value::Integer(n as i64)];
+                params.extend(filter_params);
+
+                let rows = stmt
+                    .query_map(rusqlite::params_from_iter(params), |row| {
+                        Ok((row.get(5)?, row.get(0)?, document_json(row)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(rows)
+            })
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))
+    }
+
+    async fn keyword_top_n(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, serde_json::Value)>, VectorStoreError> {
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        let (filter_sql, filter_params) = self.filter.to_sql(3);
+
+        self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT d.id, d.source_id, d.content, d.created_at, d.tags,
+                            bm25(documents_fts) AS rank
+                     FROM documents_fts
+                     JOIN documents d ON d.rowid = documents_fts.rowid
+                     WHERE documents_fts MATCH ?1{}
+                     ORDER BY rank
+                     LIMIT ?2",
+                    filter_sql
+                ))?;
+
+                let mut params = vec![Value::Text(fts_query), Value::Integer(n as i64)];
+                params.extend(filter_params);
+
+                let rows = stmt
+                    .query_map(rusqlite::params_from_iter(params), |row| {
+                        let rank: f64 = row.get(5)?;
+                        Ok((-rank, row.get(0)?, document_json(row)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(rows)
+            })
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))
+    }
+
+    async fn hybrid_top_n(
+        &self,
+        query: &str,
+        query_vec: Vec<u8>,
+        n: usize,
+    ) -> Result<Vec<(f64, String, serde_json::Value)>, VectorStoreError> {
+        let candidates = n * HYBRID_CANDIDATE_FACTOR;
+        let vector = self.vector_top_n(query_vec, candidates).await?;
+        let keyword = self.keyword_top_n(query, candidates).await?;
+
+        debug!(
+            vector = vector.len(),
+            keyword = keyword.len(),
+            "Fusing retrieval candidates"
+        );
+
+        let ranked = reciprocal_rank_fusion(&[
+            vector.iter().map(|(_, id, _)| id.clone()).collect(),
+            keyword.iter().map(|(_, id, _)| id.clone()).collect(),
+        ]);
+
+        let mut documents: HashMap<String, serde_json::Value> = vector
+            .into_iter()
+            .chain(keyword)
+            .map(|(_, id, document)| (id, document))
+            .collect();
+
+        Ok(ranked
+            .into_iter()
+            .take(n)
+            .filter_map(|(score, id)| documents.remove(&id).map(|doc| (score, id, doc)))
+            .collect())
+    }
+
+    /// Cosine similarity between the query and each of the given documents.
+    async fn similarities(
+        &self,
+        query_vec: Vec<u8>,
+        ids: Vec<String>,
+    ) -> Result<HashMap<String, f64>, VectorStoreError> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        self.conn
+            .call(move |conn| {
+                let placeholders = (2..ids.len() + 2)
+                    .map(|i| format!("?{}", i))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT d.id, 1.0 - vec_distance_cosine(e.embedding, ?1)
+                     FROM documents d
+                     JOIN documents_embeddings e ON e.rowid = d.rowid
+                     WHERE d.id IN ({})",
+                    placeholders
+                ))?;
+
+                let params = std::iter::once(Value::Blob(query_vec))
+                    .chain(ids.into_iter().map(Value::Text));
+
+                let similarities = stmt
+                    .query_map(rusqlite::params_from_iter(params), |row| {
+                        let similarity: Option<f64> = row.get(1)?;
+                        Ok((
+                            row.get(0)?,
+                            similarity.filter(|s| s.is_finite()).unwrap_or_default(),
+                        ))
+                    })?
+                    .collect::<Result<HashMap<_, _>, _>>()?;
+                Ok(similarities)
+            })
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))
+    }
+}
+
+impl<E: EmbeddingModel + Sync + 'static> VectorStoreIndex for DocumentIndex<E> {
+    async fn top_n<T: for<'a> Deserialize<'a> + Send>(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String, T)>, VectorStoreError> {
+        self.retrieve(query, None, n)
+            .await?
+            .into_iter()
+            .map(|(score, id, doc)| Ok((score, id, serde_json::from_value(doc)?)))
+            .collect()
+    }
+
+    async fn top_n_ids(
+        &self,
+        query: &str,
+        n: usize,
+    ) -> Result<Vec<(f64, String)>, VectorStoreError> {
+        Ok(self
+            .retrieve(query, None, n)
+            .await?
+            .into_iter()
+            .map(|(score, id, _)| (score, id))
+            .collect())
+    }
+}
+
+/// Document columns as a JSON object that deserializes into a
+/// [`Document`](super::Document), read from a row starting with
+/// `id, source_id, content, created_at, tags`. Tags are stored as a JSON array.
+fn document_json(row: &rusqlite::Row) -> rusqlite::Result<serde_json::Value> {
+    let tags: String = row.get(4)?;
+    let tags = serde_json::from_str::<serde_json::Value>(&tags).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>(0)?,
+        "source_id": row.get::<_, String>(1)?,
+        "content": row.get::<_, String>(2)?,
+        "created_at": row.get::<_, String>(3)?,
+        "tags": tags,
+    }))
+}
+
+/// Turns free text into an FTS5 query matching any of its words. Each word is
+/// quoted so punctuation in the input (hyphenated names, apostrophes) can't
+/// be parsed as query syntax.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"", term))
+        .collect();
+
+    (!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+/// Turns free text into an FTS5 query only documents about the same thing
+/// match. Stop words are dropped; if capitalised words remain past the first
+/// word they are taken as names and all of them must match, otherwise all
+/// remaining words must. Each word is quoted as a phrase, so a hyphenated
+/// name has to appear as a whole.
+fn strong_fts_query(query: &str) -> Option<String> {
+    let words: Vec<(usize, &str)> = query
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .enumerate()
+        .filter(|(_, word)| !word.is_empty() && !STOP_WORDS.contains(&word.to_lowercase().as_str()))
+        .collect();
+
+    let names: Vec<&str> = words
+        .iter()
+        .filter(|(position, word)| *position > 0 && word.starts_with(char::is_uppercase))
+        .map(|(_, word)| *word)
+        .collect();
+    let terms: Vec<&str> = if names.is_empty() {
+        words.iter().map(|(_, word)| *word).collect()
+    } else {
+        names
+    };
+
+    let phrases: Vec<String> = terms
+        .into_iter()
+        .map(|word| {
+            let tokens: Vec<&str> = word
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|token| !token.is_empty())
+                .collect();
+            format!("\"{}\"", tokens.join(" "))
+        })
+        .collect();
+
+    (!phrases.is_empty()).then(|| phrases.join(" AND "))
+}
+
+/// Fuses ranked id lists, scoring each id by the sum of `1 / (k + rank)` over
+/// the lists it appears in. Returns ids sorted by descending fused score.
+fn reciprocal_rank_fusion(lists: &[Vec<String>]) -> Vec<(f64, String)> {
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+
+    for list in lists {
+        for (rank, id) in list.iter().enumerate() {
+            *scores.entry(id).or_default() += 1.0 / (RRF_K + rank as f64 + 1.0);
+        }
+    }
+
+    let mut ranked: Vec<(f64, String)> = scores
+        .into_iter()
+        .map(|(id, score)| (score, id.to_string()))
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_fts_query_quotes_terms() {
+        assert_eq!(
+            fts_query("Where's Sae-byeok?").as_deref(),
+            Some("\"Where\" OR \"s\" OR \"Sae\" OR \"byeok\"")
+        );
+        assert_eq!(fts_query(" -- "), None);
+    }
+
+    #[test]
+    fn test_strong_fts_query_requires_names_or_all_content_words() {
+        assert_eq!(
+            strong_fts_query("Where did Sae-byeok go with Ji-yeong?").as_deref(),
+            Some("\"Sae byeok\" AND \"Ji yeong\"")
+        );
+        assert_eq!(
+            strong_fts_query("What do you think about the games?").as_deref(),
+            Some("\"think\" AND \"games\"")
+        );
+        assert_eq!(strong_fts_query("Don't you?").as_deref(), Some("\"Don t\""));
+        assert_eq!(strong_fts_query("What about you?"), None);
+    }
+
+    #[test]
+    fn test_filter_sql_numbers_placeholders() {
+        let filter = DocumentFilter::source("dialogue")
+            .with_source("s02e01.pdf")
+            .without_source("github")
+            .with_tag("lore");
+        let (sql, params) = filter.to_sql(3);
+        assert_eq!(
+            sql,
+            " AND d.source_id IN (?3, ?4) AND d.source_id NOT IN (?5) \
+             AND EXISTS (SELECT 1 FROM json_each(d.tags) WHERE json_each.value IN (?6))"
+        );
+        assert_eq!(params.len(), 4);
+        assert!(DocumentFilter::default().to_sql(1).0.is_empty());
+    }
+
+    #[test]
+    fn test_rrf_prefers_documents_in_both_lists() {
+        let ranked = reciprocal_rank_fusion(&[ids(&["a", "b", "c"]), ids(&["c", "d"])]);
+        assert_eq!(ranked[0].1, "c");
+        assert_eq!(ranked[1].1, "a");
+        assert_eq!(ranked.len(), 4);
+    }
+}
@@ -3,10 +3,20 @@ use rig::{
     vector_store::VectorStoreError,
 };
 use tokio_rusqlite::Connection;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use super::models::{Account, Channel, Document, Message};
-use rig_sqlite::{SqliteError, SqliteVectorIndex, SqliteVectorStore};
+use super::backup::{
+    from_blob, to_blob, AccountRecord, BackupSummary, ChannelRecord, ExportHeader, ExportOptions,
+    ExportRecord, EXPORT_FORMAT_VERSION,
+};
+use super::models::{
+    Account, AttentionLog, Channel, ChannelAttention, Document, EngagementPolicy, KnowledgeStats,
+    Message,
+};
+use super::retention::{ForgetSummary, RetentionPolicy};
+use super::search::{DocumentFilter, DocumentIndex, RetrievalMode};
+use super::types::{ChannelType, EngagementMode, Source};
+use rig_sqlite::{SqliteError, SqliteVectorIndex, SqliteVectorStore, SqliteVectorStoreTable};
 use rusqlite::OptionalExtension;
 
 #[derive(Clone)]
@@ -15,10 +25,71 @@ pub struct KnowledgeBase<E: EmbeddingModel + Clone + 'static> {
     document_store: SqliteVectorStore<E, Document>,
     message_store: SqliteVectorStore<E, Message>,
     embedding_model: E,
+    retrieval_mode: RetrievalMode,
 }
 
 impl<E: EmbeddingModel> KnowledgeBase<E> {
-    pub async fn new(conn: Connection, embedding_model: E) -> Result<Self, VectorStoreError> {
+    /// Opens the knowledge base, creating any missing tables.
+    ///
+    /// `model_name` and the model's dimension are recorded in the database. If
+    /// they differ from what the stored vectors were built with, the vector
+    /// tables are rebuilt: documents are re-embedded immediately and messages
+    /// are queued for the [`EmbeddingWorker`](super::EmbeddingWorker).
+    pub async fn new(
+        conn: Connection,
+        embedding_model: E,
+        model_name: &str,
+    ) -> Result<Self, VectorStoreError> {
+        let model_name = model_name.to_string();
+        let ndims = embedding_model.ndims();
+
+        let reembed = conn
+            .call(move |conn| {
+                conn.execute_batch(
+                    "CREATE TABLE IF NOT EXISTS kb_metadata (
+                        key TEXT PRIMARY KEY,
+                        value TEXT NOT NULL
+                    );",
+                )?;
+
+                let stored_name = get_metadata(conn, "embedding_model")?;
+                let mut stored_dims = get_metadata(conn, "embedding_dims")?;
+
+                let changed = match (&stored_name, &stored_dims) {
+                    (Some(name), Some(dims)) => *name != model_name || *dims != ndims.to_string(),
+                    // Vectors from before the model was recorded can't be
+                    // trusted to match it
+                    _ => match embedding_table_dims(conn)? {
+                        Some(dims) => {
+                            stored_dims = Some(dims.to_string());
+                            true
+                        }
+                        None => false,
+                    },
+                };
+
+                if changed {
+                    warn!(
+                        from_model = ?stored_name,
+                        from_dims = ?stored_dims,
+                        to_model = %model_name,
+                        to_dims = ndims,
+                        "Embedding model changed, rebuilding vector tables"
+                    );
+                    conn.execute_batch(
+                        "DROP TABLE IF EXISTS documents_embeddings;
+                         DROP TABLE IF EXISTS messages_embeddings;",
+                    )?;
+                }
+
+                set_metadata(conn, "embedding_model", &model_name)?;
+                set_metadata(conn, "embedding_dims", &ndims.to_string())?;
+
+                Ok(changed)
+            })
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+
         let document_store = SqliteVectorStore::new(conn.clone(), &embedding_model).await?;
         let message_store = SqliteVectorStore::new(conn.clone(), &embedding_model).await?;
 
@@ -49,6 +120,53 @@ impl<E: EmbeddingModel> KnowledgeBase<E> {
                 );
                 CREATE INDEX IF NOT EXISTS idx_channel_id_type ON channels(channel_id, channel_type);
 
+                -- Whether the bot may speak in a channel, see `Attention`
+                CREATE TABLE IF NOT EXISTS channel_attention (
+                    source TEXT NOT NULL,
+                    channel_id TEXT NOT NULL,
+                    muted_until TEXT,
+                    messages_since_reply INTEGER,
+                    last_replied_at TEXT,
+                    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (source, channel_id)
+                );
+
+                -- Who the bot may reply to, per channel or for every channel
+                -- of a source when channel_id is '*'
+                CREATE TABLE IF NOT EXISTS engagement_policies (
+                    source TEXT NOT NULL,
+                    channel_id TEXT NOT NULL,
+                    mode TEXT NOT NULL,
+                    allowed_users TEXT NOT NULL,
+                    blocked_users TEXT NOT NULL,
+                    -- 'file' for --policies, 'admin' once changed by an admin command
+                    origin TEXT NOT NULL DEFAULT 'file',
+                    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (source, channel_id)
+                );
+
+                -- Every decision `Attention` took, for tuning its thresholds
+                CREATE TABLE IF NOT EXISTS attention_decisions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    source TEXT NOT NULL,
+                    channel_id TEXT NOT NULL,
+                    message_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    command TEXT NOT NULL,
+                    score REAL,
+                    reason TEXT NOT NULL,
+                    model TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_attention_decisions_message ON attention_decisions(message_id);
+
+                -- Messages waiting for the background worker to embed them
+                CREATE TABLE IF NOT EXISTS message_embedding_queue (
+                    message_rowid INTEGER PRIMARY KEY,
+                    queued_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    attempts INTEGER NOT NULL DEFAULT 0
+                );
+
                 COMMIT;"
             )
             .map_err(tokio_rusqlite::Error::from)
@@ -56,12 +174,232 @@ impl<E: EmbeddingModel> KnowledgeBase<E> {
         .await
         .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
 
-        Ok(Self {
+        conn.call(|conn| {
+            // `INSERT OR REPLACE` only fires delete triggers with recursive
+            // triggers enabled, which the FTS index relies on to drop
+            // replaced documents
+            conn.pragma_update(None, "recursive_triggers", true)?;
+
+            // Databases created before documents were tagged lack the column
+            let has_tags: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM pragma_table_info('documents') WHERE name = 'tags')",
+                [],
+                |row| row.get(0),
+            )?;
+            if !has_tags {
+                conn.execute("ALTER TABLE documents ADD COLUMN tags TEXT DEFAULT '[]'", [])?;
+            }
+
+            // Queues created before failed embeddings were counted lack the column
+            let has_attempts: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM pragma_table_info('message_embedding_queue') WHERE name = 'attempts')",
+                [],
+                |row| row.get(0),
+            )?;
+            if !has_attempts {
+                conn.execute(
+                    "ALTER TABLE message_embedding_queue ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0",
+                    [],
+                )?;
+            }
+
+            // Policies stored before admin edits were told apart lack the column
+            let has_origin: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM pragma_table_info('engagement_policies') WHERE name = 'origin')",
+                [],
+                |row| row.get(0),
+            )?;
+            if !has_origin {
+                conn.execute(
+                    "ALTER TABLE engagement_policies ADD COLUMN origin TEXT NOT NULL DEFAULT 'file'",
+                    [],
+                )?;
+            }
+
+            let fts_exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'documents_fts')",
+                [],
+                |row| row.get(0),
+            )?;
+
+            conn.execute_batch(
+                "BEGIN;
+
+                -- Keyword index mirroring documents.content
+                CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts
+                    USING fts5(content, content='documents', content_rowid='rowid');
+
+                CREATE TRIGGER IF NOT EXISTS documents_fts_insert AFTER INSERT ON documents BEGIN
+                    INSERT INTO documents_fts(rowid, content) VALUES (new.rowid, new.content);
+                END;
+                CREATE TRIGGER IF NOT EXISTS documents_fts_delete AFTER DELETE ON documents BEGIN
+                    INSERT INTO documents_fts(documents_fts, rowid, content)
+                        VALUES ('delete', old.rowid, old.content);
+                END;
+                CREATE TRIGGER IF NOT EXISTS documents_fts_update AFTER UPDATE ON documents BEGIN
+                    INSERT INTO documents_fts(documents_fts, rowid, content)
+                        VALUES ('delete', old.rowid, old.content);
+                    INSERT INTO documents_fts(rowid, content) VALUES (new.rowid, new.content);
+                END;
+
+                COMMIT;",
+            )?;
+
+            // Index documents stored before the keyword index existed
+            if !fts_exists {
+                conn.execute("INSERT INTO documents_fts(documents_fts) VALUES ('rebuild')", [])?;
+            }
+
+            Ok(())
+        })
+        .await
+        .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+
+        let mut knowledge = Self {
             conn,
             document_store,
             message_store,
             embedding_model,
-        })
+            retrieval_mode: RetrievalMode::default(),
+        };
+
+        if reembed {
+            knowledge
+                .reembed_all()
+                .await
+                .map_err(|e| VectorStoreError::DatastoreError(e.into()))?;
+        }
+
+        Ok(knowledge)
+    }
+
+    /// Sets how [`Self::document_index`] retrieves documents.
+    pub fn with_retrieval_mode(mut self, retrieval_mode: RetrievalMode) -> Self {
+        self.retrieval_mode = retrieval_mode;
+        self
+    }
+
+    /// Name and dimension of the embedding model the stored vectors were built with.
+    pub async fn embedding_model_info(&self) -> Result<(String, usize), SqliteError> {
+        self.conn
+            .call(|conn| {
+                let name = get_metadata(conn, "embedding_model")?.unwrap_or_default();
+                let dims = get_metadata(conn, "embedding_dims")?
+                    .and_then(|dims| dims.parse().ok())
+                    .unwrap_or_default();
+                Ok((name, dims))
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Document and message counts, embedding model and database size.
+    pub async fn stats(&self) -> Result<KnowledgeStats, SqliteError> {
+        self.conn
+            .call(|conn| {
+                let counts = |sql: &str| -> rusqlite::Result<Vec<(String, usize)>> {
+                    let mut stmt = conn.prepare(sql)?;
+                    let rows = stmt
+                        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                        .collect();
+                    rows
+                };
+
+                Ok(KnowledgeStats {
+                    documents: counts(
+                        "SELECT source_id, COUNT(*) FROM documents GROUP BY source_id ORDER BY source_id",
+                    )?,
+                    messages: counts(
+                        "SELECT source, COUNT(*) FROM messages GROUP BY source ORDER BY source",
+                    )?,
+                    pending_embeddings: conn.query_row(
+                        "SELECT COUNT(*) FROM message_embedding_queue",
+                        [],
+                        |row| row.get(0),
+                    )?,
+                    embedding_model: get_metadata(conn, "embedding_model")?.unwrap_or_default(),
+                    embedding_dims: get_metadata(conn, "embedding_dims")?
+                        .and_then(|dims| dims.parse().ok())
+                        .unwrap_or_default(),
+                    size_bytes: conn.query_row(
+                        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                        [],
+                        |row| row.get(0),
+                    )?,
+                })
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    pub async fn get_document(&self, id: &str) -> Result<Option<Document>, SqliteError> {
+        let id = id.to_string();
+
+        self.conn
+            .call(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT id, source_id, content, created_at, tags FROM documents WHERE id = ?1",
+                        [id],
+                        |row| Document::try_from(row),
+                    )
+                    .optional()?)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Every document, or those of `source_id`, ordered by source and id.
+    pub async fn documents(&self, source_id: Option<&str>) -> Result<Vec<Document>, SqliteError> {
+        let source_id = source_id.map(String::from);
+
+        self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, source_id, content, created_at, tags
+                     FROM documents
+                     WHERE ?1 IS NULL OR source_id = ?1
+                     ORDER BY source_id, id",
+                )?;
+                let documents = stmt
+                    .query_map([source_id], |row| Document::try_from(row))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(documents)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Re-embeds every stored document and queues every stored message after
+    /// the vector tables have been rebuilt for a new embedding model.
+    async fn reembed_all(&mut self) -> anyhow::Result<()> {
+        let documents = self
+            .conn
+            .call(|conn| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO message_embedding_queue (message_rowid, queued_at)
+                     SELECT rowid, CURRENT_TIMESTAMP FROM messages",
+                    [],
+                )?;
+                // The new model gets a fresh chance at messages the old one failed on
+                conn.execute("UPDATE message_embedding_queue SET attempts = 0", [])?;
+
+                let mut stmt =
+                    conn.prepare("SELECT id, source_id, content, created_at, tags FROM documents")?;
+                let documents = stmt
+                    .query_map([], |row| Document::try_from(row))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(documents)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        info!(count = documents.len(), "Re-embedding documents");
+        for batch in documents.chunks(E::MAX_DOCUMENTS) {
+            self.add_documents(batch.to_vec()).await?;
+        }
+
+        Ok(())
     }
 
     pub async fn create_user(&self, name: String, source: String) -> Result<i64, SqliteError> {
@@ -82,12 +420,17 @@ impl<E: EmbeddingModel> KnowledgeBase<E> {
             .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
     }
 
-    pub fn document_index(self) -> SqliteVectorIndex<E, Document> {
-        SqliteVectorIndex::new(self.embedding_model, self.document_store)
+    pub fn document_index(self) -> DocumentIndex<E> {
+        self.filtered_document_index(DocumentFilter::default())
     }
 
-    pub fn dialogue_index(self) -> SqliteVectorIndex<E, Document> {
-        SqliteVectorIndex::new(self.embedding_model, self.document_store)
+    /// Character dialogue only, for generating lines in the persona's voice.
+    pub fn dialogue_index(self) -> DocumentIndex<E> {
+        self.filtered_document_index(DocumentFilter::source(DIALOGUE_SOURCE))
+    }
+
+    pub fn filtered_document_index(self, filter: DocumentFilter) -> DocumentIndex<E> {
+        DocumentIndex::new(self.conn, self.embedding_model, self.retrieval_mode, filter)
     }
 
     pub fn message_index(self) -> SqliteVectorIndex<E, Message> {
@@ -174,41 +517,188 @@ impl<E: EmbeddingModel> KnowledgeBase<E> {
             .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
     }
 
+    /// Stores a message without embedding it. The message is queued for the
+    /// background [`EmbeddingWorker`](super::EmbeddingWorker) and becomes
+    /// searchable through [`Self::message_index`] once it has been embedded.
     pub async fn create_message(&self, msg: Message) -> anyhow::Result<i64> {
-        let embeddings = EmbeddingsBuilder::new(self.embedding_model.clone())
-            .documents(vec![msg.clone()])?
-            .build()
-            .await?;
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let id = store_message(&tx, &msg, None)?;
+                tx.commit()?;
+                Ok(id)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// Number of stored messages that are still waiting to be embedded,
+    /// including those given up on after [`MAX_EMBEDDING_ATTEMPTS`].
+    pub async fn pending_embeddings(&self) -> Result<i64, SqliteError> {
+        self.conn
+            .call(|conn| {
+                Ok(conn.query_row(
+                    "SELECT COUNT(*) FROM message_embedding_queue",
+                    [],
+                    |row| row.get(0),
+                )?)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Embeds up to `batch_size` queued messages in a single embedding request
+    /// and adds them to the message vector index. Returns how many messages
+    /// were embedded.
+    ///
+    /// When the batch fails its messages are embedded one by one, so a single
+    /// message the model rejects doesn't hold back the rest. Failed messages
+    /// move to the back of the queue and are skipped after
+    /// [`MAX_EMBEDDING_ATTEMPTS`]. Fails only when nothing could be embedded.
+    pub async fn embed_pending_messages(&self, batch_size: usize) -> anyhow::Result<usize> {
+        let limit = batch_size.clamp(1, E::MAX_DOCUMENTS);
+
+        let pending: Vec<(i64, String)> = self
+            .conn
+            .call(move |conn| {
+                // Forget queue entries whose message has since been deleted
+                conn.execute(
+                    "DELETE FROM message_embedding_queue
+                     WHERE message_rowid NOT IN (SELECT rowid FROM messages)",
+                    [],
+                )?;
 
-        let store = self.message_store.clone();
+                let mut stmt = conn.prepare(
+                    "SELECT q.message_rowid, m.content
+                     FROM message_embedding_queue q
+                     JOIN messages m ON m.rowid = q.message_rowid
+                     WHERE q.attempts < ?2
+                     ORDER BY q.attempts, q.message_rowid
+                     LIMIT ?1",
+                )?;
+                let pending = stmt
+                    .query_map(rusqlite::params![limit, MAX_EMBEDDING_ATTEMPTS], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(pending)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        debug!(count = pending.len(), "Embedding queued messages");
+        let (rowids, contents): (Vec<i64>, Vec<String>) = pending.iter().cloned().unzip();
+        let batch_err = match self.embedding_model.embed_texts(contents).await {
+            Ok(embeddings) => {
+                self.store_message_embeddings(rowids.into_iter().zip(embeddings).collect())
+                    .await?;
+                return Ok(pending.len());
+            }
+            Err(err) => err,
+        };
+
+        if pending.len() > 1 {
+            warn!(?batch_err, "Embedding batch failed, embedding messages one by one");
+        }
+        let mut embedded = Vec::new();
+        let mut failed = Vec::new();
+        let mut last_err = batch_err;
+        for (rowid, content) in pending {
+            match self.embedding_model.embed_text(&content).await {
+                Ok(embedding) => embedded.push((rowid, embedding)),
+                Err(err) => {
+                    warn!(?err, rowid, "Failed to embed queued message");
+                    failed.push(rowid);
+                    last_err = err;
+                }
+            }
+        }
+
+        let count = embedded.len();
+        self.store_message_embeddings(embedded).await?;
+        self.record_embedding_failures(failed).await?;
+
+        if count == 0 {
+            return Err(last_err.into());
+        }
+        Ok(count)
+    }
+
+    /// Adds embeddings to the message vector index and takes their messages
+    /// off the queue.
+    async fn store_message_embeddings(
+        &self,
+        embeddings: Vec<(i64, rig::embeddings::Embedding)>,
+    ) -> anyhow::Result<()> {
+        if embeddings.is_empty() {
+            return Ok(());
+        }
 
         self.conn
             .call(move |conn| {
                 let tx = conn.transaction()?;
 
-                // First upsert the channel
-                tx.execute(
-                    "INSERT INTO channels (channel_id, channel_type, source, name, created_at, updated_at) 
-                     VALUES (?1, ?2, ?3, NULL, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
-                     ON CONFLICT (channel_id) DO UPDATE SET 
-                     updated_at = CURRENT_TIMESTAMP",
-                    [
-                        &msg.channel_id,
-                        &msg.channel_type.as_str().to_string(),
-                        &msg.source.as_str().to_string(),
-                    ],
-                )?;
+                for (rowid, embedding) in &embeddings {
+                    let blob = to_blob(&embedding.vec.iter().map(|x| *x as f32).collect::<Vec<_>>());
 
-                let id = store.add_rows_with_txn(&tx, embeddings)?;
+                    tx.execute("DELETE FROM messages_embeddings WHERE rowid = ?1", [rowid])?;
+                    tx.execute(
+                        "INSERT INTO messages_embeddings (rowid, embedding) VALUES (?1, ?2)",
+                        rusqlite::params![rowid, blob],
+                    )?;
+                    tx.execute(
+                        "DELETE FROM message_embedding_queue WHERE message_rowid = ?1",
+                        [rowid],
+                    )?;
+                }
 
                 tx.commit()?;
-
-                Ok(id)
+                Ok(())
             })
             .await
             .map_err(|e| anyhow::anyhow!(e))
     }
 
+    async fn record_embedding_failures(&self, rowids: Vec<i64>) -> anyhow::Result<()> {
+        if rowids.is_empty() {
+            return Ok(());
+        }
+
+        let given_up: Vec<i64> = self
+            .conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let mut given_up = Vec::new();
+
+                for rowid in &rowids {
+                    let attempts: i64 = tx.query_row(
+                        "UPDATE message_embedding_queue SET attempts = attempts + 1
+                         WHERE message_rowid = ?1
+                         RETURNING attempts",
+                        [rowid],
+                        |row| row.get(0),
+                    )?;
+                    if attempts >= MAX_EMBEDDING_ATTEMPTS {
+                        given_up.push(*rowid);
+                    }
+                }
+
+                tx.commit()?;
+                Ok(given_up)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        if !given_up.is_empty() {
+            warn!(?given_up, attempts = MAX_EMBEDDING_ATTEMPTS, "Giving up on embedding messages");
+        }
+        Ok(())
+    }
+
     pub async fn get_message(&self, id: i64) -> Result<Option<Message>, SqliteError> {
         self.conn
             .call(move |conn| {
@@ -275,16 +765,68 @@ impl<E: EmbeddingModel> KnowledgeBase<E> {
             .map_err(|e| anyhow::anyhow!(e))
     }
 
-    pub async fn add_documents<'a, I>(&mut self, documents: I) -> anyhow::Result<()>
+    /// The newest documents of `source_id` created at or after `since`.
+    pub async fn recent_documents(
+        &self,
+        source_id: &str,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> Result<Vec<Document>, SqliteError> {
+        let source_id = source_id.to_string();
+
+        self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, source_id, content, created_at, tags
+                     FROM documents
+                     WHERE source_id = ?1 AND julianday(created_at) >= julianday(?2)
+                     ORDER BY julianday(created_at) DESC
+                     LIMIT ?3",
+                )?;
+                let documents = stmt
+                    .query_map(
+                        rusqlite::params![source_id, since.to_rfc3339(), limit],
+                        |row| Document::try_from(row),
+                    )?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(documents)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Embeds and stores `documents`, replacing any existing documents with
+    /// the same ids along with their vectors.
+    pub async fn add_documents<I>(&mut self, documents: I) -> anyhow::Result<()>
     where
         I: IntoIterator<Item = Document>,
     {
         info!("Adding documents to KnowledgeBase");
+        let documents: Vec<Document> = documents.into_iter().collect();
         let embeddings = EmbeddingsBuilder::new(self.embedding_model.clone())
-            .documents(documents)?
+            .documents(documents.clone())?
             .build()
             .await?;
 
+        // The store replaces document rows, which gives them a new rowid and
+        // would leave the old vectors behind
+        let ids: Vec<String> = documents.into_iter().map(|document| document.id).collect();
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                for id in &ids {
+                    tx.execute(
+                        "DELETE FROM documents_embeddings
+                         WHERE rowid IN (SELECT rowid FROM documents WHERE id = ?1)",
+                        [id],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
         debug!("Adding embeddings to document store");
         self.document_store.add_rows(embeddings).await?;
 
@@ -292,8 +834,1473 @@ impl<E: EmbeddingModel> KnowledgeBase<E> {
         Ok(())
     }
 
+    /// Deletes the documents of `source_id` whose id starts with `id_prefix`,
+    /// along with their vectors. An empty prefix deletes the whole source.
+    pub async fn delete_documents(
+        &self,
+        source_id: &str,
+        id_prefix: &str,
+    ) -> Result<usize, SqliteError> {
+        let source_id = source_id.to_string();
+        let id_prefix = id_prefix.to_string();
 
-    
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "DELETE FROM documents_embeddings WHERE rowid IN (
+                        SELECT rowid FROM documents
+                        WHERE source_id = ?1 AND substr(id, 1, length(?2)) = ?2
+                    )",
+                    [&source_id, &id_prefix],
+                )?;
+                let deleted = tx.execute(
+                    "DELETE FROM documents WHERE source_id = ?1 AND substr(id, 1, length(?2)) = ?2",
+                    [&source_id, &id_prefix],
+                )?;
+                tx.commit()?;
+                Ok(deleted)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
 
+    /// Returns the position a loader last synced `key` up to, e.g. a commit.
+    pub async fn get_sync_cursor(&self, key: &str) -> Result<Option<String>, SqliteError> {
+        let key = format!("sync:{}", key);
+        self.conn
+            .call(move |conn| Ok(get_metadata(conn, &key)?))
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
 
+    pub async fn set_sync_cursor(&self, key: &str, cursor: &str) -> Result<(), SqliteError> {
+        let key = format!("sync:{}", key);
+        let cursor = cursor.to_string();
+        self.conn
+            .call(move |conn| Ok(set_metadata(conn, &key, &cursor)?))
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Attention state of a channel, the default for channels without one.
+    pub async fn channel_attention(
+        &self,
+        source: &Source,
+        channel_id: &str,
+    ) -> Result<ChannelAttention, SqliteError> {
+        let source = source.as_str().to_string();
+        let channel_id = channel_id.to_string();
+
+        self.conn
+            .call(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT muted_until, messages_since_reply, last_replied_at
+                         FROM channel_attention WHERE source = ?1 AND channel_id = ?2",
+                        [&source, &channel_id],
+                        |row| {
+                            Ok(ChannelAttention {
+                                muted_until: row.get(0)?,
+                                messages_since_reply: row.get(1)?,
+                                last_replied_at: row.get(2)?,
+                            })
+                        },
+                    )
+                    .optional()?
+                    .unwrap_or_default())
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    pub async fn set_channel_attention(
+        &self,
+        source: &Source,
+        channel_id: &str,
+        state: &ChannelAttention,
+    ) -> Result<(), SqliteError> {
+        let source = source.as_str().to_string();
+        let channel_id = channel_id.to_string();
+        let state = state.clone();
+
+        self.conn
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO channel_attention
+                        (source, channel_id, muted_until, messages_since_reply, last_replied_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP)
+                     ON CONFLICT (source, channel_id) DO UPDATE SET
+                        muted_until = excluded.muted_until,
+                        messages_since_reply = excluded.messages_since_reply,
+                        last_replied_at = excluded.last_replied_at,
+                        updated_at = CURRENT_TIMESTAMP",
+                    rusqlite::params![
+                        source,
+                        channel_id,
+                        state.muted_until.map(|t| t.to_rfc3339()),
+                        state.messages_since_reply,
+                        state.last_replied_at.map(|t| t.to_rfc3339()),
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// The policy stored for a channel, or for every channel of `source` when
+    /// `channel_id` is `None`, without merging the two.
+    pub async fn stored_engagement_policy(
+        &self,
+        source: &Source,
+        channel_id: Option<&str>,
+    ) -> Result<EngagementPolicy, SqliteError> {
+        let source = source.as_str().to_string();
+        let channel_id = channel_id.unwrap_or(ALL_CHANNELS).to_string();
+
+        self.conn
+            .call(move |conn| {
+                let row = conn
+                    .query_row(
+                        "SELECT mode, allowed_users, blocked_users
+                         FROM engagement_policies WHERE source = ?1 AND channel_id = ?2",
+                        [&source, &channel_id],
+                        |row| {
+                            Ok((
+                                row.get::<_, String>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, String>(2)?,
+                            ))
+                        },
+                    )
+                    .optional()?;
+
+                let Some((mode, allowed_users, blocked_users)) = row else {
+                    return Ok(EngagementPolicy::default());
+                };
+                let users = |json: &str| -> Result<Vec<String>, tokio_rusqlite::Error> {
+                    serde_json::from_str(json).map_err(|e| tokio_rusqlite::Error::Other(Box::new(e)))
+                };
+                Ok(EngagementPolicy {
+                    mode: EngagementMode::from_str(&mode).unwrap_or_default(),
+                    allowed_users: users(&allowed_users)?,
+                    blocked_users: users(&blocked_users)?,
+                })
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// The policy that applies in a channel: its own merged into the one for
+    /// every channel of `source`.
+    pub async fn engagement_policy(
+        &self,
+        source: &Source,
+        channel_id: &str,
+    ) -> Result<EngagementPolicy, SqliteError> {
+        let source_wide = self.stored_engagement_policy(source, None).await?;
+        let channel = self.stored_engagement_policy(source, Some(channel_id)).await?;
+        Ok(source_wide.merge(&channel))
+    }
+
+    /// Replaces the policy of a channel, or of every channel of `source` when
+    /// `channel_id` is `None`, as an admin edit that the policy file no
+    /// longer overrides.
+    pub async fn set_engagement_policy(
+        &self,
+        source: &Source,
+        channel_id: Option<&str>,
+        policy: &EngagementPolicy,
+    ) -> Result<(), SqliteError> {
+        self.store_engagement_policy(source, channel_id, policy, "admin")
+            .await
+            .map(|_| ())
+    }
+
+    /// Stores a policy from the policy file unless an admin has changed the
+    /// policy of that channel since. Returns whether it was stored.
+    pub async fn set_file_engagement_policy(
+        &self,
+        source: &Source,
+        channel_id: Option<&str>,
+        policy: &EngagementPolicy,
+    ) -> Result<bool, SqliteError> {
+        self.store_engagement_policy(source, channel_id, policy, "file")
+            .await
+    }
+
+    /// Writes a policy with its `origin`. A file policy never replaces an
+    /// admin one.
+    async fn store_engagement_policy(
+        &self,
+        source: &Source,
+        channel_id: Option<&str>,
+        policy: &EngagementPolicy,
+        origin: &'static str,
+    ) -> Result<bool, SqliteError> {
+        let source = source.as_str().to_string();
+        let channel_id = channel_id.unwrap_or(ALL_CHANNELS).to_string();
+        let mode = policy.mode.as_str();
+        let allowed_users = serde_json::to_string(&policy.allowed_users)
+            .map_err(|e| SqliteError::SerializationError(Box::new(e)))?;
+        let blocked_users = serde_json::to_string(&policy.blocked_users)
+            .map_err(|e| SqliteError::SerializationError(Box::new(e)))?;
+
+        self.conn
+            .call(move |conn| {
+                let changed = conn.execute(
+                    "INSERT INTO engagement_policies
+                        (source, channel_id, mode, allowed_users, blocked_users, origin, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, CURRENT_TIMESTAMP)
+                     ON CONFLICT (source, channel_id) DO UPDATE SET
+                        mode = excluded.mode,
+                        allowed_users = excluded.allowed_users,
+                        blocked_users = excluded.blocked_users,
+                        origin = excluded.origin,
+                        updated_at = CURRENT_TIMESTAMP
+                     WHERE excluded.origin = 'admin' OR engagement_policies.origin = 'file'",
+                    rusqlite::params![source, channel_id, mode, allowed_users, blocked_users, origin],
+                )?;
+                Ok(changed > 0)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    pub async fn log_attention_decision(&self, log: &AttentionLog) -> Result<(), SqliteError> {
+        let log = log.clone();
+
+        self.conn
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO attention_decisions
+                        (source, channel_id, message_id, kind, command, score, reason, model, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    rusqlite::params![
+                        log.source.as_str(),
+                        log.channel_id,
+                        log.message_id,
+                        log.kind,
+                        log.command,
+                        log.score,
+                        log.reason,
+                        log.model,
+                        log.created_at.to_rfc3339(),
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// The latest `limit` logged attention decisions, newest first.
+    pub async fn attention_decisions(&self, limit: usize) -> Result<Vec<AttentionLog>, SqliteError> {
+        self.conn
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT source, channel_id, message_id, kind, command, score, reason, model, created_at
+                     FROM attention_decisions ORDER BY id DESC LIMIT ?1",
+                )?;
+                let logs = stmt
+                    .query_map([limit as i64], |row| AttentionLog::try_from(row))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(logs)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Deletes messages older than `policy` allows for their source and
+    /// channel type, along with their vectors and attention decisions, and
+    /// attention decisions older than its attention log age. Returns how many
+    /// messages were deleted.
+    pub async fn purge_expired_messages(
+        &self,
+        policy: &RetentionPolicy,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<usize, SqliteError> {
+        if policy.is_empty() {
+            return Ok(0);
+        }
+        let policy = policy.clone();
+
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+
+                let scopes = tx
+                    .prepare("SELECT DISTINCT source, channel_type FROM messages")?
+                    .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut deleted = 0;
+                for (source, channel_type) in scopes {
+                    let max_age = Source::from_str(&source)
+                        .zip(ChannelType::from_str(&channel_type))
+                        .and_then(|(s, c)| policy.max_age(&s, &c));
+                    // Nothing is older than a cutoff before the earliest date
+                    let Some(cutoff) = max_age.and_then(|max_age| now.checked_sub_signed(max_age))
+                    else {
+                        continue;
+                    };
+
+                    let cutoff = cutoff.to_rfc3339();
+                    let count = delete_messages(
+                        &tx,
+                        "source = ?1 AND channel_type = ?2 AND julianday(created_at) < julianday(?3)",
+                        [&source, &channel_type, &cutoff],
+                    )?;
+                    if count > 0 {
+                        debug!(source, channel_type, count, %cutoff, "Deleted expired messages");
+                    }
+                    deleted += count;
+                }
+
+                if let Some(cutoff) = policy
+                    .attention_log_max_age
+                    .and_then(|max_age| now.checked_sub_signed(max_age))
+                {
+                    let count = tx.execute(
+                        "DELETE FROM attention_decisions WHERE julianday(created_at) < julianday(?1)",
+                        [cutoff.to_rfc3339()],
+                    )?;
+                    if count > 0 {
+                        debug!(count, %cutoff, "Deleted expired attention decisions");
+                    }
+                }
+
+                tx.commit()?;
+                Ok(deleted)
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Deletes every message `account_id` wrote on `source`, with their
+    /// vectors and attention decisions, and the stored account, for deletion
+    /// requests.
+    pub async fn forget_user(
+        &self,
+        source: Source,
+        account_id: &str,
+    ) -> Result<ForgetSummary, SqliteError> {
+        let source = source.as_str().to_string();
+        let account_id = account_id.to_string();
+
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let messages =
+                    delete_messages(&tx, "source = ?1 AND account_id = ?2", [&source, &account_id])?;
+                let accounts = tx.execute(
+                    "DELETE FROM accounts WHERE source = ?1 AND source_id = ?2",
+                    [&source, &account_id],
+                )?;
+                tx.commit()?;
+
+                info!(source, messages, accounts, "Forgot user");
+                Ok(ForgetSummary { messages, accounts })
+            })
+            .await
+            .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+    }
+
+    /// Writes the knowledge base to `writer` as JSON lines in the format of
+    /// [`ExportRecord`], for backups or moving to another database.
+    pub async fn export(
+        &self,
+        mut writer: impl std::io::Write,
+        options: ExportOptions,
+    ) -> anyhow::Result<BackupSummary> {
+        let (embedding_model, embedding_dims) = self
+            .embedding_model_info()
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+        let ExportOptions {
+            include_vectors,
+            source_id,
+        } = options;
+
+        let records = self
+            .conn
+            .call(move |conn| {
+                let mut records = Vec::new();
+                let embedding = |table: &str, alias: &str| {
+                    if include_vectors {
+                        format!("(SELECT embedding FROM {}_embeddings WHERE rowid = {}.rowid)", table, alias)
+                    } else {
+                        "NULL".to_string()
+                    }
+                };
+
+                if source_id.is_none() {
+                    let mut stmt = conn.prepare(
+                        "SELECT name, source_id, source, created_at, updated_at FROM accounts ORDER BY id",
+                    )?;
+                    let accounts = stmt.query_map([], |row| {
+                        Ok(ExportRecord::Account(AccountRecord {
+                            name: row.get(0)?,
+                            source_id: row.get(1)?,
+                            source: row.get(2)?,
+                            created_at: row.get(3)?,
+                            updated_at: row.get(4)?,
+                        }))
+                    })?;
+                    records.extend(accounts.collect::<Result<Vec<_>, _>>()?);
+
+                    let mut stmt = conn.prepare(
+                        "SELECT channel_id, channel_type, source, name, created_at, updated_at
+                         FROM channels ORDER BY id",
+                    )?;
+                    let channels = stmt.query_map([], |row| {
+                        Ok(ExportRecord::Channel(ChannelRecord {
+                            channel_id: row.get(0)?,
+                            channel_type: row.get(1)?,
+                            source: row.get(2)?,
+                            name: row.get(3)?,
+                            created_at: row.get(4)?,
+                            updated_at: row.get(5)?,
+                        }))
+                    })?;
+                    records.extend(channels.collect::<Result<Vec<_>, _>>()?);
+                }
+
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT d.id, d.source_id, d.content, d.created_at, d.tags, {}
+                     FROM documents d
+                     WHERE ?1 IS NULL OR d.source_id = ?1
+                     ORDER BY d.source_id, d.id",
+                    embedding("documents", "d")
+                ))?;
+                let documents = stmt.query_map([&source_id], |row| {
+                    Ok(ExportRecord::Document {
+                        document: Document::try_from(row)?,
+                        embedding: row.get::<_, Option<Vec<u8>>>(5)?.map(|blob| from_blob(&blob)),
+                    })
+                })?;
+                records.extend(documents.collect::<Result<Vec<_>, _>>()?);
+
+                if source_id.is_none() {
+                    let mut stmt = conn.prepare(&format!(
+                        "SELECT m.id, m.source, m.source_id, m.channel_type, m.channel_id,
+                                m.account_id, m.role, m.content, m.created_at, {}
+                         FROM messages m
+                         ORDER BY m.rowid",
+                        embedding("messages", "m")
+                    ))?;
+                    let messages = stmt.query_map([], |row| {
+                        Ok(ExportRecord::Message {
+                            message: Message::try_from(row)?,
+                            embedding: row.get::<_, Option<Vec<u8>>>(9)?.map(|blob| from_blob(&blob)),
+                        })
+                    })?;
+                    records.extend(messages.collect::<Result<Vec<_>, _>>()?);
+                }
+
+                Ok(records)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        let header = ExportRecord::Header(ExportHeader {
+            version: EXPORT_FORMAT_VERSION,
+            embedding_model,
+            embedding_dims,
+            exported_at: chrono::Utc::now(),
+        });
+
+        let mut summary = BackupSummary::default();
+        for record in std::iter::once(&header).chain(&records) {
+            match record {
+                ExportRecord::Header(_) => {}
+                ExportRecord::Account(_) => summary.accounts += 1,
+                ExportRecord::Channel(_) => summary.channels += 1,
+                ExportRecord::Document { .. } => summary.documents += 1,
+                ExportRecord::Message { .. } => summary.messages += 1,
+            }
+            serde_json::to_writer(&mut writer, record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        info!(?summary, "Exported knowledge base");
+        Ok(summary)
+    }
+
+    /// Imports an export written by [`Self::export`], replacing rows with the
+    /// same ids.
+    ///
+    /// Vectors in the export are reused when it was made with the current
+    /// embedding model. Otherwise, or when the export has no vectors,
+    /// documents are embedded again and messages are queued for the
+    /// [`EmbeddingWorker`](super::EmbeddingWorker).
+    pub async fn import(&mut self, reader: impl std::io::BufRead) -> anyhow::Result<BackupSummary> {
+        let mut records = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: ExportRecord = serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("Invalid record on line {}: {}", i + 1, e))?;
+            records.push(record);
+        }
+
+        let mut records = records.into_iter();
+        let header = match records.next() {
+            Some(ExportRecord::Header(header)) => header,
+            _ => anyhow::bail!("Export does not start with a header"),
+        };
+        if header.version > EXPORT_FORMAT_VERSION {
+            anyhow::bail!(
+                "Export format version {} is newer than the supported version {}",
+                header.version,
+                EXPORT_FORMAT_VERSION
+            );
+        }
+
+        let (model, dims) = self
+            .embedding_model_info()
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+        let reuse_vectors = header.embedding_model == model && header.embedding_dims == dims;
+        if !reuse_vectors {
+            info!(
+                from_model = %header.embedding_model,
+                from_dims = header.embedding_dims,
+                to_model = %model,
+                to_dims = dims,
+                "Export was made with another embedding model, re-embedding"
+            );
+        }
+        let usable = |embedding: Option<Vec<f32>>| {
+            embedding.filter(|embedding| reuse_vectors && embedding.len() == dims)
+        };
+
+        let mut summary = BackupSummary::default();
+        let mut rows = Vec::new();
+        let mut to_embed = Vec::new();
+        for record in records {
+            match record {
+                ExportRecord::Header(_) => anyhow::bail!("Export has more than one header"),
+                ExportRecord::Account(_) => {
+                    summary.accounts += 1;
+                    rows.push(record);
+                }
+                ExportRecord::Channel(_) => {
+                    summary.channels += 1;
+                    rows.push(record);
+                }
+                ExportRecord::Document {
+                    document,
+                    embedding,
+                } => {
+                    summary.documents += 1;
+                    match usable(embedding) {
+                        Some(embedding) => rows.push(ExportRecord::Document {
+                            document,
+                            embedding: Some(embedding),
+                        }),
+                        None => to_embed.push(document),
+                    }
+                }
+                ExportRecord::Message { message, embedding } => {
+                    summary.messages += 1;
+                    let embedding = usable(embedding);
+                    if embedding.is_none() {
+                        summary.reembedded += 1;
+                    }
+                    rows.push(ExportRecord::Message { message, embedding });
+                }
+            }
+        }
+        summary.reembedded += to_embed.len();
+
+        self.conn
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+
+                for record in rows {
+                    match record {
+                        ExportRecord::Account(account) => {
+                            tx.execute(
+                                "INSERT INTO accounts (name, source_id, source, created_at, updated_at)
+                                 VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), COALESCE(?5, CURRENT_TIMESTAMP))
+                                 ON CONFLICT(source_id) DO UPDATE SET
+                                     name = excluded.name,
+                                     source = excluded.source,
+                                     updated_at = excluded.updated_at",
+                                rusqlite::params![
+                                    account.name,
+                                    account.source_id,
+                                    account.source,
+                                    account.created_at,
+                                    account.updated_at
+                                ],
+                            )?;
+                        }
+                        ExportRecord::Channel(channel) => {
+                            tx.execute(
+                                "INSERT INTO channels (channel_id, channel_type, source, name, created_at, updated_at)
+                                 VALUES (?1, ?2, ?3, ?4, COALESCE(?5, CURRENT_TIMESTAMP), COALESCE(?6, CURRENT_TIMESTAMP))
+                                 ON CONFLICT(channel_id) DO UPDATE SET
+                                     channel_type = excluded.channel_type,
+                                     source = excluded.source,
+                                     name = excluded.name,
+                                     updated_at = excluded.updated_at",
+                                rusqlite::params![
+                                    channel.channel_id,
+                                    channel.channel_type,
+                                    channel.source,
+                                    channel.name,
+                                    channel.created_at,
+                                    channel.updated_at
+                                ],
+                            )?;
+                        }
+                        ExportRecord::Document {
+                            document,
+                            embedding: Some(embedding),
+                        } => {
+                            tx.execute(
+                                "DELETE FROM documents_embeddings
+                                 WHERE rowid IN (SELECT rowid FROM documents WHERE id = ?1)",
+                                [&document.id],
+                            )?;
+                            let id = insert_row(&tx, &document)?;
+                            tx.execute(
+                                "INSERT INTO documents_embeddings (rowid, embedding) VALUES (?1, ?2)",
+                                rusqlite::params![id, to_blob(&embedding)],
+                            )?;
+                        }
+                        ExportRecord::Message { message, embedding } => {
+                            store_message(&tx, &message, embedding.as_deref())?;
+                        }
+                        ExportRecord::Header(_) | ExportRecord::Document { .. } => {}
+                    }
+                }
+
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        for batch in to_embed.chunks(E::MAX_DOCUMENTS) {
+            self.add_documents(batch.to_vec()).await?;
+        }
+
+        info!(?summary, "Imported knowledge base");
+        Ok(summary)
+    }
+}
+
+/// Name and dimension of the embedding model a database was built with,
+/// read without opening it as a [`KnowledgeBase`], which would rebuild the
+/// vectors if the model differs. `None` for a new database, and no name for
+/// one built before the model was recorded.
+pub async fn stored_embedding_model(
+    conn: &Connection,
+) -> Result<Option<(Option<String>, usize)>, SqliteError> {
+    conn.call(|conn| {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'kb_metadata')",
+            [],
+            |row| row.get(0),
+        )?;
+        if exists {
+            let name = get_metadata(conn, "embedding_model")?;
+            let dims = get_metadata(conn, "embedding_dims")?.and_then(|dims| dims.parse().ok());
+            if let Some((name, dims)) = name.zip(dims) {
+                return Ok(Some((Some(name), dims)));
+            }
+        }
+
+        Ok(embedding_table_dims(conn)?.map(|dims| (None, dims)))
+    })
+    .await
+    .map_err(|e| SqliteError::DatabaseError(Box::new(e)))
+}
+
+/// Dimension of the existing document vectors, read from the `float[N]`
+/// column of their vec0 table.
+fn embedding_table_dims(conn: &rusqlite::Connection) -> rusqlite::Result<Option<usize>> {
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'documents_embeddings'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(sql.and_then(|sql| {
+        let start = sql.find("float[")? + "float[".len();
+        let end = start + sql[start..].find(']')?;
+        sql[start..end].trim().parse().ok()
+    }))
+}
+
+fn get_metadata(conn: &rusqlite::Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM kb_metadata WHERE key = ?1",
+        [key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn set_metadata(conn: &rusqlite::Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO kb_metadata (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        [key, value],
+    )?;
+    Ok(())
+}
+
+/// `source_id` of documents holding the character's own dialogue lines.
+pub const DIALOGUE_SOURCE: &str = "dialogue";
+
+/// `source_id` of documents built from episode transcripts.
+pub const TRANSCRIPT_SOURCE: &str = "transcript";
+
+/// `source_id` of documents loaded from the local docs directory.
+pub const DOCS_SOURCE: &str = "docs";
+
+/// `source_id` of documents fetched from web pages and feeds.
+pub const WEB_SOURCE: &str = "web";
+
+/// `channel_id` of engagement policies for every channel of a source.
+const ALL_CHANNELS: &str = "*";
+
+/// Times a queued message is tried before the embedding worker skips it.
+pub const MAX_EMBEDDING_ATTEMPTS: i64 = 5;
+
+/// Stores a message, upserting its channel. With an `embedding` the message
+/// is added to the vector index directly, otherwise it's queued for the
+/// [`EmbeddingWorker`](super::EmbeddingWorker).
+fn store_message(
+    tx: &rusqlite::Transaction<'_>,
+    msg: &Message,
+    embedding: Option<&[f32]>,
+) -> rusqlite::Result<i64> {
+    // First upsert the channel
+    tx.execute(
+        "INSERT INTO channels (channel_id, channel_type, source, name, created_at, updated_at) 
+         VALUES (?1, ?2, ?3, NULL, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+         ON CONFLICT (channel_id) DO UPDATE SET 
+         updated_at = CURRENT_TIMESTAMP",
+        [
+            &msg.channel_id,
+            &msg.channel_type.as_str().to_string(),
+            &msg.source.as_str().to_string(),
+        ],
+    )?;
+
+    // A replaced message gets a new rowid, so drop the stale embedding
+    // and queue entry of the previous row
+    tx.execute(
+        "DELETE FROM messages_embeddings
+         WHERE rowid IN (SELECT rowid FROM messages WHERE id = ?1)",
+        [&msg.id],
+    )?;
+    tx.execute(
+        "DELETE FROM message_embedding_queue
+         WHERE message_rowid IN (SELECT rowid FROM messages WHERE id = ?1)",
+        [&msg.id],
+    )?;
+
+    let id = insert_row(tx, msg)?;
+
+    match embedding {
+        Some(embedding) => {
+            tx.execute(
+                "INSERT INTO messages_embeddings (rowid, embedding) VALUES (?1, ?2)",
+                rusqlite::params![id, to_blob(embedding)],
+            )?;
+        }
+        None => {
+            tx.execute(
+                "INSERT INTO message_embedding_queue (message_rowid, queued_at)
+                 VALUES (?1, CURRENT_TIMESTAMP)",
+                [id],
+            )?;
+        }
+    }
+
+    Ok(id)
+}
+
+/// Deletes the messages matching the SQL `condition`, along with their vectors
+/// and embedding queue entries.
+fn delete_messages<P: rusqlite::Params + Copy>(
+    tx: &rusqlite::Transaction<'_>,
+    condition: &str,
+    params: P,
+) -> rusqlite::Result<usize> {
+    tx.execute(
+        &format!(
+            "DELETE FROM messages_embeddings WHERE rowid IN (SELECT rowid FROM messages WHERE {})",
+            condition
+        ),
+        params,
+    )?;
+    tx.execute(
+        &format!(
+            "DELETE FROM message_embedding_queue
+             WHERE message_rowid IN (SELECT rowid FROM messages WHERE {})",
+            condition
+        ),
+        params,
+    )?;
+    // Decision reasons can quote or describe the message
+    tx.execute(
+        &format!(
+            "DELETE FROM attention_decisions
+             WHERE (source, message_id) IN (SELECT source, id FROM messages WHERE {})",
+            condition
+        ),
+        params,
+    )?;
+    tx.execute(&format!("DELETE FROM messages WHERE {}", condition), params)
+}
+
+/// Inserts a row into `T`'s table without touching its embeddings table,
+/// mirroring the statement `rig_sqlite` uses in `add_rows_with_txn`.
+fn insert_row<T: SqliteVectorStoreTable>(
+    tx: &rusqlite::Transaction<'_>,
+    row: &T,
+) -> rusqlite::Result<i64> {
+    let values = row.column_values();
+    let columns = values.iter().map(|(col, _)| *col).collect::<Vec<_>>();
+    let placeholders = (1..=values.len())
+        .map(|i| format!("?{}", i))
+        .collect::<Vec<_>>();
+
+    tx.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+            T::name(),
+            columns.join(", "),
+            placeholders.join(", ")
+        ),
+        rusqlite::params_from_iter(values.iter().map(|(_, val)| val.to_sql_string())),
+    )?;
+
+    Ok(tx.last_insert_rowid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::{ChannelType, Source};
+    use rig::vector_store::VectorStoreIndex;
+    use crate::embeddings::HashingEmbeddingModel;
+    use crate::knowledge::testing::{
+        init_sqlite_vec, open_test_knowledge_base, temp_path, test_knowledge_base,
+    };
+
+    fn message(id: &str, content: &str) -> Message {
+        Message {
+            id: id.to_string(),
+            source: Source::Discord,
+            source_id: "user-1".to_string(),
+            channel_type: ChannelType::Text,
+            channel_id: "channel-1".to_string(),
+            account_id: "user-1".to_string(),
+            role: "user".to_string(),
+            content: content.to_string(),
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_embedded_in_background() {
+        let knowledge = test_knowledge_base(64).await;
+
+        knowledge
+            .create_message(message("1", "Player 456 survived the glass bridge"))
+            .await
+            .unwrap();
+        knowledge
+            .create_message(message("2", "The Front Man wears a black mask"))
+            .await
+            .unwrap();
+        assert_eq!(knowledge.pending_embeddings().await.unwrap(), 2);
+
+        assert_eq!(knowledge.embed_pending_messages(1).await.unwrap(), 1);
+        assert_eq!(knowledge.embed_pending_messages(10).await.unwrap(), 1);
+        assert_eq!(knowledge.embed_pending_messages(10).await.unwrap(), 0);
+        assert_eq!(knowledge.pending_embeddings().await.unwrap(), 0);
+
+        let results = knowledge
+            .message_index()
+            .top_n_ids("glass bridge", 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].1, "1");
+    }
+
+    /// Fails to embed any batch containing "POISON".
+    #[derive(Clone)]
+    struct RejectingEmbeddingModel(HashingEmbeddingModel);
+
+    impl EmbeddingModel for RejectingEmbeddingModel {
+        const MAX_DOCUMENTS: usize = 16;
+
+        fn ndims(&self) -> usize {
+            self.0.ndims()
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<rig::embeddings::Embedding>, rig::embeddings::EmbeddingError> {
+            let texts: Vec<String> = texts.into_iter().collect();
+            if texts.iter().any(|text| text.contains("POISON")) {
+                return Err(rig::embeddings::EmbeddingError::ProviderError(
+                    "input rejected".to_string(),
+                ));
+            }
+            self.0.embed_texts(texts).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_message_does_not_block_the_queue() {
+        init_sqlite_vec();
+        let conn = Connection::open(":memory:").await.unwrap();
+        let knowledge = KnowledgeBase::new(conn, RejectingEmbeddingModel(HashingEmbeddingModel::new(16)), "rejecting")
+            .await
+            .unwrap();
+
+        knowledge.create_message(message("1", "POISON")).await.unwrap();
+        knowledge.create_message(message("2", "Red light")).await.unwrap();
+        knowledge.create_message(message("3", "Green light")).await.unwrap();
+
+        assert_eq!(knowledge.embed_pending_messages(10).await.unwrap(), 2);
+        for _ in 1..MAX_EMBEDDING_ATTEMPTS {
+            assert!(knowledge.embed_pending_messages(10).await.is_err());
+        }
+        knowledge.create_message(message("4", "Squid game")).await.unwrap();
+        assert_eq!(knowledge.embed_pending_messages(10).await.unwrap(), 1);
+
+        // Given up on, but still counted
+        assert_eq!(knowledge.embed_pending_messages(10).await.unwrap(), 0);
+        assert_eq!(knowledge.pending_embeddings().await.unwrap(), 1);
+    }
+
+    fn document(id: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            source_id: DIALOGUE_SOURCE.to_string(),
+            content: content.to_string(),
+            created_at: chrono::Utc::now(),
+            tags: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keyword_search_finds_exact_names() {
+        let mut knowledge = test_knowledge_base(16).await;
+        knowledge
+            .add_documents(vec![
+                document("doc-1", "Sae-byeok: I don't trust anyone here."),
+                document("doc-2", "Jun-ho: I'm looking for my brother."),
+            ])
+            .await
+            .unwrap();
+        // Replacing a document must not leave its old text in the keyword index
+        knowledge
+            .add_documents(vec![document("doc-2", "Il-nam: We're friends, aren't we?")])
+            .await
+            .unwrap();
+
+        let index = knowledge.with_retrieval_mode(RetrievalMode::Keyword).document_index();
+        let results = index.top_n_ids("Where is Sae-byeok?", 5).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1, "doc-1");
+        assert!(index.top_n_ids("Jun-ho", 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_top_n_returns_documents() {
+        let mut knowledge = test_knowledge_base(32).await;
+        knowledge
+            .add_documents(vec![Document {
+                tags: vec!["season:1".to_string(), "episode:6".to_string()],
+                ..document("doc-1", "Sae-byeok: I don't trust anyone here.")
+            }])
+            .await
+            .unwrap();
+
+        for mode in [RetrievalMode::Vector, RetrievalMode::Keyword, RetrievalMode::Hybrid] {
+            let index = knowledge.clone().with_retrieval_mode(mode).document_index();
+            let results = index.top_n::<Document>("Sae-byeok", 1).await.unwrap();
+            assert_eq!(results.len(), 1, "{:?}", mode);
+            assert_eq!(results[0].2.tags, vec!["season:1", "episode:6"], "{:?}", mode);
+        }
+
+        let context = crate::knowledge::ContextIndex::new(
+            knowledge.document_index(),
+            None,
+            crate::knowledge::ContextConfig {
+                min_similarity: 0.0,
+                ..Default::default()
+            },
+        );
+        let results = context.top_n::<Document>("Sae-byeok", 1).await.unwrap();
+        assert_eq!(results[0].2.id, "doc-1");
+        assert_eq!(results[0].2.tags.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_filtered_indexes() {
+        let mut knowledge = test_knowledge_base(32).await.with_retrieval_mode(RetrievalMode::Vector);
+        knowledge
+            .add_documents(vec![
+                document("line-1", "I'm going to stop the games"),
+                Document {
+                    source_id: "github:cartridge-gg/docs".to_string(),
+                    tags: vec!["docs".to_string()],
+                    ..document("doc-1", "How to stop the games server")
+                },
+            ])
+            .await
+            .unwrap();
+
+        let ids = |results: Vec<(f64, String)>| -> Vec<String> {
+            results.into_iter().map(|(_, id)| id).collect()
+        };
+
+        let dialogue = knowledge.clone().dialogue_index();
+        assert_eq!(ids(dialogue.top_n_ids("stop the games", 5).await.unwrap()), ["line-1"]);
+
+        let docs = knowledge
+            .clone()
+            .filtered_document_index(DocumentFilter::default().with_tag("docs"));
+        assert_eq!(ids(docs.top_n_ids("stop the games", 5).await.unwrap()), ["doc-1"]);
+
+        let future = knowledge
+            .filtered_document_index(DocumentFilter::default().since(chrono::Utc::now()));
+        assert!(future.top_n_ids("stop the games", 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_documents_by_prefix() {
+        let mut knowledge = test_knowledge_base(16).await;
+        let repo_doc = |id: &str, content: &str| Document {
+            source_id: "github:cartridge-gg/docs".to_string(),
+            ..document(id, content)
+        };
+        knowledge
+            .add_documents(vec![
+                repo_doc("src/a.md#0", "alpha"),
+                repo_doc("src/a.md#1", "beta"),
+                repo_doc("src/ab.md#0", "gamma"),
+                document("src/a.md#2", "dialogue with the same id prefix"),
+            ])
+            .await
+            .unwrap();
+
+        let deleted = knowledge
+            .delete_documents("github:cartridge-gg/docs", "src/a.md#")
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+        assert_eq!(
+            knowledge
+                .delete_documents("github:cartridge-gg/docs", "")
+                .await
+                .unwrap(),
+            1
+        );
+        let results = knowledge.document_index().top_n_ids("dialogue", 5).await.unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stats_and_document_listing() {
+        let mut knowledge = test_knowledge_base(16).await;
+        knowledge
+            .add_documents(vec![
+                document("line-1", "Red light"),
+                document("line-2", "Green light"),
+                Document {
+                    source_id: DOCS_SOURCE.to_string(),
+                    ..document("rules.md#0", "Rules")
+                },
+            ])
+            .await
+            .unwrap();
+        knowledge.create_message(message("msg-1", "Hello")).await.unwrap();
+
+        let stats = knowledge.stats().await.unwrap();
+        assert_eq!(
+            stats.documents,
+            vec![(DIALOGUE_SOURCE.to_string(), 2), (DOCS_SOURCE.to_string(), 1)]
+        );
+        assert_eq!(stats.messages, vec![("discord".to_string(), 1)]);
+        assert_eq!((stats.embedding_model.as_str(), stats.embedding_dims), ("hashing-16", 16));
+        assert!(stats.size_bytes > 0);
+
+        let ids = |documents: Vec<Document>| documents.into_iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(ids(knowledge.documents(None).await.unwrap()), ["line-1", "line-2", "rules.md#0"]);
+        assert_eq!(ids(knowledge.documents(Some(DOCS_SOURCE)).await.unwrap()), ["rules.md#0"]);
+
+        let document = knowledge.get_document("line-2").await.unwrap().unwrap();
+        assert_eq!(document.content, "Green light");
+        assert!(knowledge.get_document("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import() {
+        let mut knowledge = test_knowledge_base(16).await;
+        knowledge
+            .add_documents(vec![
+                document("line-1", "I'm not a horse, I'm a person"),
+                Document {
+                    source_id: DOCS_SOURCE.to_string(),
+                    tags: vec!["rules".to_string()],
+                    ..document("rules.md#0", "Red light, green light")
+                },
+            ])
+            .await
+            .unwrap();
+        knowledge.create_message(message("msg-1", "Hello 456")).await.unwrap();
+        knowledge.embed_pending_messages(10).await.unwrap();
+
+        let mut export = Vec::new();
+        let options = ExportOptions {
+            include_vectors: true,
+            ..Default::default()
+        };
+        let written = knowledge.export(&mut export, options).await.unwrap();
+        assert_eq!((written.channels, written.documents, written.messages), (1, 2, 1));
+
+        // Same model: vectors are reused as they are
+        let mut copy = test_knowledge_base(16).await;
+        let read = copy.import(export.as_slice()).await.unwrap();
+        assert_eq!(read, BackupSummary { reembedded: 0, ..written.clone() });
+        assert_eq!(copy.pending_embeddings().await.unwrap(), 0);
+        let document = copy.get_document("rules.md#0").await.unwrap().unwrap();
+        assert_eq!(document.tags, ["rules"]);
+        let results = copy.clone().document_index().top_n_ids("horse", 1).await.unwrap();
+        assert_eq!(results[0].1, "line-1");
+
+        // Another model: documents are embedded again and messages queued
+        let mut other = test_knowledge_base(32).await;
+        let read = other.import(export.as_slice()).await.unwrap();
+        assert_eq!(read.reembedded, 3);
+        assert_eq!(other.pending_embeddings().await.unwrap(), 1);
+        let results = other.document_index().top_n_ids("horse", 1).await.unwrap();
+        assert_eq!(results[0].1, "line-1");
+
+        // Documents of a single source, without the conversations
+        let mut export = Vec::new();
+        let options = ExportOptions {
+            source_id: Some(DOCS_SOURCE.to_string()),
+            ..Default::default()
+        };
+        let written = knowledge.export(&mut export, options).await.unwrap();
+        assert_eq!(written, BackupSummary { documents: 1, ..Default::default() });
+
+        assert!(copy.import(&b"{\"type\":\"account\"}\n"[..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_over_existing_database_replaces_vectors() {
+        let mut knowledge = test_knowledge_base(16).await;
+        knowledge
+            .add_documents(vec![
+                document("line-1", "I'm not a horse, I'm a person"),
+                document("line-2", "Red light, green light"),
+            ])
+            .await
+            .unwrap();
+        let vectors = |knowledge: &KnowledgeBase<HashingEmbeddingModel>| {
+            let conn = knowledge.conn.clone();
+            async move {
+                conn.call(|conn| {
+                    Ok(conn.query_row("SELECT COUNT(*) FROM documents_embeddings", [], |row| {
+                        row.get::<_, i64>(0)
+                    })?)
+                })
+                .await
+                .unwrap()
+            }
+        };
+
+        for include_vectors in [true, false] {
+            let mut export = Vec::new();
+            let options = ExportOptions {
+                include_vectors,
+                ..Default::default()
+            };
+            knowledge.export(&mut export, options).await.unwrap();
+
+            // Once into the database itself, then again into a copy of it
+            knowledge.import(export.as_slice()).await.unwrap();
+            let mut other = test_knowledge_base(32).await;
+            other.import(export.as_slice()).await.unwrap();
+            other.import(export.as_slice()).await.unwrap();
+
+            assert_eq!(vectors(&knowledge).await, 2);
+            assert_eq!(vectors(&other).await, 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retention_and_forgetting_users() {
+        let knowledge = test_knowledge_base(16).await;
+        let now = chrono::Utc::now();
+        let old = |id: &str, days: i64| Message {
+            created_at: now - chrono::Duration::days(days),
+            ..message(id, "Red light")
+        };
+
+        for msg in [
+            Message {
+                channel_type: ChannelType::DirectMessage,
+                channel_id: "dm-1".to_string(),
+                ..old("dm-old", 10)
+            },
+            Message {
+                channel_type: ChannelType::DirectMessage,
+                channel_id: "dm-1".to_string(),
+                ..old("dm-new", 2)
+            },
+            old("text-old", 10),
+            Message {
+                source: Source::Telegram,
+                ..old("telegram-old", 400)
+            },
+        ] {
+            knowledge.create_message(msg).await.unwrap();
+        }
+        knowledge.embed_pending_messages(10).await.unwrap();
+        let decision = |message_id: &str, days: i64| AttentionLog {
+            source: Source::Discord,
+            channel_id: "channel-1".to_string(),
+            message_id: message_id.to_string(),
+            kind: "reply".to_string(),
+            command: "ignore".to_string(),
+            score: None,
+            reason: "talking about the games".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            created_at: now - chrono::Duration::days(days),
+        };
+        for log in [
+            decision("dm-old", 10),
+            decision("dm-new", 2),
+            decision("text-old", 10),
+            decision("tweet-1", 100),
+        ] {
+            knowledge.log_attention_decision(&log).await.unwrap();
+        }
+        let decisions = || async {
+            let mut ids: Vec<String> = knowledge
+                .attention_decisions(10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|log| log.message_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let policy = RetentionPolicy::new(vec!["discord:direct_message=7d".parse().unwrap()])
+            .with_attention_log_max_age(chrono::Duration::days(30));
+        assert_eq!(knowledge.purge_expired_messages(&policy, now).await.unwrap(), 1);
+        assert_eq!(knowledge.purge_expired_messages(&policy, now).await.unwrap(), 0);
+        assert_eq!(decisions().await, ["dm-new", "text-old"]);
+        // Older than any date, so nothing expires
+        let forever = RetentionPolicy::new(vec!["*=9999999w".parse().unwrap()]);
+        assert_eq!(knowledge.purge_expired_messages(&forever, now).await.unwrap(), 0);
+        let remaining = knowledge.clone().message_index().top_n_ids("Red light", 10).await.unwrap();
+        assert_eq!(remaining.len(), 3);
+
+        let summary = knowledge.forget_user(Source::Discord, "user-1").await.unwrap();
+        assert_eq!(summary.messages, 2);
+        assert!(decisions().await.is_empty());
+        let stats = knowledge.stats().await.unwrap();
+        assert_eq!(stats.messages, vec![("telegram".to_string(), 1)]);
+        assert_eq!(stats.pending_embeddings, 0);
+    }
+
+    #[tokio::test]
+    async fn test_attention_decision_log() {
+        let knowledge = test_knowledge_base(16).await;
+        let log = |message_id: &str, command: &str, score| AttentionLog {
+            source: Source::Discord,
+            channel_id: "channel-1".to_string(),
+            message_id: message_id.to_string(),
+            kind: "reply".to_string(),
+            command: command.to_string(),
+            score,
+            reason: "because".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            created_at: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        };
+        knowledge.log_attention_decision(&log("message-1", "ignore", None)).await.unwrap();
+        knowledge.log_attention_decision(&log("message-2", "respond", Some(0.75))).await.unwrap();
+
+        assert_eq!(
+            knowledge.attention_decisions(10).await.unwrap(),
+            vec![log("message-2", "respond", Some(0.75)), log("message-1", "ignore", None)]
+        );
+        assert_eq!(knowledge.attention_decisions(1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_engagement_policies() {
+        let knowledge = test_knowledge_base(16).await;
+        assert_eq!(
+            knowledge.engagement_policy(&Source::Discord, "channel-1").await.unwrap(),
+            EngagementPolicy::default()
+        );
+
+        let source_wide = EngagementPolicy {
+            mode: EngagementMode::MentionOnly,
+            allowed_users: vec![],
+            blocked_users: vec!["user-2".to_string()],
+        };
+        knowledge
+            .set_engagement_policy(&Source::Discord, None, &source_wide)
+            .await
+            .unwrap();
+        let channel = EngagementPolicy {
+            mode: EngagementMode::Always,
+            allowed_users: vec!["user-1".to_string()],
+            blocked_users: vec![],
+        };
+        knowledge
+            .set_engagement_policy(&Source::Discord, Some("channel-1"), &channel)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            knowledge.stored_engagement_policy(&Source::Discord, Some("channel-1")).await.unwrap(),
+            channel
+        );
+        let policy = knowledge.engagement_policy(&Source::Discord, "channel-1").await.unwrap();
+        assert_eq!(policy.mode, EngagementMode::Always);
+        assert_eq!(policy.allowed_users, vec!["user-1".to_string()]);
+        assert_eq!(policy.blocked_users, vec!["user-2".to_string()]);
+        assert_eq!(
+            knowledge.engagement_policy(&Source::Discord, "channel-2").await.unwrap(),
+            source_wide
+        );
+        assert_eq!(
+            knowledge.engagement_policy(&Source::Telegram, "channel-1").await.unwrap(),
+            EngagementPolicy::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_attention_state() {
+        let knowledge = test_knowledge_base(16).await;
+        assert_eq!(
+            knowledge.channel_attention(&Source::Discord, "channel-1").await.unwrap(),
+            ChannelAttention::default()
+        );
+
+        let state = ChannelAttention {
+            muted_until: Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+            messages_since_reply: Some(2),
+            last_replied_at: None,
+        };
+        knowledge
+            .set_channel_attention(&Source::Discord, "channel-1", &state)
+            .await
+            .unwrap();
+
+        assert_eq!(knowledge.channel_attention(&Source::Discord, "channel-1").await.unwrap(), state);
+        assert_eq!(
+            knowledge.channel_attention(&Source::Telegram, "channel-1").await.unwrap(),
+            ChannelAttention::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_model_change_rebuilds_vectors() {
+        let path = temp_path("kb", ".db");
+        let path = path.to_str().unwrap();
+
+        {
+            let mut knowledge = open_test_knowledge_base(path, 32).await;
+            knowledge
+                .add_documents(vec![document("doc-1", "I'm not a horse, I'm a person")])
+                .await
+                .unwrap();
+            knowledge
+                .create_message(message("1", "hello"))
+                .await
+                .unwrap();
+            knowledge.embed_pending_messages(10).await.unwrap();
+        }
+
+        let knowledge = open_test_knowledge_base(path, 48).await;
+        assert_eq!(
+            knowledge.embedding_model_info().await.unwrap(),
+            ("hashing-48".to_string(), 48)
+        );
+        assert_eq!(knowledge.pending_embeddings().await.unwrap(), 1);
+
+        let results = knowledge
+            .document_index()
+            .top_n_ids("horse", 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].1, "doc-1");
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_unrecorded_model_rebuilds_vectors() {
+        let path = temp_path("kb-legacy", ".db");
+        let path = path.to_str().unwrap();
+
+        {
+            let mut knowledge = open_test_knowledge_base(path, 32).await;
+            knowledge
+                .add_documents(vec![document("doc-1", "I'm not a horse, I'm a person")])
+                .await
+                .unwrap();
+            knowledge
+                .create_message(message("1", "hello"))
+                .await
+                .unwrap();
+            knowledge.embed_pending_messages(10).await.unwrap();
+            // As built before the embedding model was recorded
+            knowledge
+                .conn
+                .call(|conn| Ok(conn.execute_batch("DROP TABLE kb_metadata;")?))
+                .await
+                .unwrap();
+        }
+
+        let conn = Connection::open(path).await.unwrap();
+        assert_eq!(stored_embedding_model(&conn).await.unwrap(), Some((None, 32)));
+        drop(conn);
+
+        let knowledge = open_test_knowledge_base(path, 48).await;
+        assert_eq!(
+            knowledge.embedding_model_info().await.unwrap(),
+            ("hashing-48".to_string(), 48)
+        );
+        assert_eq!(knowledge.pending_embeddings().await.unwrap(), 1);
+        let results = knowledge
+            .document_index()
+            .top_n_ids("horse", 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].1, "doc-1");
+
+        std::fs::remove_file(path).ok();
+    }
 }
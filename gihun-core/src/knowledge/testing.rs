@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+use sqlite_vec::sqlite3_vec_init;
+use tokio_rusqlite::ffi::sqlite3_auto_extension;
+use tokio_rusqlite::Connection;
+
+use super::KnowledgeBase;
+use crate::embeddings::HashingEmbeddingModel;
+
+/// Registers `sqlite-vec` for every connection opened afterwards.
+pub(crate) fn init_sqlite_vec() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| unsafe {
+        sqlite3_auto_extension(Some(std::mem::transmute::<
+            *const (),
+            unsafe extern "C" fn(
+                *mut tokio_rusqlite::ffi::sqlite3,
+                *mut *mut std::ffi::c_char,
+                *const tokio_rusqlite::ffi::sqlite3_api_routines,
+            ) -> std::ffi::c_int,
+        >(sqlite3_vec_init as *const ())));
+    });
+}
+
+/// Knowledge base in the database at `path`, embedding with a hashing model
+/// of `ndims` dimensions.
+pub(crate) async fn open_test_knowledge_base(
+    path: &str,
+    ndims: usize,
+) -> KnowledgeBase<HashingEmbeddingModel> {
+    init_sqlite_vec();
+    let conn = Connection::open(path).await.unwrap();
+    let model = HashingEmbeddingModel::new(ndims);
+    let name = model.model_name();
+    KnowledgeBase::new(conn, model, &name).await.unwrap()
+}
+
+/// In-memory knowledge base embedding with a hashing model of `ndims`
+/// dimensions.
+pub(crate) async fn test_knowledge_base(ndims: usize) -> KnowledgeBase<HashingEmbeddingModel> {
+    open_test_knowledge_base(":memory:", ndims).await
+}
+
+/// A path in the temp dir no other test uses, such as
+/// `gihun-kb-<pid>-<n>.db` for `temp_path("kb", ".db")`. Nothing is created.
+pub(crate) fn temp_path(name: &str, extension: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "gihun-{}-{}-{}-{}{}",
+        name,
+        std::process::id(),
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
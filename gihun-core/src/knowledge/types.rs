@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum Source {
     Discord,
     Telegram,
@@ -18,6 +18,7 @@ impl Source {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "discord" => Some(Source::Discord),
@@ -30,7 +31,7 @@ impl Source {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ChannelType {
     DirectMessage,
     Text,
@@ -48,6 +49,7 @@ impl ChannelType {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "direct_message" => Some(ChannelType::DirectMessage),
@@ -59,6 +61,43 @@ impl ChannelType {
     }
 }
 
+/// How the bot engages in a channel, set by an [`EngagementPolicy`](super::EngagementPolicy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EngagementMode {
+    /// Inherit the source-wide mode, or let attention decide
+    #[default]
+    Default,
+    /// Reply to every message
+    Always,
+    /// Only reply when addressed by name or mention
+    MentionOnly,
+    /// Never reply
+    Never,
+}
+
+impl EngagementMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngagementMode::Default => "default",
+            EngagementMode::Always => "always",
+            EngagementMode::MentionOnly => "mention-only",
+            EngagementMode::Never => "never",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "default" => Some(EngagementMode::Default),
+            "always" => Some(EngagementMode::Always),
+            "mention-only" => Some(EngagementMode::MentionOnly),
+            "never" => Some(EngagementMode::Never),
+            _ => None,
+        }
+    }
+}
+
 pub trait MessageMetadata {
     fn id(&self) -> String;
     fn source_id(&self) -> String;
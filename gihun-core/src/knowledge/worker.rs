@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use rig::embeddings::EmbeddingModel;
+use tracing::{debug, error, info};
+
+use super::store::KnowledgeBase;
+
+#[derive(Clone, Debug)]
+pub struct EmbeddingWorkerConfig {
+    /// Maximum number of messages embedded per request
+    pub batch_size: usize,
+    /// Time to wait between polls once the queue has been drained
+    pub poll_interval: Duration,
+}
+
+impl Default for EmbeddingWorkerConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 32,
+            poll_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Background task that embeds messages queued by
+/// [`KnowledgeBase::create_message`] so message handlers never wait on the
+/// embedding API.
+pub struct EmbeddingWorker<E: EmbeddingModel + 'static> {
+    knowledge: KnowledgeBase<E>,
+    config: EmbeddingWorkerConfig,
+}
+
+impl<E: EmbeddingModel + 'static> EmbeddingWorker<E> {
+    pub fn new(knowledge: KnowledgeBase<E>, config: EmbeddingWorkerConfig) -> Self {
+        Self { knowledge, config }
+    }
+
+    pub async fn start(&self) {
+        info!(
+            batch_size = self.config.batch_size,
+            "Starting message embedding worker"
+        );
+
+        loop {
+            self.drain().await;
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Embeds batches until the queue is empty or nothing in a batch could be
+    /// embedded.
+    async fn drain(&self) {
+        loop {
+            match self.knowledge.embed_pending_messages(self.config.batch_size).await {
+                Ok(0) => break,
+                Ok(count) => debug!(count, "Embedded queued messages"),
+                Err(err) => {
+                    error!(?err, "Failed to embed queued messages");
+                    break;
+                }
+            }
+        }
+
+        match self.knowledge.pending_embeddings().await {
+            Ok(0) => {}
+            Ok(backlog) => info!(pending_embeddings = backlog, "Message embedding backlog"),
+            Err(err) => error!(?err, "Failed to read message embedding backlog"),
+        }
+    }
+}
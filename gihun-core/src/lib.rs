@@ -13,9 +13,14 @@ pub fn init_logging() {
         .init();
 }
 
+pub mod admin;
 pub mod agent;
 pub mod attention;
 pub mod character;
 pub mod clients;
+pub mod embeddings;
+pub mod evaluation;
 pub mod knowledge;
-pub mod loaders;
\ No newline at end of file
+pub mod loaders;
+pub mod policy;
+pub mod providers;
\ No newline at end of file
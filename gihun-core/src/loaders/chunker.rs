@@ -0,0 +1,422 @@
+use std::fmt;
+
+use tiktoken_rs::CoreBPE;
+
+/// Counts tokens the way the embedding model does, so chunks stay within its
+/// input limit.
+#[derive(Clone, Copy)]
+pub enum Tokenizer {
+    /// A BPE vocabulary, such as `cl100k_base` used by OpenAI's embedding models
+    Bpe(&'static CoreBPE),
+    /// Whitespace separated words, for embedders that work on words such as
+    /// [`HashingEmbeddingModel`](crate::embeddings::HashingEmbeddingModel)
+    Words,
+}
+
+impl Tokenizer {
+    /// The `cl100k_base` vocabulary of `text-embedding-3-*` and `text-embedding-ada-002`.
+    pub fn cl100k() -> Self {
+        Self::Bpe(tiktoken_rs::cl100k_base_singleton())
+    }
+
+    /// The tokenizer of a known OpenAI model, or [`Tokenizer::Words`] for
+    /// any other model.
+    pub fn for_model(model_name: &str) -> Self {
+        tiktoken_rs::bpe_for_model(model_name)
+            .map(Self::Bpe)
+            .unwrap_or(Self::Words)
+    }
+
+    /// The tokenizer of a known OpenAI chat model, or `cl100k_base` as an
+    /// approximation for any other, since chat models don't work on words.
+    pub fn for_completion_model(model_name: &str) -> Self {
+        tiktoken_rs::bpe_for_model(model_name)
+            .map(Self::Bpe)
+            .unwrap_or_else(|_| Self::cl100k())
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Bpe(bpe) => bpe.encode_ordinary(text).len(),
+            Self::Words => text.split_whitespace().count(),
+        }
+    }
+}
+
+impl fmt::Debug for Tokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bpe(_) => f.write_str("Bpe"),
+            Self::Words => f.write_str("Words"),
+        }
+    }
+}
+
+/// Places text may be split, from the coarsest to the finest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// Blank lines
+    Paragraph,
+    Line,
+    /// After `.`, `!` or `?` followed by whitespace
+    Sentence,
+    Word,
+}
+
+impl Boundary {
+    /// Text put back between two pieces split at this boundary.
+    fn separator(&self) -> &'static str {
+        match self {
+            Self::Paragraph => "\n\n",
+            Self::Line => "\n",
+            Self::Sentence | Self::Word => " ",
+        }
+    }
+
+    fn split(&self, text: &str) -> Vec<String> {
+        match self {
+            Self::Paragraph => {
+                let mut paragraphs = Vec::new();
+                let mut lines: Vec<&str> = Vec::new();
+                for line in text.lines().chain(std::iter::once("")) {
+                    if line.trim().is_empty() {
+                        if !lines.is_empty() {
+                            paragraphs.push(lines.join("\n"));
+                            lines.clear();
+                        }
+                    } else {
+                        lines.push(line.trim_end());
+                    }
+                }
+                paragraphs
+            }
+            Self::Line => text
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.trim().is_empty())
+                .map(String::from)
+                .collect(),
+            Self::Sentence => {
+                let mut sentences = Vec::new();
+                let mut words: Vec<&str> = Vec::new();
+                for word in text.split_whitespace() {
+                    words.push(word);
+                    let end = word.trim_end_matches(['"', '\'', ')', '\u{201d}', '\u{2019}']);
+                    if end.ends_with(['.', '!', '?']) {
+                        sentences.push(words.join(" "));
+                        words.clear();
+                    }
+                }
+                if !words.is_empty() {
+                    sentences.push(words.join(" "));
+                }
+                sentences
+            }
+            Self::Word => text.split_whitespace().map(String::from).collect(),
+        }
+    }
+}
+
+/// A unit of text packed into chunks.
+#[derive(Clone, Debug)]
+pub(crate) struct Piece {
+    text: String,
+    tokens: usize,
+    /// Joins the piece to the one before it in a chunk
+    separator: &'static str,
+    /// Kept in one chunk even when it's over the limit, and never repeated
+    /// as overlap
+    whole: bool,
+}
+
+/// Splits text into chunks of at most `max_tokens` tokens.
+///
+/// Text is split at the first of `boundaries` that brings every piece under
+/// the limit, falling back to the finer ones for pieces that are still too
+/// long, and the pieces are then packed back together. Consecutive chunks
+/// share up to `overlap_tokens` of trailing text.
+#[derive(Clone, Debug)]
+pub struct Chunker {
+    pub max_tokens: usize,
+    pub overlap_tokens: usize,
+    /// Preferred split points, coarsest first
+    pub boundaries: Vec<Boundary>,
+    pub tokenizer: Tokenizer,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self::new(Tokenizer::cl100k(), 512, 64)
+    }
+}
+
+impl Chunker {
+    pub fn new(tokenizer: Tokenizer, max_tokens: usize, overlap_tokens: usize) -> Self {
+        Self {
+            max_tokens: max_tokens.max(1),
+            overlap_tokens: overlap_tokens.min(max_tokens / 2),
+            boundaries: vec![
+                Boundary::Paragraph,
+                Boundary::Line,
+                Boundary::Sentence,
+                Boundary::Word,
+            ],
+            tokenizer,
+        }
+    }
+
+    /// A chunker with the default sizes and the tokenizer of `model_name`.
+    pub fn for_model(model_name: &str) -> Self {
+        Self {
+            tokenizer: Tokenizer::for_model(model_name),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_boundaries(mut self, boundaries: &[Boundary]) -> Self {
+        self.boundaries = boundaries.to_vec();
+        self
+    }
+
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer.count(text)
+    }
+
+    pub fn chunk(&self, text: &str) -> Vec<String> {
+        self.pack(self.pieces(text, ""))
+    }
+
+    /// Packs `units`, such as the turns of a dialogue, joined with
+    /// `separator`. Units are only split when one alone is over the limit.
+    pub fn chunk_units<'a>(
+        &self,
+        units: impl IntoIterator<Item = &'a str>,
+        separator: &'static str,
+    ) -> Vec<String> {
+        let pieces = units
+            .into_iter()
+            .flat_map(|unit| self.pieces(unit, separator))
+            .collect();
+        self.pack(pieces)
+    }
+
+    /// Splits `text` into pieces under the limit, the first joined to what
+    /// comes before it with `separator`.
+    pub(crate) fn pieces(&self, text: &str, separator: &'static str) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        self.split(text.trim(), &self.boundaries, separator, &mut pieces);
+        pieces
+    }
+
+    /// A piece that is never split nor repeated, such as a code block.
+    pub(crate) fn whole(&self, text: &str, separator: &'static str) -> Piece {
+        Piece {
+            text: text.to_string(),
+            tokens: self.count_tokens(text),
+            separator,
+            whole: true,
+        }
+    }
+
+    fn split(
+        &self,
+        text: &str,
+        boundaries: &[Boundary],
+        separator: &'static str,
+        pieces: &mut Vec<Piece>,
+    ) {
+        if text.trim().is_empty() {
+            return;
+        }
+
+        let tokens = self.count_tokens(text);
+        if tokens <= self.max_tokens {
+            pieces.push(Piece {
+                text: text.to_string(),
+                tokens,
+                separator,
+                whole: false,
+            });
+            return;
+        }
+
+        match boundaries.split_first() {
+            Some((boundary, finer)) => {
+                for (i, segment) in boundary.split(text).iter().enumerate() {
+                    let separator = if i == 0 { separator } else { boundary.separator() };
+                    self.split(segment, finer, separator, pieces);
+                }
+            }
+            // No boundary left, so halve the text until it fits
+            None => {
+                let chars = text.chars().count();
+                if chars < 2 {
+                    pieces.push(Piece {
+                        text: text.to_string(),
+                        tokens,
+                        separator,
+                        whole: false,
+                    });
+                    return;
+                }
+                let middle = text
+                    .char_indices()
+                    .nth(chars / 2)
+                    .map(|(i, _)| i)
+                    .unwrap_or(text.len());
+                self.split(&text[..middle], &[], separator, pieces);
+                self.split(&text[middle..], &[], "", pieces);
+            }
+        }
+    }
+
+    /// Packs pieces into chunks, carrying the tail of each chunk over to the
+    /// next.
+    pub(crate) fn pack(&self, pieces: Vec<Piece>) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current: Vec<Piece> = Vec::new();
+        let mut has_new_content = false;
+
+        for piece in pieces {
+            if has_new_content && !self.fits(&current, &piece) {
+                chunks.push(join(&current));
+                current = self.overlap(&current);
+            }
+
+            // Overlap must not push the next piece over the limit
+            if !self.fits(&current, &piece) {
+                current.clear();
+            }
+
+            current.push(piece);
+            has_new_content = true;
+        }
+
+        if has_new_content {
+            chunks.push(join(&current));
+        }
+
+        chunks
+    }
+
+    fn fits(&self, current: &[Piece], piece: &Piece) -> bool {
+        // Each join is counted as a token of its own
+        current.is_empty()
+            || current.iter().map(|piece| piece.tokens + 1).sum::<usize>() + piece.tokens
+                <= self.max_tokens
+    }
+
+    /// Trailing pieces of a chunk that fit in the overlap, or the last words
+    /// of its final piece when no whole piece does.
+    fn overlap(&self, pieces: &[Piece]) -> Vec<Piece> {
+        if self.overlap_tokens == 0 {
+            return Vec::new();
+        }
+
+        let mut overlap = Vec::new();
+        let mut tokens = 0;
+        for piece in pieces.iter().rev() {
+            if piece.whole || tokens + piece.tokens > self.overlap_tokens {
+                break;
+            }
+            tokens += piece.tokens + 1;
+            overlap.insert(0, piece.clone());
+        }
+
+        if overlap.is_empty() {
+            if let Some(last) = pieces.last().filter(|piece| !piece.whole) {
+                if let Some(tail) = self.tail_words(&last.text) {
+                    overlap.push(tail);
+                }
+            }
+        }
+
+        overlap
+    }
+
+    /// The longest run of trailing words of `text` within the overlap.
+    fn tail_words(&self, text: &str) -> Option<Piece> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut tail = None;
+
+        for start in (0..words.len()).rev() {
+            let text = words[start..].join(" ");
+            let tokens = self.count_tokens(&text);
+            if tokens > self.overlap_tokens {
+                break;
+            }
+            tail = Some(Piece {
+                text,
+                tokens,
+                separator: "",
+                whole: false,
+            });
+        }
+
+        tail
+    }
+}
+
+fn join(pieces: &[Piece]) -> String {
+    let mut text = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        if i > 0 {
+            text.push_str(piece.separator);
+        }
+        text.push_str(&piece.text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizer_matches_model() {
+        assert!(matches!(Tokenizer::for_model("text-embedding-3-large"), Tokenizer::Bpe(_)));
+        assert!(matches!(Tokenizer::for_model("hashing-384"), Tokenizer::Words));
+        assert!(matches!(Tokenizer::for_completion_model("claude-3-5-sonnet"), Tokenizer::Bpe(_)));
+        assert_eq!(Tokenizer::cl100k().count("hello world"), 2);
+        assert_eq!(Tokenizer::Words.count(" red  light,\ngreen light "), 4);
+    }
+
+    #[test]
+    fn test_splits_at_the_coarsest_boundary_that_fits() {
+        let chunker = Chunker::new(Tokenizer::Words, 6, 0);
+        let text = "One two three.\nFour five six.\n\nSeven eight nine ten. Eleven twelve thirteen.";
+
+        assert_eq!(
+            chunker.chunk(text),
+            vec![
+                "One two three.\nFour five six.",
+                "Seven eight nine ten.",
+                "Eleven twelve thirteen.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunks_stay_within_the_token_limit() {
+        let chunker = Chunker::default();
+        let text = "Player 456 steps forward. ".repeat(200);
+
+        let chunks = chunker.chunk(&text);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunker.count_tokens(chunk) <= chunker.max_tokens));
+
+        // Without any boundary the text is halved until it fits
+        let unbroken = "x".repeat(5000);
+        let chunks = chunker.clone().with_boundaries(&[]).chunk(&unbroken);
+        assert!(chunks.iter().all(|chunk| chunker.count_tokens(chunk) <= chunker.max_tokens));
+        assert_eq!(chunks.concat(), unbroken);
+    }
+
+    #[test]
+    fn test_overlap_between_chunks() {
+        let chunker = Chunker::new(Tokenizer::Words, 10, 3);
+        let chunks = chunker.chunk_units(["one two three four five", "six seven eight nine ten"], "\n");
+
+        assert_eq!(chunks, vec!["one two three four five", "three four five\nsix seven eight nine ten"]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::epub::load_epub;
+use super::html::load_html;
+use super::json::load_json;
+use super::chunker::Chunker;
+use super::markdown::{load_markdown, MarkdownChunker};
+use super::pdf::load_pdf_flattened;
+use super::report::{file_name, read_dir_sorted, read_text, FileChunks, LoadReport};
+use crate::knowledge::Document;
+
+#[derive(Clone, Debug)]
+pub struct LoaderConfig {
+    /// Splits every file; markdown, HTML, EPUB and JSON along their headings
+    pub chunker: Chunker,
+    /// Field holding the text of JSON and JSONL records, with dots for nested fields
+    pub json_text_field: String,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            chunker: Chunker::default(),
+            json_text_field: "text".to_string(),
+        }
+    }
+}
+
+/// Loads a single file with the loader matching its extension, returning
+/// `None` for unsupported extensions.
+pub fn load_file(path: &Path, config: &LoaderConfig) -> Result<Option<Vec<String>>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    let markdown = MarkdownChunker::new(config.chunker.clone());
+    let chunks = match extension.as_str() {
+        "md" | "markdown" | "mdx" => load_markdown(path, &markdown)?,
+        "html" | "htm" | "xhtml" => load_html(path, &markdown)?,
+        "json" | "jsonl" => load_json(path, &config.json_text_field, &markdown)?,
+        "epub" => load_epub(path, &markdown)?,
+        "pdf" => load_pdf_flattened(path.to_path_buf(), &config.chunker)?,
+        "txt" => config.chunker.chunk(&read_text(path)?),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(chunks))
+}
+
+/// Load every supported file in a directory, dispatching on the extension, and return the chunks of each keyed by file name.
+///
+/// Files that fail to load are recorded in the report and skipped; only an
+/// unreadable directory is an error.
+pub fn load_dir(
+    documents_dir: &Path,
+    config: &LoaderConfig,
+) -> Result<(FileChunks, LoadReport)> {
+    let mut file_chunks = Vec::new();
+    let mut report = LoadReport::default();
+
+    for path in read_dir_sorted(documents_dir)?.into_iter().filter(|path| path.is_file()) {
+        match load_file(&path, config) {
+            Ok(Some(chunks)) if chunks.is_empty() => report.skipped(&path, "no text found"),
+            Ok(Some(chunks)) => {
+                report.loaded(&path, chunks.len());
+                file_chunks.push((file_name(&path), chunks));
+            }
+            Ok(None) => report.skipped(&path, "unsupported file type"),
+            Err(err) => report.failed(&path, &err),
+        }
+    }
+
+    Ok((file_chunks, report))
+}
+
+/// Like [`load_dir`], but returns [`Document`]s with ids `<file name>#<chunk>`,
+/// tagged with the file's extension.
+pub fn load_dir_documents(
+    documents_dir: &Path,
+    config: &LoaderConfig,
+    source_id: &str,
+) -> Result<(Vec<Document>, LoadReport)> {
+    let (file_chunks, report) = load_dir(documents_dir, config)?;
+
+    let documents = file_chunks
+        .into_iter()
+        .flat_map(|(file_name, chunks)| {
+            let extension = Path::new(&file_name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or_default()
+                .to_lowercase();
+
+            chunks.into_iter().enumerate().map(move |(i, content)| Document {
+                id: format!("{}#{}", file_name, i),
+                source_id: source_id.to_string(),
+                content,
+                created_at: chrono::Utc::now(),
+                tags: vec![extension.clone()],
+            })
+        })
+        .collect();
+
+    Ok((documents, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::temp_path;
+    use crate::loaders::report::FileStatus;
+    use std::fs;
+
+    #[test]
+    fn test_load_dir_dispatches_on_extension() {
+        let dir = temp_path("docs", "");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("rules.md"), "# Rules\n\nDon't move on red light.").unwrap();
+        fs::write(dir.join("page.html"), "<nav>Menu</nav><main><p>Green light.</p></main>").unwrap();
+        fs::write(dir.join("posts.jsonl"), "{\"body\": \"Player 456\"}\n{\"id\": 2}\n").unwrap();
+        fs::write(dir.join("image.png"), [0u8, 1, 2]).unwrap();
+        fs::write(dir.join("broken.json"), "{ not json").unwrap();
+
+        let config = LoaderConfig {
+            json_text_field: "body".to_string(),
+            ..Default::default()
+        };
+        let (documents, report) = load_dir_documents(&dir, &config, "docs").unwrap();
+        let loaded: Vec<_> = documents
+            .iter()
+            .map(|doc| (doc.id.as_str(), doc.content.as_str(), doc.tags[0].as_str()))
+            .collect();
+
+        assert_eq!(
+            loaded,
+            vec![
+                ("page.html#0", "Green light.", "html"),
+                ("posts.jsonl#0", "Player 456", "jsonl"),
+                ("rules.md#0", "Rules\n\nDon't move on red light.", "md"),
+            ]
+        );
+        assert_eq!((report.loaded_count(), report.chunks()), (3, 3));
+        assert_eq!(
+            report.files.iter().find(|file| file.path.ends_with("image.png")).unwrap().status,
+            FileStatus::Skipped { reason: "unsupported file type".to_string() }
+        );
+        assert_eq!(
+            report.failures().map(|file| file_name(&file.path)).collect::<Vec<_>>(),
+            vec!["broken.json"]
+        );
+
+        fs::remove_dir_all(dir).ok();
+    }
+}
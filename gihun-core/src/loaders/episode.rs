@@ -0,0 +1,442 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use rig::embeddings::EmbeddingModel;
+use tracing::info;
+
+use super::chunker::Chunker;
+use super::report::{read_dir_sorted, LoadReport};
+use super::transcript::{parse_turns, read_transcript, Turn};
+use crate::knowledge::{Document, KnowledgeBase, TRANSCRIPT_SOURCE};
+
+/// Speaker words that describe a role rather than name a character, so they
+/// aren't looked for as mentions in dialogue.
+const ROLE_WORDS: &[&str] = &[
+    "all", "captain", "doll", "driver", "guard", "man", "manager", "officer", "player", "voice",
+    "woman",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EpisodeId {
+    pub season: u32,
+    pub episode: u32,
+}
+
+impl EpisodeId {
+    /// Parses `s02e01` style codes from a file name, e.g. `transcripts/s02e01.pdf`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let stem = path.file_stem()?.to_str()?.to_lowercase();
+
+        stem.match_indices('s').find_map(|(i, _)| {
+            let rest = &stem[i + 1..];
+            let season_len = rest.chars().take_while(char::is_ascii_digit).count();
+            let rest = rest[season_len..].strip_prefix('e')?;
+            let episode_len = rest.chars().take_while(char::is_ascii_digit).count();
+
+            Some(Self {
+                season: stem[i + 1..i + 1 + season_len].parse().ok()?,
+                episode: rest[..episode_len].parse().ok()?,
+            })
+        })
+    }
+
+    /// Lower-case code used in document ids and tags, e.g. `s02e01`.
+    pub fn slug(&self) -> String {
+        self.to_string().to_lowercase()
+    }
+}
+
+impl fmt::Display for EpisodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S{:02}E{:02}", self.season, self.episode)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    /// 1-based position in the episode
+    pub number: usize,
+    pub turns: Vec<Turn>,
+}
+
+impl Scene {
+    /// Characters speaking in or named by the scene.
+    pub fn characters(&self, cast: &BTreeSet<String>) -> BTreeSet<String> {
+        let mut characters: BTreeSet<String> = self
+            .turns
+            .iter()
+            .map(|turn| character_name(&turn.speaker))
+            .collect();
+
+        for name in cast.iter().filter(|name| !is_role(name)) {
+            let title = title_case(name);
+            if self.turns.iter().any(|turn| contains_word(&turn.text, &title)) {
+                characters.insert(name.clone());
+            }
+        }
+
+        characters
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Episode {
+    pub id: EpisodeId,
+    pub title: Option<String>,
+    pub synopsis: Option<String>,
+    pub scenes: Vec<Scene>,
+}
+
+impl Episode {
+    /// Parses a transcript into scenes.
+    ///
+    /// Transcripts don't mark scene changes, so a music cue on its own line
+    /// such as `(ominous music playing)` is taken as the start of a new
+    /// scene. The title and synopsis are read from the header of the
+    /// transcript site the files come from, when present.
+    pub fn parse(id: EpisodeId, text: &str) -> Self {
+        let header = text
+            .find('(')
+            .map_or(text, |start| &text[..start]);
+
+        let code = id.to_string();
+        let title = header.lines().map(str::trim).find_map(|line| {
+            let rest = line.strip_prefix(&code)?.trim_start().strip_prefix('-')?;
+            Some(rest.trim().to_string()).filter(|title| !title.is_empty())
+        });
+
+        let header_lines: Vec<&str> = header
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let synopsis = header_lines
+            .iter()
+            .rposition(|line| line.ends_with("Thanks!"))
+            .map(|i| header_lines[i + 1..].join(" "))
+            .filter(|synopsis| !synopsis.is_empty());
+
+        let scenes = split_scenes(text)
+            .into_iter()
+            .map(parse_turns)
+            .filter(|turns| !turns.is_empty())
+            .enumerate()
+            .map(|(i, turns)| Scene {
+                number: i + 1,
+                turns,
+            })
+            .collect();
+
+        Self {
+            id,
+            title,
+            synopsis,
+            scenes,
+        }
+    }
+
+    /// Every speaker in the episode, normalised with [`character_name`].
+    pub fn cast(&self) -> BTreeSet<String> {
+        self.scenes
+            .iter()
+            .flat_map(|scene| &scene.turns)
+            .map(|turn| character_name(&turn.speaker))
+            .collect()
+    }
+
+    /// `S02E01 "Bread and Lottery"`, used to cite the episode.
+    pub fn heading(&self) -> String {
+        match &self.title {
+            Some(title) => format!("{} \"{}\"", self.id, title),
+            None => self.id.to_string(),
+        }
+    }
+
+    /// A synopsis document plus one or more documents per scene, scenes
+    /// over the chunker's limit being split between turns.
+    ///
+    /// Episode, season, scene and the characters involved are recorded as
+    /// tags (`episode:s02e01`, `season:2`, `scene:3`, `character:gi-hun`) so
+    /// retrieval can be filtered on them, and each document starts with the
+    /// episode heading so it can be cited.
+    pub fn documents(&self, chunker: &Chunker) -> Vec<Document> {
+        let slug = self.id.slug();
+        let heading = self.heading();
+        let episode_tags = vec![
+            "transcript".to_string(),
+            format!("episode:{}", slug),
+            format!("season:{}", self.id.season),
+        ];
+        let document = |id: String, content: String, tags: Vec<String>| Document {
+            id,
+            source_id: TRANSCRIPT_SOURCE.to_string(),
+            content,
+            created_at: chrono::Utc::now(),
+            tags,
+        };
+
+        let mut documents = Vec::new();
+
+        if let Some(synopsis) = &self.synopsis {
+            let mut tags = episode_tags.clone();
+            tags.push("summary".to_string());
+            documents.push(document(
+                format!("{}-summary", slug),
+                format!("{} synopsis: {}", heading, synopsis),
+                tags,
+            ));
+        }
+
+        let cast = self.cast();
+        for scene in &self.scenes {
+            let mut tags = episode_tags.clone();
+            tags.push(format!("scene:{}", scene.number));
+            tags.extend(
+                scene
+                    .characters(&cast)
+                    .into_iter()
+                    .map(|name| format!("character:{}", name)),
+            );
+
+            let header = format!("{}, scene {}", heading, scene.number);
+            let lines: Vec<String> = scene
+                .turns
+                .iter()
+                .map(|turn| format!("{}: {}", turn.speaker, turn.text))
+                .collect();
+            let parts = chunker.chunk_units(lines.iter().map(String::as_str), "\n");
+            for (part, content) in parts.into_iter().enumerate() {
+                documents.push(document(
+                    format!("{}-scene-{}-{}", slug, scene.number, part),
+                    format!("{}\n{}", header, content),
+                    tags.clone(),
+                ));
+            }
+        }
+
+        documents
+    }
+}
+
+/// Parses an episode transcript, or returns `None` when the file name has
+/// no `sXXeYY` code.
+pub fn load_episode(path: &Path) -> Result<Option<Episode>> {
+    let Some(id) = EpisodeId::from_path(path) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Episode::parse(id, &read_transcript(path)?)))
+}
+
+/// Adds every episode transcript in `dir` to the knowledge base.
+///
+/// Files whose size and modification time match the last ingestion are
+/// skipped; changed files replace their previous documents. A transcript
+/// that can't be read is recorded in the report and the rest are still
+/// ingested.
+pub async fn ingest_episodes<E: EmbeddingModel>(
+    dir: &Path,
+    knowledge: &mut KnowledgeBase<E>,
+    chunker: &Chunker,
+) -> Result<LoadReport> {
+    let mut report = LoadReport::default();
+
+    for path in read_dir_sorted(dir)? {
+        if !path.extension().is_some_and(|ext| ext == "pdf" || ext == "txt") {
+            continue;
+        }
+        let Some(id) = EpisodeId::from_path(&path) else {
+            report.skipped(&path, "no episode code in the file name");
+            continue;
+        };
+
+        let metadata = fs::metadata(&path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let cursor_key = format!("{}:{}", TRANSCRIPT_SOURCE, id.slug());
+        let cursor = format!("{} {}", metadata.len(), modified);
+
+        let synced = knowledge
+            .get_sync_cursor(&cursor_key)
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+        if synced.as_deref() == Some(cursor.as_str()) {
+            report.skipped(&path, "unchanged since the last ingestion");
+            continue;
+        }
+
+        let text = match read_transcript(&path) {
+            Ok(text) => text,
+            Err(err) => {
+                report.failed(&path, &err);
+                continue;
+            }
+        };
+        let episode = Episode::parse(id, &text);
+        let documents = episode.documents(chunker);
+        let chunks = documents.len();
+        info!(
+            episode = %id,
+            scenes = episode.scenes.len(),
+            documents = documents.len(),
+            "Ingesting episode transcript"
+        );
+
+        knowledge
+            .delete_documents(TRANSCRIPT_SOURCE, &format!("{}-", id.slug()))
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+        if !documents.is_empty() {
+            knowledge.add_documents(documents).await?;
+        }
+        knowledge
+            .set_sync_cursor(&cursor_key, &cursor)
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+
+        report.loaded(&path, chunks);
+    }
+
+    Ok(report)
+}
+
+/// Splits a transcript at stand-alone music cues.
+fn split_scenes(text: &str) -> Vec<&str> {
+    let mut scenes = Vec::new();
+    let mut scene_start = 0;
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find('(').map(|i| offset + i) {
+        let Some(end) = text[start..].find(')').map(|i| start + i + 1) else {
+            break;
+        };
+
+        let line_start = text[..start].rsplit('\n').next().unwrap_or_default();
+        let direction = text[start..end].to_lowercase();
+        if line_start.trim().is_empty()
+            && direction.contains("music")
+            && !direction.contains("continues")
+        {
+            scenes.push(&text[scene_start..start]);
+            scene_start = start;
+        }
+        offset = end;
+    }
+    scenes.push(&text[scene_start..]);
+
+    scenes
+}
+
+/// Normalises a speaker cue to a character name, dropping delivery notes:
+/// `FRONT MAN ON PHONE` becomes `front man`, `NAM-DU GROANING` becomes `nam-du`.
+pub fn character_name(speaker: &str) -> String {
+    let speaker = speaker.to_lowercase();
+    let speaker = speaker.split(" on ").next().unwrap_or_default();
+
+    let mut words: Vec<&str> = speaker.split_whitespace().collect();
+    while words.len() > 1 && words.last().is_some_and(|word| word.ends_with("ing")) {
+        words.pop();
+    }
+
+    words.join(" ")
+}
+
+fn is_role(name: &str) -> bool {
+    name.split(|c: char| c.is_whitespace() || c.is_ascii_digit())
+        .any(|word| ROLE_WORDS.contains(&word))
+}
+
+/// `gi-hun` becomes `Gi-hun`, matching how names are written in dialogue.
+fn title_case(name: &str) -> String {
+    name.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '-';
+
+    text.match_indices(word).any(|(i, _)| {
+        !text[..i].chars().next_back().is_some_and(is_name_char)
+            && !text[i + word.len()..].chars().next().is_some_and(is_name_char)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = "TV Show Transcripts\nS02E01 - BREAD AND LOTTERY\nDonate\nThanks!\n\
+        A vengeful Gi-hun makes a U-turn\nat the airport.\n( \ntense music playing\n )\n\
+        FRONT MAN ON PHONE\nYou have nowhere to go.\nGI-HUN\nShow yourself.\n\
+        ( \nominous music playing\n )\nWOO-SEOK\nGi-hun, we found him.\n\
+        ( \nmusic continues\n )\nMAN 1\nWho?";
+
+    #[test]
+    fn test_episode_id_from_path() {
+        assert_eq!(
+            EpisodeId::from_path(Path::new("transcripts/s02e01.pdf")),
+            Some(EpisodeId { season: 2, episode: 1 })
+        );
+        assert_eq!(
+            EpisodeId::from_path(Path::new("Squid Game S1E10 final.txt")).map(|id| id.slug()),
+            Some("s01e10".to_string())
+        );
+        assert_eq!(EpisodeId::from_path(Path::new("transcripts/information.pdf")), None);
+    }
+
+    #[test]
+    fn test_parse_episode_scenes() {
+        let episode = Episode::parse(EpisodeId { season: 2, episode: 1 }, TRANSCRIPT);
+
+        assert_eq!(episode.heading(), "S02E01 \"BREAD AND LOTTERY\"");
+        assert_eq!(
+            episode.synopsis.as_deref(),
+            Some("A vengeful Gi-hun makes a U-turn at the airport.")
+        );
+        assert_eq!(episode.scenes.len(), 2);
+        assert_eq!(episode.scenes[0].turns.len(), 2);
+        assert_eq!(episode.scenes[1].turns.len(), 2);
+
+        let cast = episode.cast();
+        assert_eq!(
+            episode.scenes[1].characters(&cast).into_iter().collect::<Vec<_>>(),
+            ["gi-hun", "man 1", "woo-seok"]
+        );
+    }
+
+    #[test]
+    fn test_episode_documents_are_tagged() {
+        let documents = Episode::parse(EpisodeId { season: 2, episode: 1 }, TRANSCRIPT)
+            .documents(&Chunker::default());
+        let ids: Vec<_> = documents.iter().map(|doc| doc.id.as_str()).collect();
+        assert_eq!(ids, ["s02e01-summary", "s02e01-scene-1-0", "s02e01-scene-2-0"]);
+
+        let scene = &documents[1];
+        assert!(scene.content.starts_with("S02E01 \"BREAD AND LOTTERY\", scene 1\n"));
+        for tag in ["episode:s02e01", "season:2", "scene:1", "character:front man", "character:gi-hun"] {
+            assert!(scene.tags.contains(&tag.to_string()), "missing {}", tag);
+        }
+    }
+
+    #[test]
+    fn test_character_name_drops_delivery_notes() {
+        assert_eq!(character_name("FRONT MAN ON PHONE"), "front man");
+        assert_eq!(character_name("NAM-DU GROANING"), "nam-du");
+        assert_eq!(character_name("GI-HUN"), "gi-hun");
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use zip::ZipArchive;
+
+use super::html::html_to_text;
+use super::markdown::{MarkdownChunk, MarkdownChunker};
+
+/// Loads the chapters of an EPUB in reading order as markdown chunks, each
+/// prefixed with its heading path.
+pub fn load_epub(path: &Path, chunker: &MarkdownChunker) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(File::open(path)?).context("Not a zip archive")?;
+
+    let mut chunks = Vec::new();
+    for chapter in spine(&mut archive)? {
+        let html = read_entry(&mut archive, &chapter)?;
+        chunks.extend(
+            chunker
+                .chunk(&html_to_text(&html))
+                .iter()
+                .map(MarkdownChunk::with_breadcrumb),
+        );
+    }
+
+    Ok(chunks)
+}
+
+/// Paths of the content documents listed in the package's spine.
+fn spine<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<String>> {
+    let container = read_entry(archive, "META-INF/container.xml")?;
+    let container = roxmltree::Document::parse(&container).context("Invalid container.xml")?;
+    let package_path = container
+        .descendants()
+        .find(|node| node.tag_name().name() == "rootfile")
+        .and_then(|node| node.attribute("full-path"))
+        .context("No rootfile in container.xml")?
+        .to_string();
+
+    let package = read_entry(archive, &package_path)?;
+    let package = roxmltree::Document::parse(&package).context("Invalid package document")?;
+    let base = package_path
+        .rsplit_once('/')
+        .map(|(dir, _)| format!("{}/", dir))
+        .unwrap_or_default();
+
+    let href = |id: &str| {
+        package
+            .descendants()
+            .filter(|node| node.tag_name().name() == "item")
+            .find(|node| node.attribute("id") == Some(id))
+            .and_then(|node| node.attribute("href"))
+    };
+
+    Ok(package
+        .descendants()
+        .filter(|node| node.tag_name().name() == "itemref")
+        .filter_map(|node| node.attribute("idref"))
+        .filter_map(href)
+        .map(|href| format!("{}{}", base, href.trim_start_matches("./")))
+        .collect())
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String> {
+    let mut entry = archive
+        .by_name(name)
+        .with_context(|| format!("Missing {} in EPUB", name))?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::temp_path;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_load_epub_in_spine_order() {
+        let path = temp_path("epub", ".epub");
+
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let files = [
+            (
+                "META-INF/container.xml",
+                r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles>
+                   <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+                   </rootfiles></container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<package xmlns="http://www.idpf.org/2007/opf"><manifest>
+                   <item id="one" href="one.xhtml" media-type="application/xhtml+xml"/>
+                   <item id="two" href="text/two.xhtml" media-type="application/xhtml+xml"/>
+                   </manifest><spine><itemref idref="two"/><itemref idref="one"/></spine></package>"#,
+            ),
+            ("OEBPS/one.xhtml", "<html><body><h1>Chapter One</h1><p>The recruiter.</p></body></html>"),
+            ("OEBPS/text/two.xhtml", "<html><body><h1>Prologue</h1><p>A business card.</p></body></html>"),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let chunks = load_epub(&path, &MarkdownChunker::default()).unwrap();
+        assert_eq!(
+            chunks,
+            vec!["Prologue\n\nA business card.", "Chapter One\n\nThe recruiter."]
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -1,8 +1,24 @@
-use git2::{FetchOptions, RemoteCallbacks, Repository};
+use git2::{Delta, FetchOptions, Oid, RemoteCallbacks, Repository};
+use rig::embeddings::EmbeddingModel;
 use rig::loaders::{file::FileLoaderError, FileLoader};
-use std::path::PathBuf;
+use rig_sqlite::SqliteError;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+use super::chunker::{Boundary, Chunker};
+use super::markdown::{MarkdownChunk, MarkdownChunker};
+use crate::knowledge::{Document, KnowledgeBase};
+
+/// Branch checked out when no reference is configured
+pub const DEFAULT_REFERENCE: &str = "main";
+
+/// Files larger than this are assumed to be generated or binary and skipped
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Split points for files other than markdown, which are mostly code
+const CODE_BOUNDARIES: &[Boundary] = &[Boundary::Paragraph, Boundary::Line, Boundary::Word];
 
 #[derive(Error, Debug)]
 pub enum GitLoaderError {
@@ -14,10 +30,30 @@ pub enum GitLoaderError {
 
     #[error("File loader error: {0}")]
     FileLoaderError(#[from] FileLoaderError),
+
+    #[error("Invalid glob pattern: {0}")]
+    PatternError(#[from] glob::PatternError),
+
+    #[error("Reference not found: {0}")]
+    ReferenceNotFound(String),
+
+    #[error("Knowledge base error: {0:?}")]
+    StoreError(SqliteError),
+
+    #[error("Embedding error: {0}")]
+    EmbeddingError(#[from] anyhow::Error),
+}
+
+// `SqliteError` doesn't implement `std::error::Error`, so it can't use `#[from]`
+impl From<SqliteError> for GitLoaderError {
+    fn from(err: SqliteError) -> Self {
+        GitLoaderError::StoreError(err)
+    }
 }
 
 pub struct GitRepo {
     url: String,
+    reference: String,
     pub(crate) path: PathBuf,
     pub(crate) base_path: PathBuf,
 }
@@ -29,19 +65,36 @@ impl GitRepo {
         let path = base_path.join(org).join(repo);
         Self {
             url,
+            reference: DEFAULT_REFERENCE.to_string(),
             base_path,
             path,
         }
     }
 
+    /// Checks out `reference` instead of `main`. It may be a branch, a tag or
+    /// a commit id.
+    pub fn with_reference(mut self, reference: &str) -> Self {
+        self.reference = reference.to_string();
+        self
+    }
+
+    /// `org/repo` part of the URL.
+    pub fn name(&self) -> String {
+        let parts: Vec<&str> = self.url.trim_end_matches(".git").split('/').collect();
+        format!("{}/{}", parts[parts.len() - 2], parts[parts.len() - 1])
+    }
+
     pub fn sync(&self) -> Result<Repository, GitLoaderError> {
-        if self.path.exists() {
+        let repo = if self.path.exists() {
             info!(path = ?self.path, "Repository path exists, updating");
-            self.reset()
+            self.reset()?
         } else {
             info!(path = ?self.path, "Repository path does not exist, cloning");
-            self.clone()
-        }
+            self.clone()?
+        };
+
+        self.checkout(&repo)?;
+        Ok(repo)
     }
 
     fn clone(&self) -> Result<Repository, GitLoaderError> {
@@ -58,41 +111,140 @@ impl GitRepo {
             let callbacks = RemoteCallbacks::new();
             let mut fetch_options = FetchOptions::new();
             fetch_options.remote_callbacks(callbacks);
-            remote.fetch(&["main"], Some(&mut fetch_options), None)?;
-
-            let main_ref = repo.find_reference("refs/remotes/origin/main")?;
-            let main_commit = main_ref.peel_to_commit()?;
-
-            let mut checkout_builder = git2::build::CheckoutBuilder::new();
-
-            repo.reset(
-                main_commit.as_object(),
-                git2::ResetType::Hard,
-                Some(&mut checkout_builder),
+            remote.fetch(
+                &[
+                    "+refs/heads/*:refs/remotes/origin/*",
+                    "+refs/tags/*:refs/tags/*",
+                ],
+                Some(&mut fetch_options),
+                None,
             )?;
         }
 
         Ok(repo)
     }
+
+    /// Detaches HEAD at the configured reference and resets the worktree to it.
+    fn checkout(&self, repo: &Repository) -> Result<(), GitLoaderError> {
+        let commit = [
+            format!("refs/remotes/origin/{}", self.reference),
+            format!("refs/tags/{}", self.reference),
+        ]
+        .iter()
+        .find_map(|name| repo.find_reference(name).ok())
+        .map(|reference| reference.peel_to_commit())
+        .or_else(|| {
+            repo.revparse_single(&self.reference)
+                .ok()
+                .map(|object| object.peel_to_commit())
+        })
+        .ok_or_else(|| GitLoaderError::ReferenceNotFound(self.reference.clone()))??;
+
+        debug!(reference = %self.reference, commit = %commit.id(), "Checking out reference");
+
+        let mut checkout_builder = git2::build::CheckoutBuilder::new();
+        repo.set_head_detached(commit.id())?;
+        repo.reset(
+            commit.as_object(),
+            git2::ResetType::Hard,
+            Some(&mut checkout_builder),
+        )?;
+
+        Ok(())
+    }
 }
 
-pub struct GitLoader<'a> {
-    path: &'a str,
+/// Include and exclude globs matched against paths relative to the repo root.
+/// With no include patterns every file is included.
+#[derive(Clone, Debug, Default)]
+pub struct GitFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl GitFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, GitLoaderError> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| glob::Pattern::new(pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(path)))
+            && !self.exclude.iter().any(|p| p.matches_path(path))
+    }
+
+    /// Identifies the filter in the sync cursor, so changing the globs
+    /// triggers a full re-ingestion.
+    fn fingerprint(&self) -> String {
+        let join = |patterns: &[glob::Pattern]| {
+            patterns
+                .iter()
+                .map(|p| p.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!("{};{}", join(&self.include), join(&self.exclude))
+    }
+}
+
+/// Files that differ between two commits, relative to the repo root.
+#[derive(Debug, Default, PartialEq)]
+pub struct GitChanges {
+    pub changed: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+}
+
+pub struct GitLoader {
     repo: GitRepo,
+    chunker: Chunker,
 }
 
-impl<'a> GitLoader<'a> {
-    pub fn new(url: String, path: &'a str) -> Result<Self, GitLoaderError> {
-        debug!(url = %url, path = path, "Creating new GitLoader");
-        let repo = GitRepo::new(url, PathBuf::from(path));
+impl GitLoader {
+    pub fn new(url: String, path: &str) -> Result<Self, GitLoaderError> {
+        Self::new_with_reference(url, path, DEFAULT_REFERENCE)
+    }
+
+    /// Clones or updates the repository and checks out `reference`.
+    pub fn new_with_reference(
+        url: String,
+        path: &str,
+        reference: &str,
+    ) -> Result<Self, GitLoaderError> {
+        debug!(url = %url, path = path, reference, "Creating new GitLoader");
+        let repo = GitRepo::new(url, PathBuf::from(path)).with_reference(reference);
         repo.sync()?;
-        Ok(Self { path, repo })
+        Ok(Self {
+            repo,
+            chunker: Chunker::default(),
+        })
+    }
+
+    /// Sets the chunker used to split files into documents.
+    pub fn with_chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// `source_id` given to documents ingested from this repository.
+    pub fn source_id(&self) -> String {
+        format!("github:{}", self.repo.name())
     }
 
     pub fn with_root(
         self,
-    ) -> Result<FileLoader<'a, Result<PathBuf, FileLoaderError>>, FileLoaderError> {
-        FileLoader::with_dir(self.path)
+    ) -> Result<FileLoader<'static, Result<PathBuf, FileLoaderError>>, FileLoaderError> {
+        let path = Box::leak(self.repo.path.to_str().unwrap().to_string().into_boxed_str());
+
+        FileLoader::with_dir(path)
     }
 
     /// Creates a new [FileLoader] using a glob pattern to match files.
@@ -100,7 +252,7 @@ impl<'a> GitLoader<'a> {
     /// # Example
     /// Create a [FileLoader] for all `.txt` files that match the glob "files/*.txt".
     ///
-    /// ```rust
+    /// ```rust,no_run
 	/// use rig::loaders::FileLoader;
     /// let loader = FileLoader::with_glob("files/*.txt").unwrap();
     /// ```
@@ -120,14 +272,14 @@ impl<'a> GitLoader<'a> {
     /// # Example
     /// Create a [FileLoader] for all files that are in the directory "files" (ignores subdirectories).
     ///
-    /// ```rust
+    /// ```rust,no_run
 	/// use rig::loaders::FileLoader;
     /// let loader = FileLoader::with_dir("files").unwrap();
     /// ```
     pub fn with_dir(
         self,
         directory: &str,
-    ) -> Result<FileLoader<'a, Result<PathBuf, FileLoaderError>>, FileLoaderError> {
+    ) -> Result<FileLoader<'static, Result<PathBuf, FileLoaderError>>, FileLoaderError> {
         let path = Box::leak(
             self.repo
                 .path
@@ -140,4 +292,295 @@ impl<'a> GitLoader<'a> {
 
         FileLoader::with_dir(path)
     }
+
+    /// Id of the checked out commit.
+    pub fn head_commit(&self) -> Result<String, GitLoaderError> {
+        let repo = Repository::open(&self.repo.path)?;
+        let commit = repo.head()?.peel_to_commit()?;
+        Ok(commit.id().to_string())
+    }
+
+    /// Files in the checked out tree matching `filter`, sorted.
+    pub fn files(&self, filter: &GitFilter) -> Result<Vec<PathBuf>, GitLoaderError> {
+        let mut files = Vec::new();
+
+        for entry in WalkDir::new(&self.repo.path)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+        {
+            let entry = entry.map_err(std::io::Error::from)?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let path = entry
+                .path()
+                .strip_prefix(&self.repo.path)
+                .unwrap_or(entry.path())
+                .to_path_buf();
+            if filter.matches(&path) {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+
+    /// Files changed between `commit` and the checked out commit.
+    pub fn changes_since(&self, commit: &str) -> Result<GitChanges, GitLoaderError> {
+        let repo = Repository::open(&self.repo.path)?;
+        let old_tree = repo.find_commit(Oid::from_str(commit)?)?.tree()?;
+        let new_tree = repo.head()?.peel_to_commit()?.tree()?;
+        let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+
+        let mut changes = GitChanges::default();
+        for delta in diff.deltas() {
+            let old_path = delta.old_file().path().map(Path::to_path_buf);
+            let new_path = delta.new_file().path().map(Path::to_path_buf);
+
+            match delta.status() {
+                Delta::Deleted => changes.deleted.extend(old_path),
+                Delta::Renamed => {
+                    changes.deleted.extend(old_path);
+                    changes.changed.extend(new_path);
+                }
+                _ => changes.changed.extend(new_path),
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Adds the repository's files matching `filter` to `knowledge`.
+    ///
+    /// The ingested commit is recorded in the knowledge base, so subsequent
+    /// runs only re-ingest files changed since then. Changing the filter or
+    /// losing the previous commit (e.g. after a force push) falls back to
+    /// re-ingesting everything. Returns the number of files ingested.
+    pub async fn ingest<E: EmbeddingModel>(
+        &self,
+        knowledge: &mut KnowledgeBase<E>,
+        filter: &GitFilter,
+    ) -> Result<usize, GitLoaderError> {
+        let source_id = self.source_id();
+        let cursor_key = format!("{}@{}", source_id, self.repo.reference);
+        let commit = self.head_commit()?;
+        let cursor = format!("{} {}", commit, filter.fingerprint());
+
+        let previous = knowledge.get_sync_cursor(&cursor_key).await?;
+        if previous.as_deref() == Some(cursor.as_str()) {
+            info!(source_id, commit, "Repository already ingested");
+            return Ok(0);
+        }
+
+        let changes = previous
+            .as_deref()
+            .and_then(|previous| previous.split_once(' '))
+            .filter(|(_, fingerprint)| *fingerprint == filter.fingerprint())
+            .and_then(|(previous_commit, _)| match self.changes_since(previous_commit) {
+                Ok(changes) => Some(changes),
+                Err(err) => {
+                    warn!(?err, previous_commit, "Failed to diff against last synced commit");
+                    None
+                }
+            });
+
+        let files = match changes {
+            Some(changes) => {
+                info!(
+                    source_id,
+                    commit,
+                    changed = changes.changed.len(),
+                    deleted = changes.deleted.len(),
+                    "Ingesting repository changes"
+                );
+                for path in changes.deleted.iter().chain(&changes.changed) {
+                    knowledge
+                        .delete_documents(&source_id, &document_prefix(path))
+                        .await?;
+                }
+                changes
+                    .changed
+                    .into_iter()
+                    .filter(|path| filter.matches(path))
+                    .collect()
+            }
+            None => {
+                info!(source_id, commit, "Ingesting repository");
+                knowledge.delete_documents(&source_id, "").await?;
+                self.files(filter)?
+            }
+        };
+
+        let markdown = MarkdownChunker::new(self.chunker.clone());
+        let code = self.chunker.clone().with_boundaries(CODE_BOUNDARIES);
+
+        let mut documents = Vec::new();
+        let mut ingested = 0;
+        for path in &files {
+            let Some(content) = self.read_text(path)? else {
+                continue;
+            };
+
+            let chunks = if is_markdown(path) {
+                markdown
+                    .chunk(&content)
+                    .iter()
+                    .map(MarkdownChunk::with_breadcrumb)
+                    .collect()
+            } else {
+                code.chunk(&content)
+            };
+
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                documents.push(Document {
+                    id: format!("{}{}", document_prefix(path), i),
+                    source_id: source_id.clone(),
+                    content: chunk,
+                    created_at: chrono::Utc::now(),
+                    tags: vec!["github".to_string()],
+                });
+            }
+            ingested += 1;
+        }
+
+        if !documents.is_empty() {
+            knowledge.add_documents(documents).await?;
+        }
+        knowledge.set_sync_cursor(&cursor_key, &cursor).await?;
+
+        info!(source_id, commit, files = ingested, "Repository ingested");
+        Ok(ingested)
+    }
+
+    /// Reads a file from the worktree, returning `None` for files that are
+    /// too large, not UTF-8 or empty.
+    fn read_text(&self, path: &Path) -> Result<Option<String>, GitLoaderError> {
+        let full_path = self.repo.path.join(path);
+        if std::fs::metadata(&full_path)?.len() > MAX_FILE_SIZE {
+            debug!(?path, "Skipping large file");
+            return Ok(None);
+        }
+
+        match String::from_utf8(std::fs::read(&full_path)?) {
+            Ok(content) if !content.trim().is_empty() => Ok(Some(content)),
+            Ok(_) => Ok(None),
+            Err(_) => {
+                debug!(?path, "Skipping binary file");
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "md" || ext == "mdx" || ext == "markdown")
+}
+
+/// Documents of a file have ids `<path>#<chunk>`.
+fn document_prefix(path: &Path) -> String {
+    format!("{}#", path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::temp_path;
+
+    fn temp_dir() -> PathBuf {
+        let dir = temp_path("git", "");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"].iter(), None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_filter_matches_relative_paths() {
+        let filter = GitFilter::new(
+            &["**/*.md".to_string()],
+            &["drafts/**".to_string()],
+        )
+        .unwrap();
+        assert!(filter.matches(Path::new("README.md")));
+        assert!(filter.matches(Path::new("docs/guide/intro.md")));
+        assert!(!filter.matches(Path::new("drafts/todo.md")));
+        assert!(!filter.matches(Path::new("src/main.rs")));
+        assert!(GitFilter::default().matches(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn test_sync_reference_and_changes() {
+        let dir = temp_dir();
+        let origin_path = dir.join("origin").join("org").join("repo");
+        let mut options = git2::RepositoryInitOptions::new();
+        options.initial_head("main");
+        let origin = Repository::init_opts(&origin_path, &options).unwrap();
+
+        std::fs::write(origin_path.join("README.md"), "# Docs").unwrap();
+        std::fs::write(origin_path.join("old.md"), "old").unwrap();
+        let first = commit_all(&origin, "first");
+        origin
+            .tag_lightweight("v1", &origin.find_object(first, None).unwrap(), false)
+            .unwrap();
+
+        let url = origin_path.to_str().unwrap().to_string();
+        let clone_path = dir.join("clones");
+        let clone_path = clone_path.to_str().unwrap();
+
+        let loader = GitLoader::new(url.clone(), clone_path).unwrap();
+        assert_eq!(loader.source_id(), "github:org/repo");
+        assert_eq!(loader.head_commit().unwrap(), first.to_string());
+
+        std::fs::write(origin_path.join("README.md"), "# Docs\nUpdated").unwrap();
+        std::fs::remove_file(origin_path.join("old.md")).unwrap();
+        std::fs::write(origin_path.join("new.md"), "new").unwrap();
+        let second = commit_all(&origin, "second");
+
+        let loader = GitLoader::new(url.clone(), clone_path).unwrap();
+        assert_eq!(loader.head_commit().unwrap(), second.to_string());
+        assert_eq!(
+            loader.files(&GitFilter::default()).unwrap(),
+            vec![PathBuf::from("README.md"), PathBuf::from("new.md")]
+        );
+        assert_eq!(
+            loader.changes_since(&first.to_string()).unwrap(),
+            GitChanges {
+                changed: vec![PathBuf::from("README.md"), PathBuf::from("new.md")],
+                deleted: vec![PathBuf::from("old.md")],
+            }
+        );
+
+        let tagged = GitLoader::new_with_reference(url, clone_path, "v1").unwrap();
+        assert_eq!(tagged.head_commit().unwrap(), first.to_string());
+        assert!(tagged.files(&GitFilter::default()).unwrap().contains(&PathBuf::from("old.md")));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
 }
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use scraper::{ElementRef, Html, Node, Selector};
+
+use super::markdown::{MarkdownChunk, MarkdownChunker};
+
+/// Elements that never hold the readable content of a page
+const BOILERPLATE: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "iframe", "form", "button", "nav",
+    "header", "footer", "aside",
+];
+
+/// Elements rendered on lines of their own
+const BLOCKS: &[&str] = &[
+    "p", "div", "section", "article", "main", "ul", "ol", "li", "table", "tr", "blockquote",
+    "figure", "figcaption", "dl", "dt", "dd", "br", "hr",
+];
+
+/// Extracts the readable text of an HTML page as lightweight markdown.
+///
+/// The page's `<article>` or `<main>` is used when there is one, navigation,
+/// headers, footers, scripts and forms are dropped, headings become `#`
+/// lines and `<pre>` blocks become fenced code, so the result can be split
+/// with [`MarkdownChunker`].
+pub fn html_to_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+
+    let root = ["article", "main", "[role=main]", "body"]
+        .iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| document.select(&selector).next())
+        .unwrap_or_else(|| document.root_element());
+
+    let mut out = String::new();
+    render(root, &mut out);
+
+    // Collapse the blank lines left behind by nested blocks
+    let mut text = String::new();
+    let mut blank = true;
+    for line in out.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            if !blank {
+                text.push('\n');
+            }
+            blank = true;
+        } else {
+            text.push_str(line);
+            text.push('\n');
+            blank = false;
+        }
+    }
+
+    text.trim().to_string()
+}
+
+/// Loads an HTML file as markdown chunks, each prefixed with its heading path.
+pub fn load_html(path: &Path, chunker: &MarkdownChunker) -> Result<Vec<String>> {
+    let html = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+
+    Ok(chunker
+        .chunk(&html_to_text(&html))
+        .iter()
+        .map(MarkdownChunk::with_breadcrumb)
+        .collect())
+}
+
+fn render(element: ElementRef, out: &mut String) {
+    let name = element.value().name();
+    if BOILERPLATE.contains(&name) {
+        return;
+    }
+
+    if let Some(level) = name
+        .strip_prefix('h')
+        .and_then(|level| level.parse::<usize>().ok())
+        .filter(|level| (1..=6).contains(level))
+    {
+        let title = collapse_whitespace(&element.text().collect::<String>());
+        if !title.is_empty() {
+            out.push_str(&format!("\n\n{} {}\n\n", "#".repeat(level), title));
+        }
+        return;
+    }
+
+    if name == "pre" {
+        out.push_str(&format!(
+            "\n\n```\n{}\n```\n\n",
+            element.text().collect::<String>().trim_end()
+        ));
+        return;
+    }
+
+    let block = BLOCKS.contains(&name);
+    if block {
+        out.push_str("\n\n");
+    }
+    if name == "li" {
+        out.push_str("- ");
+    }
+
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                // Keep whitespace at node edges so `<b>a</b> b` and
+                // `<b>a</b>.` both render as written
+                let collapsed = collapse_whitespace(text);
+                if text.starts_with(char::is_whitespace) && !out.ends_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+                out.push_str(&collapsed);
+                if !collapsed.is_empty() && text.ends_with(char::is_whitespace) {
+                    out.push(' ');
+                }
+            }
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    render(child, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if block {
+        out.push_str("\n\n");
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_strips_boilerplate() {
+        let html = r#"<html><head><title>t</title><style>p { color: red }</style></head>
+            <body>
+              <nav><a href="/">Home</a></nav>
+              <article>
+                <h1>Red Light, <em>Green Light</em></h1>
+                <p>The doll  turns
+                   around.</p>
+                <ul><li>Stop</li><li>Go</li></ul>
+                <pre>let x = 1;
+let y = 2;</pre>
+                <script>track()</script>
+              </article>
+              <footer>Copyright</footer>
+            </body></html>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "# Red Light, Green Light\n\nThe doll turns around.\n\n- Stop\n\n- Go\n\n```\nlet x = 1;\nlet y = 2;\n```"
+        );
+    }
+
+    #[test]
+    fn test_body_is_used_without_article() {
+        let html = "<body><header>Site</header><div>Content <b>here</b></div></body>";
+        assert_eq!(html_to_text(html), "Content here");
+    }
+}
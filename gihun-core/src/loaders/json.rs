@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::markdown::MarkdownChunker;
+use super::report::read_text;
+
+/// Loads the text of each record in a `.json` or `.jsonl` file.
+///
+/// A `.json` file may hold a single record or an array of them, a `.jsonl`
+/// file one record per line. `text_field` names the field holding the text,
+/// with dots for nested fields (`post.body`); a record that is itself a
+/// string is used as is. Records without the field are skipped, and long
+/// texts are split with `chunker`.
+pub fn load_json(path: &Path, text_field: &str, chunker: &MarkdownChunker) -> Result<Vec<String>> {
+    let content = read_text(path)?;
+
+    let records: Vec<Value> = if path.extension().is_some_and(|ext| ext == "jsonl") {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| format!("Invalid JSON on line {}", i + 1))
+            })
+            .collect::<Result<_>>()?
+    } else {
+        match serde_json::from_str(&content).context("Invalid JSON")? {
+            Value::Array(records) => records,
+            record => vec![record],
+        }
+    };
+
+    Ok(records
+        .iter()
+        .filter_map(|record| record_text(record, text_field))
+        .flat_map(|text| chunker.chunk(&text))
+        .map(|chunk| chunk.content)
+        .collect())
+}
+
+fn record_text(record: &Value, text_field: &str) -> Option<String> {
+    let value = match record {
+        Value::String(_) => record,
+        _ => text_field
+            .split('.')
+            .try_fold(record, |value, key| value.get(key))?,
+    };
+
+    value
+        .as_str()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_text_follows_nested_fields() {
+        let record = json!({ "post": { "body": " Red light! " }, "id": 1 });
+        assert_eq!(record_text(&record, "post.body").as_deref(), Some("Red light!"));
+        assert_eq!(record_text(&record, "post.title"), None);
+        assert_eq!(record_text(&record, "id"), None);
+        assert_eq!(record_text(&json!("Green light"), "text").as_deref(), Some("Green light"));
+    }
+}
@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::chunker::Chunker;
+use super::report::read_text;
+
+/// A piece of a markdown document along with the headings it sits under.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkdownChunk {
+    /// Titles of the enclosing headings, outermost first
+    pub heading_path: Vec<String>,
+    pub content: String,
+}
+
+impl MarkdownChunk {
+    /// Content prefixed with the heading path, so the chunk still says what
+    /// it is about once it's retrieved on its own.
+    pub fn with_breadcrumb(&self) -> String {
+        if self.heading_path.is_empty() {
+            self.content.clone()
+        } else {
+            format!("{}\n\n{}", self.heading_path.join(" > "), self.content)
+        }
+    }
+}
+
+/// Splits markdown into chunks along its structure.
+///
+/// Every heading starts a new section and sections are filled paragraph by
+/// paragraph with the [`Chunker`], so chunks respect its token limit and
+/// overlap. Fenced code blocks are never split nor repeated as overlap, even
+/// when that makes a chunk exceed the limit.
+#[derive(Clone, Debug, Default)]
+pub struct MarkdownChunker {
+    pub chunker: Chunker,
+}
+
+#[derive(Debug)]
+struct Block {
+    text: String,
+    code: bool,
+}
+
+impl MarkdownChunker {
+    pub fn new(chunker: Chunker) -> Self {
+        Self { chunker }
+    }
+
+    pub fn chunk(&self, text: &str) -> Vec<MarkdownChunk> {
+        parse_sections(text)
+            .into_iter()
+            .flat_map(|(heading_path, blocks)| {
+                let pieces = blocks
+                    .iter()
+                    .flat_map(|block| {
+                        if block.code {
+                            vec![self.chunker.whole(&block.text, "\n\n")]
+                        } else {
+                            self.chunker.pieces(&block.text, "\n\n")
+                        }
+                    })
+                    .collect();
+
+                self.chunker
+                    .pack(pieces)
+                    .into_iter()
+                    .map(move |content| MarkdownChunk {
+                        heading_path: heading_path.clone(),
+                        content,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Loads a markdown file as chunks, each prefixed with its heading path.
+pub fn load_markdown(path: &Path, chunker: &MarkdownChunker) -> Result<Vec<String>> {
+    let content = read_text(path)?;
+
+    Ok(chunker
+        .chunk(&content)
+        .iter()
+        .map(MarkdownChunk::with_breadcrumb)
+        .collect())
+}
+
+/// Splits markdown into sections at each heading, each holding its heading
+/// path and its paragraphs and code blocks.
+fn parse_sections(text: &str) -> Vec<(Vec<String>, Vec<Block>)> {
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut fence: Option<(String, Vec<&str>)> = None;
+
+    let flush_paragraph = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block {
+                text: paragraph.join("\n"),
+                code: false,
+            });
+            paragraph.clear();
+        }
+    };
+    let heading_path = |headings: &[(usize, String)]| {
+        headings.iter().map(|(_, title)| title.clone()).collect::<Vec<_>>()
+    };
+
+    for line in text.lines() {
+        if let Some((marker, lines)) = &mut fence {
+            lines.push(line);
+            if line.trim_start().starts_with(marker.as_str()) {
+                blocks.push(Block {
+                    text: lines.join("\n"),
+                    code: true,
+                });
+                fence = None;
+            }
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence_marker(trimmed) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            fence = Some((marker, vec![line]));
+        } else if let Some((level, title)) = heading(trimmed) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            if !blocks.is_empty() {
+                sections.push((heading_path(&headings), std::mem::take(&mut blocks)));
+            }
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, title));
+        } else if trimmed.is_empty() {
+            flush_paragraph(&mut paragraph, &mut blocks);
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    // An unterminated fence runs to the end of the document
+    if let Some((_, lines)) = fence {
+        blocks.push(Block {
+            text: lines.join("\n"),
+            code: true,
+        });
+    }
+    flush_paragraph(&mut paragraph, &mut blocks);
+    if !blocks.is_empty() {
+        sections.push((heading_path(&headings), blocks));
+    }
+
+    sections
+}
+
+/// Returns the `###` level and title of an ATX heading line.
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim();
+    Some((level, title.to_string()))
+}
+
+fn fence_marker(line: &str) -> Option<String> {
+    ["```", "~~~"]
+        .iter()
+        .find(|marker| line.starts_with(**marker))
+        .map(|marker| marker.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loaders::chunker::Tokenizer;
+
+    #[test]
+    fn test_chunks_carry_heading_path() {
+        let chunks = MarkdownChunker::default().chunk(
+            "Intro text\n\n# Guide\n\nStart here.\n\n## Install\n\nRun it.\n\n# FAQ\n\nAsk away.",
+        );
+        let paths: Vec<_> = chunks.iter().map(|c| c.heading_path.join(" > ")).collect();
+        assert_eq!(paths, ["", "Guide", "Guide > Install", "FAQ"]);
+        assert_eq!(chunks[2].content, "Run it.");
+        assert_eq!(chunks[2].with_breadcrumb(), "Guide > Install\n\nRun it.");
+    }
+
+    #[test]
+    fn test_code_blocks_are_kept_whole() {
+        let code = "```rust\n# not a heading\nfn main() {}\n\nlet x = 1;\n```";
+        let text = format!("# Example\n\nSome words before.\n\n{}\n\nAfter.", code);
+        let chunks = MarkdownChunker::new(Chunker::new(Tokenizer::Words, 4, 0)).chunk(&text);
+        assert!(chunks.iter().any(|c| c.content == code));
+        assert!(chunks.iter().all(|c| c.heading_path == ["Example"]));
+    }
+
+    #[test]
+    fn test_overlap_between_chunks() {
+        let text = "one two three four five\n\nsix seven eight nine ten";
+        let chunks = MarkdownChunker::new(Chunker::new(Tokenizer::Words, 10, 2)).chunk(text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].content, "one two three four five");
+        assert_eq!(chunks[1].content, "four five\n\nsix seven eight nine ten");
+    }
+
+    #[test]
+    fn test_long_paragraphs_are_split_within_the_limit() {
+        let text = "alpha beta gamma delta epsilon zeta";
+        let chunker = Chunker::new(Tokenizer::Words, 2, 0);
+        let chunks = MarkdownChunker::new(chunker.clone()).chunk(text);
+        assert!(chunks.iter().all(|c| chunker.count_tokens(&c.content) <= 2));
+        assert_eq!(
+            chunks.iter().map(|c| c.content.as_str()).collect::<Vec<_>>().join(" "),
+            text
+        );
+    }
+}
@@ -1,3 +1,17 @@
+pub mod chunker;
+pub mod dir;
+pub mod episode;
+pub mod epub;
 pub mod github;
+pub mod html;
+pub mod json;
+pub mod markdown;
 pub mod pdf;
-pub mod txt;
\ No newline at end of file
+pub mod report;
+pub mod transcript;
+pub mod txt;
+pub mod web;
+
+pub use chunker::{Boundary, Chunker, Tokenizer};
+pub use dir::{load_dir, load_dir_documents, load_file, LoaderConfig};
+pub use report::{FileChunks, FileReport, FileStatus, LoadReport};
@@ -1,26 +1,24 @@
 use anyhow::{Context, Result};
-use rig::{
-    loaders::PdfFileLoader,
-    Embed,
-};
-use serde::{Deserialize, Serialize};
-use tracing::{debug, error, info};
-use std::path::PathBuf;
-use std::fs;
+use rig::loaders::PdfFileLoader;
+use std::path::{Path, PathBuf};
 
-#[derive(Embed, Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
-struct Document {
-    id: String,
-    #[embed]
-    content: String,
+use super::chunker::Chunker;
+use super::report::{file_name, read_dir_sorted, FileChunks, LoadReport};
+
+/// Reads the text of every page of a PDF.
+pub(crate) fn read_pdf(path: &Path) -> Result<Vec<String>> {
+    let pattern = path.to_str().context("PDF path is not valid UTF-8")?;
+
+    Ok(PdfFileLoader::with_glob(pattern)?
+        .read()
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?)
 }
 
 pub fn load_dialog_pdf(path: PathBuf) -> Result<Vec<String>> {
     let mut chunks = Vec::new();
 
-    for entry in PdfFileLoader::with_glob(path.to_str().unwrap())?.read() {
-        let content = entry?;
-        println!("Raw PDF Content: {:?}", content);
+    for content in read_pdf(&path)? {
         // Split content by line breaks
         for line in content.lines() {
             let trimmed_line = line.trim();
@@ -37,35 +35,9 @@ pub fn load_dialog_pdf(path: PathBuf) -> Result<Vec<String>> {
     Ok(chunks)
 }
 
-pub fn load_pdf_flattened(path: PathBuf) -> Result<Vec<String>> {
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
-    let chunk_size = 2000; // Approximately 2000 characters per chunk
-
-    for entry in PdfFileLoader::with_glob(path.to_str().unwrap())?.read() {
-        let content = entry?;
-
-        // Split content into words
-        let words: Vec<&str> = content.split_whitespace().collect();
-
-        for word in words {
-            if current_chunk.len() + word.len() + 1 > chunk_size {
-                // If adding the next word would exceed chunk size,
-                // save current chunk and start a new one
-                if !current_chunk.is_empty() {
-                    chunks.push(current_chunk.trim().to_string());
-                    current_chunk.clear();
-                }
-            }
-            current_chunk.push_str(word);
-            current_chunk.push(' ');
-        }
-    }
-
-    // Don't forget the last chunk
-    if !current_chunk.is_empty() {
-        chunks.push(current_chunk.trim().to_string());
-    }
+/// Loads a PDF as chunks of its text, ignoring page breaks.
+pub fn load_pdf_flattened(path: PathBuf, chunker: &Chunker) -> Result<Vec<String>> {
+    let chunks = chunker.chunk(&read_pdf(&path)?.join("\n\n"));
 
     if chunks.is_empty() {
         anyhow::bail!("No content found in PDF file: {:?}", path);
@@ -74,24 +46,25 @@ pub fn load_pdf_flattened(path: PathBuf) -> Result<Vec<String>> {
     Ok(chunks)
 }
 
-pub fn load_pdfs_from_dir(documents_dir: PathBuf) -> Result<Vec<(String, Vec<String>)>> {
+/// Loads every PDF in a directory line by line. PDFs that fail to load are
+/// recorded in the report and skipped.
+pub fn load_pdfs_from_dir(documents_dir: PathBuf) -> Result<(FileChunks, LoadReport)> {
     let mut pdf_chunks = Vec::new();
+    let mut report = LoadReport::default();
 
-    for entry in fs::read_dir(&documents_dir).context("Failed to read documents directory")? {
-        let entry = entry.context("Failed to read entry")?;
-        let path = entry.path();
-
-        if path.extension().map_or(false, |ext| ext == "pdf") {
-            let file_name = path.file_name()
-                .and_then(|name| name.to_str())
-                .unwrap_or("Unknown file")
-                .to_string();
-
-            let chunks = load_dialog_pdf(path).with_context(|| format!("Failed to load {}", file_name))?;
+    for path in read_dir_sorted(&documents_dir)? {
+        if path.extension().is_none_or(|ext| ext != "pdf") {
+            continue;
+        }
 
-            pdf_chunks.push((file_name, chunks));
+        match load_dialog_pdf(path.clone()) {
+            Ok(chunks) => {
+                report.loaded(&path, chunks.len());
+                pdf_chunks.push((file_name(&path), chunks));
+            }
+            Err(err) => report.failed(&path, &err),
         }
     }
 
-    Ok(pdf_chunks)
+    Ok((pdf_chunks, report))
 }
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::{debug, info, warn};
+
+/// Chunks loaded from a directory, keyed by file name.
+pub type FileChunks<T = String> = Vec<(String, Vec<T>)>;
+
+/// What happened to a single file during loading.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileStatus {
+    Loaded { chunks: usize },
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub status: FileStatus,
+}
+
+/// Per-file outcome of loading a directory, returned alongside the loaded
+/// chunks so one unreadable file doesn't abort the rest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadReport {
+    pub files: Vec<FileReport>,
+}
+
+impl LoadReport {
+    pub fn loaded(&mut self, path: &Path, chunks: usize) {
+        debug!(?path, chunks, "Loaded file");
+        self.push(path, FileStatus::Loaded { chunks });
+    }
+
+    pub fn skipped(&mut self, path: &Path, reason: impl Into<String>) {
+        let reason = reason.into();
+        debug!(?path, reason, "Skipped file");
+        self.push(path, FileStatus::Skipped { reason });
+    }
+
+    pub fn failed(&mut self, path: &Path, error: &anyhow::Error) {
+        let error = format!("{:#}", error);
+        warn!(?path, error, "Failed to load file");
+        self.push(path, FileStatus::Failed { error });
+    }
+
+    /// Total number of chunks loaded.
+    pub fn chunks(&self) -> usize {
+        self.files
+            .iter()
+            .map(|file| match file.status {
+                FileStatus::Loaded { chunks } => chunks,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.count(|status| matches!(status, FileStatus::Loaded { .. }))
+    }
+
+    pub fn skipped_count(&self) -> usize {
+        self.count(|status| matches!(status, FileStatus::Skipped { .. }))
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.count(|status| matches!(status, FileStatus::Failed { .. }))
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, FileStatus::Failed { .. }))
+    }
+
+    /// Logs the totals of the report under `source`.
+    pub fn log_summary(&self, source: &str) {
+        info!(
+            source,
+            loaded = self.loaded_count(),
+            skipped = self.skipped_count(),
+            failed = self.failed_count(),
+            chunks = self.chunks(),
+            "Finished loading files"
+        );
+    }
+
+    fn push(&mut self, path: &Path, status: FileStatus) {
+        self.files.push(FileReport {
+            path: path.to_path_buf(),
+            status,
+        });
+    }
+
+    fn count(&self, predicate: impl Fn(&FileStatus) -> bool) -> usize {
+        self.files.iter().filter(|file| predicate(&file.status)).count()
+    }
+}
+
+/// Reads a text file, replacing invalid UTF-8 rather than failing.
+pub fn read_text(path: &Path) -> Result<String> {
+    let bytes = fs::read(path)?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(err) => {
+            warn!(?path, "File is not valid UTF-8, replacing invalid bytes");
+            String::from_utf8_lossy(err.as_bytes()).into_owned()
+        }
+    })
+}
+
+/// The entries of a directory, sorted by path.
+pub(crate) fn read_dir_sorted(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to read entry")?;
+    paths.sort();
+    Ok(paths)
+}
+
+/// The file name of `path` for keying loaded chunks.
+pub(crate) fn file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("Unknown file")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::temp_path;
+
+    #[test]
+    fn test_report_totals() {
+        let mut report = LoadReport::default();
+        report.loaded(Path::new("a.md"), 3);
+        report.loaded(Path::new("b.md"), 2);
+        report.skipped(Path::new("c.png"), "unsupported file type");
+        report.failed(Path::new("d.pdf"), &anyhow::anyhow!("broken xref"));
+
+        assert_eq!(report.chunks(), 5);
+        assert_eq!(
+            (report.loaded_count(), report.skipped_count(), report.failed_count()),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            report.failures().map(|file| &file.status).collect::<Vec<_>>(),
+            vec![&FileStatus::Failed { error: "broken xref".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_read_text_replaces_invalid_utf8() {
+        let path = temp_path("utf8", ".txt");
+        fs::write(&path, b"GI-HUN: Mugunghwa \xff kkochi\n").unwrap();
+
+        assert_eq!(read_text(&path).unwrap(), "GI-HUN: Mugunghwa \u{fffd} kkochi\n");
+
+        fs::remove_file(path).ok();
+    }
+}
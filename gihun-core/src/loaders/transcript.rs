@@ -0,0 +1,300 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::pdf::read_pdf;
+use super::report::{file_name, read_dir_sorted, read_text, FileChunks, LoadReport};
+
+/// One uninterrupted piece of dialogue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Turn {
+    pub speaker: String,
+    pub text: String,
+}
+
+/// A turn by one of the configured speakers, optionally with the turn it
+/// answers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogueLine {
+    pub turn: Turn,
+    /// The preceding turn, when it was spoken by someone else
+    pub context: Option<Turn>,
+}
+
+impl DialogueLine {
+    /// `Speaker: text` lines, the context first.
+    pub fn to_text(&self) -> String {
+        match &self.context {
+            Some(context) => format!(
+                "{}: {}\n{}: {}",
+                context.speaker, context.text, self.turn.speaker, self.turn.text
+            ),
+            None => format!("{}: {}", self.turn.speaker, self.turn.text),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TranscriptConfig {
+    /// Speakers whose lines are extracted, matched case-insensitively. A
+    /// transcript speaker also matches when it only adds a suffix, so
+    /// `Gi-hun` matches `GI-HUN ON PHONE`.
+    pub speakers: Vec<String>,
+    /// Keep the preceding turn from another speaker as context
+    pub include_context: bool,
+}
+
+impl TranscriptConfig {
+    pub fn new(speakers: Vec<String>) -> Self {
+        Self {
+            speakers,
+            include_context: false,
+        }
+    }
+
+    pub fn with_context(mut self, include_context: bool) -> Self {
+        self.include_context = include_context;
+        self
+    }
+
+    pub fn is_speaker(&self, speaker: &str) -> bool {
+        let speaker = normalize(speaker);
+        self.speakers.iter().any(|name| {
+            let name = normalize(name);
+            speaker == name || speaker.starts_with(&format!("{} ", name))
+        })
+    }
+
+    /// Lines spoken by the configured speakers.
+    pub fn extract(&self, turns: &[Turn]) -> Vec<DialogueLine> {
+        turns
+            .iter()
+            .enumerate()
+            .filter(|(_, turn)| self.is_speaker(&turn.speaker))
+            .map(|(i, turn)| DialogueLine {
+                turn: turn.clone(),
+                context: i
+                    .checked_sub(1)
+                    .map(|prev| &turns[prev])
+                    .filter(|prev| self.include_context && !self.is_speaker(&prev.speaker))
+                    .cloned(),
+            })
+            .collect()
+    }
+}
+
+/// Splits a transcript into turns.
+///
+/// Two layouts are recognised: `Speaker: line` as in subtitles and chat
+/// logs, and screenplays where an upper-case cue such as `GI-HUN` sits on
+/// its own line above the dialogue. Stage directions in parentheses are
+/// dropped, and one on its own line ends the current turn, as does a blank
+/// line. Lines that can't be attributed to a speaker are skipped.
+pub fn parse_turns(text: &str) -> Vec<Turn> {
+    let mut turns = Vec::new();
+    let mut speaker: Option<String> = None;
+    let mut lines: Vec<&str> = Vec::new();
+
+    let mut finish = |speaker: &mut Option<String>, lines: &mut Vec<&str>| {
+        if let (Some(name), false) = (speaker.as_ref(), lines.is_empty()) {
+            turns.push(Turn {
+                speaker: name.clone(),
+                text: lines.join(" "),
+            });
+        }
+        if !lines.is_empty() {
+            *speaker = None;
+        }
+        lines.clear();
+    };
+
+    let text = strip_directions(text);
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            finish(&mut speaker, &mut lines);
+        } else if is_cue(line) {
+            finish(&mut speaker, &mut lines);
+            speaker = Some(line.to_string());
+        } else if let Some((name, rest)) = speaker_prefix(line) {
+            finish(&mut speaker, &mut lines);
+            speaker = Some(name.to_string());
+            if !rest.is_empty() {
+                lines.push(rest);
+            }
+        } else if speaker.is_some() {
+            lines.push(line);
+        }
+    }
+    finish(&mut speaker, &mut lines);
+
+    turns
+}
+
+/// Reads the text of a `.txt` or `.pdf` transcript.
+pub fn read_transcript(path: &Path) -> Result<String> {
+    Ok(match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pdf") => read_pdf(path)?.join("\n"),
+        _ => read_text(path)?,
+    })
+}
+
+/// Reads a `.txt` or `.pdf` transcript and splits it into turns.
+pub fn load_transcript(path: &Path) -> Result<Vec<Turn>> {
+    Ok(parse_turns(&read_transcript(path)?))
+}
+
+/// Loads the configured speakers' lines from every `.txt` and `.pdf` file in
+/// a directory, keyed by file name. Files that fail to load are recorded in
+/// the report and skipped.
+pub fn load_transcripts_from_dir(
+    documents_dir: PathBuf,
+    config: &TranscriptConfig,
+) -> Result<(FileChunks<DialogueLine>, LoadReport)> {
+    let mut transcripts = Vec::new();
+    let mut report = LoadReport::default();
+
+    for path in read_dir_sorted(&documents_dir)? {
+        if !path.extension().is_some_and(|ext| ext == "txt" || ext == "pdf") {
+            continue;
+        }
+
+        match load_transcript(&path) {
+            Ok(turns) => {
+                let lines = config.extract(&turns);
+                report.loaded(&path, lines.len());
+                transcripts.push((file_name(&path), lines));
+            }
+            Err(err) => report.failed(&path, &err),
+        }
+    }
+
+    Ok((transcripts, report))
+}
+
+/// Replaces parenthesised stage directions, which may span lines, with a
+/// blank line when they stand alone and removes them when inline.
+fn strip_directions(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('(') {
+        let Some(len) = rest[start..].find(')') else {
+            break;
+        };
+        let before = &rest[..start];
+        out.push_str(before);
+
+        let line_start = before.rsplit('\n').next().unwrap_or_default();
+        if line_start.trim().is_empty() {
+            out.push_str("\n\n");
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// An upper-case screenplay cue, e.g. `FRONT MAN ON PHONE` or `MAN 2`.
+fn is_cue(line: &str) -> bool {
+    // Numbered extras keep their number as the last word
+    let name = match line.rsplit_once(' ') {
+        Some((name, number)) if number.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => line,
+    };
+
+    line.chars().count() <= 40
+        && name.chars().filter(|c| c.is_alphabetic()).count() >= 2
+        && name
+            .chars()
+            .all(|c| c.is_uppercase() || matches!(c, ' ' | '-' | '\'' | '.'))
+        && !line.ends_with('.')
+}
+
+/// Splits `Speaker: text`, accepting only short capitalised names so
+/// sentences containing a colon aren't mistaken for turns.
+fn speaker_prefix(line: &str) -> Option<(&str, &str)> {
+    let (name, rest) = line.split_once(':')?;
+    let name = name.trim();
+
+    let valid = !name.is_empty()
+        && name.chars().count() <= 30
+        && name.split_whitespace().count() <= 4
+        && name.chars().next().is_some_and(char::is_uppercase)
+        && name
+            .chars()
+            .all(|c| c.is_alphabetic() || matches!(c, ' ' | '-' | '\'' | '.'));
+
+    valid.then(|| (name, rest.trim()))
+}
+
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(speaker: &str, text: &str) -> Turn {
+        Turn {
+            speaker: speaker.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_colon_turns() {
+        let turns = parse_turns(
+            "Gi-hun: I'm not a horse.\n\nSang-woo: We have to win.\nWe have no choice.\n\nNote to self, 10:30",
+        );
+        assert_eq!(
+            turns,
+            vec![
+                turn("Gi-hun", "I'm not a horse."),
+                turn("Sang-woo", "We have to win. We have no choice."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_screenplay_turns() {
+        let turns = parse_turns(
+            "S02E01 - BREAD AND LOTTERY\n( \ntense music playing\n )\nFRONT MAN ON PHONE\n\
+            What do you think you're doing?\n( \nnervous gasp\n )\nMAN 2\nIdiot!\nGI-HUN\n\
+            How about you stop running your mouth\nand show yourself?\n( \ndoor opens\n )\n\
+            I'm sorry, kid.\nDOLL\n(  singing  ) Red light!",
+        );
+        assert_eq!(
+            turns,
+            vec![
+                turn("FRONT MAN ON PHONE", "What do you think you're doing?"),
+                turn("MAN 2", "Idiot!"),
+                turn("GI-HUN", "How about you stop running your mouth and show yourself?"),
+                turn("DOLL", "Red light!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_with_context() {
+        let turns = vec![
+            turn("FRONT MAN ON PHONE", "You have nowhere to go."),
+            turn("GI-HUN", "Show yourself."),
+            turn("Gi-hun", "Stop hiding."),
+        ];
+        let config = TranscriptConfig::new(vec!["Gi-hun".to_string()]).with_context(true);
+        let lines = config.extract(&turns);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0].to_text(),
+            "FRONT MAN ON PHONE: You have nowhere to go.\nGI-HUN: Show yourself."
+        );
+        assert_eq!(lines[1].to_text(), "Gi-hun: Stop hiding.");
+        assert!(!config.is_speaker("GI-HUNTER"));
+    }
+}
@@ -1,69 +1,96 @@
-use std::fs::{self, File};
-use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 
-/// Load lines from a single `.txt` file, considering only Gi-hun's dialogue and separating by blank lines.
-pub fn load_txt_lines(path: &Path) -> Result<Vec<String>, io::Error> {
-    let file = File::open(path)?;
-    let lines = io::BufReader::new(file)
-        .lines()
-        .filter_map(|line| line.ok()) // Filter out invalid lines gracefully
-        .collect::<Vec<String>>();
+use super::chunker::Chunker;
+use super::report::{file_name, read_dir_sorted, read_text, FileChunks, LoadReport};
+use super::transcript::{parse_turns, TranscriptConfig};
 
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
+/// Load the configured speakers' lines from a single `.txt` transcript, one chunk per turn.
+/// Turns over the chunker's limit are split.
+pub fn load_txt_lines(
+    path: &Path,
+    config: &TranscriptConfig,
+    chunker: &Chunker,
+) -> Result<Vec<String>> {
+    let content = read_text(path)?;
 
-    for line in lines {
-        let trimmed_line = line.trim();
+    Ok(config
+        .extract(&parse_turns(&content))
+        .iter()
+        .flat_map(|line| chunker.chunk(&line.to_text()))
+        .collect())
+}
 
-        // If the line is empty, treat this as a separator (new chunk)
-        if trimmed_line.is_empty() {
-            if !current_chunk.is_empty() {
-                chunks.push(current_chunk.clone());
-                current_chunk.clear();
-            }
+/// Load all `.txt` files from a directory and return the configured speakers' lines from each as separate chunks.
+///
+/// Files that fail to load are recorded in the report and skipped.
+pub fn load_txts_from_dir(
+    documents_dir: PathBuf,
+    config: &TranscriptConfig,
+    chunker: &Chunker,
+) -> Result<(FileChunks, LoadReport)> {
+    let mut txt_chunks = Vec::new();
+    let mut report = LoadReport::default();
+
+    for path in read_dir_sorted(&documents_dir)? {
+        if !path.is_file() {
+            continue;
+        }
+        if path.extension().is_none_or(|ext| ext != "txt") {
+            report.skipped(&path, "not a .txt file");
             continue;
         }
 
-        // Ensure that we are collecting only Gi-hun's lines
-        if let Some(speaker) = line.split(':').next() {
-            if speaker.trim() == "Gi-hun" {
-                current_chunk.push_str(trimmed_line);  // Add dialogue text to current chunk
-                current_chunk.push_str("\n");  // To retain the format, add newline
+        match load_txt_lines(&path, config, chunker) {
+            Ok(chunks) if chunks.is_empty() => {
+                report.skipped(&path, "no lines by the configured speakers");
+            }
+            Ok(chunks) => {
+                report.loaded(&path, chunks.len());
+                txt_chunks.push((file_name(&path), chunks));
             }
+            Err(err) => report.failed(&path, &err),
         }
     }
 
-    // If there’s any remaining chunk, push it (in case the last chunk is not followed by a blank line)
-    if !current_chunk.is_empty() {
-        chunks.push(current_chunk);
-    }
-
-    Ok(chunks)
+    Ok((txt_chunks, report))
 }
 
-/// Load all `.txt` files from a directory and return Gi-hun's dialogue from each as separate chunks.
-pub fn load_txts_from_dir(documents_dir: PathBuf) -> Result<Vec<(String, Vec<String>)>> {
-    let mut txt_chunks = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::temp_path;
+    use crate::loaders::report::FileStatus;
+    use std::fs;
 
-    for entry in fs::read_dir(&documents_dir).context("Failed to read documents directory")? {
-        let entry = entry.context("Failed to read entry")?;
-        let path = entry.path();
+    #[test]
+    fn test_load_txts_from_dir_reports_each_file() {
+        let dir = temp_path("txt", "");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), b"Gi-hun: I'm not a horse.\nSang-woo: You are.\nGi-hun: \xffFine.").unwrap();
+        fs::write(dir.join("b.txt"), "Sang-woo: Nothing from him here.").unwrap();
+        fs::write(dir.join("c.pdf"), "not a transcript").unwrap();
 
-        if path.extension().map_or(false, |ext| ext == "txt") { // Handle `.txt` files
-            let file_name = path.file_name()
-                .and_then(|name| name.to_str())
-                .unwrap_or("Unknown file")
-                .to_string();
+        let config = TranscriptConfig::new(vec!["Gi-hun".to_string()]);
+        let (chunks, report) = load_txts_from_dir(dir.clone(), &config, &Chunker::default()).unwrap();
 
-            let chunks = load_txt_lines(&path)
-                .with_context(|| format!("Failed to load {}", file_name))?;
+        assert_eq!(
+            chunks,
+            vec![(
+                "a.txt".to_string(),
+                vec!["Gi-hun: I'm not a horse.".to_string(), "Gi-hun: \u{fffd}Fine.".to_string()]
+            )]
+        );
+        assert_eq!(
+            report.files.iter().map(|file| &file.status).collect::<Vec<_>>(),
+            vec![
+                &FileStatus::Loaded { chunks: 2 },
+                &FileStatus::Skipped { reason: "no lines by the configured speakers".to_string() },
+                &FileStatus::Skipped { reason: "not a .txt file".to_string() },
+            ]
+        );
 
-            txt_chunks.push((file_name, chunks)); // Append filename and chunks
-        }
+        fs::remove_dir_all(dir).ok();
     }
-
-    Ok(txt_chunks)
-}
\ No newline at end of file
+}
@@ -0,0 +1,454 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use rig::embeddings::EmbeddingModel;
+use tracing::{debug, error, info};
+
+use super::html::html_to_text;
+use super::chunker::Chunker;
+use super::markdown::{MarkdownChunk, MarkdownChunker};
+use crate::embeddings::fnv1a;
+use crate::knowledge::{Document, KnowledgeBase, WEB_SOURCE};
+
+/// Maximum number of item keys remembered per feed
+const MAX_SEEN_ITEMS: usize = 500;
+
+#[derive(Clone, Debug)]
+pub struct WebLoaderConfig {
+    /// Web pages and RSS/Atom feeds to fetch
+    pub urls: Vec<String>,
+    /// Time to wait between refreshes
+    pub refresh_interval: Duration,
+    pub chunker: Chunker,
+}
+
+impl Default for WebLoaderConfig {
+    fn default() -> Self {
+        Self {
+            urls: Vec::new(),
+            refresh_interval: Duration::from_secs(60 * 60),
+            chunker: Chunker::default(),
+        }
+    }
+}
+
+/// An entry of an RSS or Atom feed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeedItem {
+    /// The item's guid or id, falling back to its link or title
+    pub key: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub text: String,
+    pub published: Option<DateTime<Utc>>,
+}
+
+impl FeedItem {
+    fn to_text(&self) -> String {
+        let mut text = format!("# {}\n\n{}", self.title, self.text);
+        if let Some(link) = &self.link {
+            text.push_str(&format!("\n\n{}", link));
+        }
+        text
+    }
+}
+
+/// Background task that keeps web pages and feeds in the knowledge base.
+///
+/// Feeds only add the items not seen on a previous refresh, dated with the
+/// item's publication date so [`KnowledgeBase::recent_documents`] finds
+/// fresh news. Pages are replaced whenever their readable text changes.
+pub struct WebLoader<E: EmbeddingModel + 'static> {
+    knowledge: KnowledgeBase<E>,
+    client: reqwest::Client,
+    config: WebLoaderConfig,
+}
+
+impl<E: EmbeddingModel + 'static> WebLoader<E> {
+    pub fn new(knowledge: KnowledgeBase<E>, config: WebLoaderConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("gihun/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            knowledge,
+            client,
+            config,
+        }
+    }
+
+    pub async fn start(&mut self) {
+        info!(
+            urls = self.config.urls.len(),
+            interval = ?self.config.refresh_interval,
+            "Starting web loader"
+        );
+
+        loop {
+            self.refresh_all().await;
+            tokio::time::sleep(self.config.refresh_interval).await;
+        }
+    }
+
+    /// Refreshes every configured URL, returning the number of documents
+    /// added. A URL that fails is logged and skipped.
+    pub async fn refresh_all(&mut self) -> usize {
+        let mut added = 0;
+        for url in self.config.urls.clone() {
+            match self.refresh(&url).await {
+                Ok(count) => {
+                    debug!(url, count, "Refreshed web source");
+                    added += count;
+                }
+                Err(err) => error!(url, ?err, "Failed to refresh web source"),
+            }
+        }
+        added
+    }
+
+    /// Fetches `url` and stores whatever is new, returning the number of
+    /// documents added.
+    pub async fn refresh(&mut self, url: &str) -> Result<usize> {
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let body = String::from_utf8_lossy(&response.bytes().await?).into_owned();
+
+        match parse_feed(&body) {
+            Some(items) => self.store_feed(url, items).await,
+            None => self.store_page(url, &body).await,
+        }
+    }
+
+    async fn store_feed(&mut self, url: &str, items: Vec<FeedItem>) -> Result<usize> {
+        let cursor_key = format!("web:{}", url);
+        let seen = self
+            .knowledge
+            .get_sync_cursor(&cursor_key)
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?
+            .unwrap_or_default();
+        let seen: HashSet<&str> = seen.lines().collect();
+
+        let tag = format!("feed:{}", url);
+        let chunker = MarkdownChunker::new(self.config.chunker.clone());
+        let documents: Vec<Document> = items
+            .iter()
+            .filter(|item| !seen.contains(item.key.as_str()))
+            .flat_map(|item| {
+                chunker
+                    .chunk(&item.to_text())
+                    .iter()
+                    .map(MarkdownChunk::with_breadcrumb)
+                    .enumerate()
+                    .map(|(i, content)| Document {
+                        id: format!("{}|{}#{}", url, item.key, i),
+                        source_id: WEB_SOURCE.to_string(),
+                        content,
+                        created_at: item.published.unwrap_or_else(Utc::now),
+                        tags: vec!["web".to_string(), tag.clone()],
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let added = documents.len();
+        if added > 0 {
+            self.knowledge.add_documents(documents).await?;
+        }
+
+        // Items that dropped out of the feed stay remembered until the list
+        // is full, so a feed that reorders doesn't bring them back
+        let current: HashSet<&str> = items.iter().map(|item| item.key.as_str()).collect();
+        let mut keys: Vec<&str> = items.iter().map(|item| item.key.as_str()).collect();
+        keys.extend(seen.iter().filter(|key| !current.contains(*key)));
+        keys.truncate(MAX_SEEN_ITEMS);
+        self.knowledge
+            .set_sync_cursor(&cursor_key, &keys.join("\n"))
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+
+        Ok(added)
+    }
+
+    async fn store_page(&mut self, url: &str, html: &str) -> Result<usize> {
+        let text = html_to_text(html);
+
+        let fingerprint = format!("{:016x}", fnv1a(text.as_bytes()));
+
+        let cursor_key = format!("web:{}", url);
+        let cursor = self
+            .knowledge
+            .get_sync_cursor(&cursor_key)
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+        if cursor.as_deref() == Some(fingerprint.as_str()) {
+            return Ok(0);
+        }
+
+        let prefix = format!("{}#", url);
+        self.knowledge
+            .delete_documents(WEB_SOURCE, &prefix)
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+
+        let tags = vec!["web".to_string(), format!("page:{}", url)];
+        let documents: Vec<Document> = MarkdownChunker::new(self.config.chunker.clone())
+            .chunk(&text)
+            .iter()
+            .map(MarkdownChunk::with_breadcrumb)
+            .enumerate()
+            .map(|(i, content)| Document {
+                id: format!("{}{}", prefix, i),
+                source_id: WEB_SOURCE.to_string(),
+                content,
+                created_at: Utc::now(),
+                tags: tags.clone(),
+            })
+            .collect();
+
+        let added = documents.len();
+        if added > 0 {
+            self.knowledge
+                .add_documents(documents)
+                .await
+                .context("Failed to store page")?;
+        }
+
+        self.knowledge
+            .set_sync_cursor(&cursor_key, &fingerprint)
+            .await
+            .map_err(|e| anyhow!("{:?}", e))?;
+
+        Ok(added)
+    }
+}
+
+/// Parses an RSS 2.0, RSS 1.0 or Atom feed. Returns `None` if `body` isn't
+/// a feed.
+pub fn parse_feed(body: &str) -> Option<Vec<FeedItem>> {
+    let document = roxmltree::Document::parse(body.trim_start()).ok()?;
+    let root = document.root_element();
+
+    let (entry, atom) = match root.tag_name().name() {
+        "rss" | "RDF" => ("item", false),
+        "feed" => ("entry", true),
+        _ => return None,
+    };
+
+    Some(
+        root.descendants()
+            .filter(|node| node.tag_name().name() == entry)
+            .filter_map(|node| feed_item(node, atom))
+            .collect(),
+    )
+}
+
+fn feed_item(node: roxmltree::Node, atom: bool) -> Option<FeedItem> {
+    let child = |name: &str| {
+        node.children()
+            .find(|child| child.tag_name().name() == name)
+            .and_then(|child| child.text())
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    };
+
+    let title = child("title").unwrap_or_default().to_string();
+
+    let link = if atom {
+        node.children()
+            .filter(|child| child.tag_name().name() == "link")
+            .find(|child| child.attribute("rel").unwrap_or("alternate") == "alternate")
+            .and_then(|child| child.attribute("href"))
+    } else {
+        child("link")
+    }
+    .map(String::from);
+
+    let content = if atom {
+        child("content").or_else(|| child("summary"))
+    } else {
+        child("encoded").or_else(|| child("description"))
+    };
+    let text = content.map(html_to_text).unwrap_or_default();
+
+    let published = if atom {
+        child("published")
+            .or_else(|| child("updated"))
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+    } else {
+        child("pubDate")
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .or_else(|| child("date").and_then(|date| DateTime::parse_from_rfc3339(date).ok()))
+    }
+    .map(|date| date.with_timezone(&Utc));
+
+    let key = child(if atom { "id" } else { "guid" })
+        .map(String::from)
+        .or_else(|| link.clone())
+        .or_else(|| Some(title.clone()).filter(|title| !title.is_empty()))?;
+
+    if title.is_empty() && text.is_empty() {
+        return None;
+    }
+
+    Some(FeedItem {
+        key,
+        title,
+        link,
+        text,
+        published,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::{open_test_knowledge_base, temp_path};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    type Routes = Arc<Mutex<HashMap<String, (&'static str, String)>>>;
+
+    fn rss(items: &[(&str, &str)]) -> String {
+        let items: String = items
+            .iter()
+            .map(|(guid, title)| {
+                format!(
+                    "<item><guid>{guid}</guid><title>{title}</title>\
+                     <link>https://news.example/{guid}</link>\
+                     <description>&lt;p&gt;About {title}.&lt;/p&gt;</description>\
+                     <pubDate>Tue, 10 Jun 2025 04:00:00 GMT</pubDate></item>"
+                )
+            })
+            .collect();
+        format!(r#"<?xml version="1.0"?><rss version="2.0"><channel><title>News</title>{items}</channel></rss>"#)
+    }
+
+    /// Serves `routes` over plain HTTP/1.1, one request per connection.
+    async fn serve(routes: Routes) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+                    let route = routes.lock().unwrap().get(path).cloned();
+                    let response = match route {
+                        Some((content_type, body)) => format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            content_type,
+                            body.len(),
+                            body
+                        ),
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string(),
+                    };
+                    stream.write_all(response.as_bytes()).await.ok();
+                });
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_parse_atom_feed() {
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title>
+            <entry><id>urn:1</id><title>Season two</title>
+              <link rel="self" href="https://blog.example/self"/>
+              <link href="https://blog.example/season-two"/>
+              <summary type="html">&lt;b&gt;Filming&lt;/b&gt; wrapped</summary>
+              <updated>2025-06-10T04:00:00Z</updated></entry>
+            </feed>"#;
+
+        let items = parse_feed(atom).unwrap();
+        assert_eq!(
+            items,
+            vec![FeedItem {
+                key: "urn:1".to_string(),
+                title: "Season two".to_string(),
+                link: Some("https://blog.example/season-two".to_string()),
+                text: "Filming wrapped".to_string(),
+                published: Some("2025-06-10T04:00:00Z".parse().unwrap()),
+            }]
+        );
+        assert_eq!(parse_feed("<html><body>Not a feed</body></html>"), None);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_adds_only_new_items() {
+        let routes: Routes = Arc::new(Mutex::new(HashMap::from([
+            (
+                "/feed.xml".to_string(),
+                ("application/rss+xml", rss(&[("a", "Doll spotted"), ("b", "Games return")])),
+            ),
+            (
+                "/page".to_string(),
+                ("text/html", "<html><body><p>Recruitment is open.</p></body></html>".to_string()),
+            ),
+        ])));
+        let base = serve(routes.clone()).await;
+
+        let path = temp_path("web", ".db");
+        let knowledge = open_test_knowledge_base(path.to_str().unwrap(), 32).await;
+
+        let feed = format!("{}/feed.xml", base);
+        let page = format!("{}/page", base);
+        let mut loader = WebLoader::new(
+            knowledge.clone(),
+            WebLoaderConfig {
+                urls: vec![feed.clone(), page.clone(), format!("{}/missing", base)],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(loader.refresh_all().await, 3);
+        assert_eq!(loader.refresh_all().await, 0);
+
+        routes.lock().unwrap().insert(
+            "/feed.xml".to_string(),
+            ("application/rss+xml", rss(&[("c", "Prize grows"), ("a", "Doll spotted")])),
+        );
+        assert_eq!(loader.refresh(&feed).await.unwrap(), 1);
+
+        let since = "2025-01-01T00:00:00Z".parse().unwrap();
+        let documents = knowledge.recent_documents(WEB_SOURCE, since, 10).await.unwrap();
+        let mut ids: Vec<_> = documents.iter().map(|doc| doc.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec![
+                format!("{}|a#0", feed),
+                format!("{}|b#0", feed),
+                format!("{}|c#0", feed),
+                format!("{}#0", page),
+            ]
+        );
+
+        let item = documents.iter().find(|doc| doc.id.ends_with("|c#0")).unwrap();
+        assert_eq!(
+            item.content,
+            "Prize grows\n\nAbout Prize grows.\n\nhttps://news.example/c"
+        );
+        assert!(item.tags.contains(&format!("feed:{}", feed)));
+
+        std::fs::remove_file(path).ok();
+    }
+}
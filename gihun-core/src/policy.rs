@@ -0,0 +1,353 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use rig::embeddings::EmbeddingModel;
+use rig_sqlite::SqliteError;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::knowledge::{EngagementMode, EngagementPolicy, KnowledgeBase, Source};
+
+/// Engagement policies and admins read from a TOML file:
+///
+/// ```toml
+/// admins = ["discord:123456789012345678", "telegram:42"]
+///
+/// [[policy]]
+/// source = "discord"
+/// mode = "mention-only"
+///
+/// [[policy]]
+/// source = "discord"
+/// channel = "987654321098765432"
+/// mode = "always"
+/// block = ["111111111111111111"]
+/// ```
+///
+/// A policy without a channel applies to every channel of its source.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PolicyFile {
+    /// `<source>:<user id>` of users allowed to run admin commands
+    #[serde(default)]
+    pub admins: Vec<String>,
+    #[serde(default, rename = "policy")]
+    pub policies: Vec<PolicyEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PolicyEntry {
+    pub source: String,
+    /// `None` for every channel of the source
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub mode: EngagementMode,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub block: Vec<String>,
+}
+
+impl PolicyFile {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        info!(path = path, "Loading engagement policies");
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy file {}", path))?;
+        let file: Self = toml::from_str(&content)
+            .with_context(|| format!("Invalid policy file {}", path))?;
+
+        for entry in &file.policies {
+            if Source::from_str(&entry.source).is_none() {
+                anyhow::bail!("Unknown source {:?} in policy file {}", entry.source, path);
+            }
+        }
+        for admin in &file.admins {
+            match admin.split_once(':') {
+                Some((source, id)) if Source::from_str(source).is_some() && !id.is_empty() => {}
+                _ => anyhow::bail!("Expected <source>:<user id> for admin {:?} in {}", admin, path),
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Stores the policies of the file, replacing the ones it stored before
+    /// but not those an admin has changed with a command since. Returns how
+    /// many were stored.
+    pub async fn apply<E: EmbeddingModel>(
+        &self,
+        knowledge: &KnowledgeBase<E>,
+    ) -> Result<usize, SqliteError> {
+        let mut applied = 0;
+        for entry in &self.policies {
+            let Some(source) = Source::from_str(&entry.source) else {
+                continue;
+            };
+            let policy = EngagementPolicy {
+                mode: entry.mode,
+                allowed_users: entry.allow.clone(),
+                blocked_users: entry.block.clone(),
+            };
+            if knowledge
+                .set_file_engagement_policy(&source, entry.channel.as_deref(), &policy)
+                .await?
+            {
+                applied += 1;
+            } else {
+                info!(
+                    source = %entry.source,
+                    channel = ?entry.channel,
+                    "Keeping the engagement policy set by an admin over the policy file"
+                );
+            }
+        }
+        Ok(applied)
+    }
+
+    pub fn admins(&self) -> Admins {
+        Admins::new(self.admins.clone())
+    }
+}
+
+/// Users allowed to run admin commands, as `<source>:<user id>`.
+#[derive(Clone, Debug, Default)]
+pub struct Admins {
+    ids: HashSet<String>,
+}
+
+impl Admins {
+    pub fn new(ids: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            ids: ids.into_iter().map(|id| id.to_lowercase()).collect(),
+        }
+    }
+
+    pub fn contains(&self, source: &Source, user_id: &str) -> bool {
+        self.ids
+            .contains(&format!("{}:{}", source.as_str(), user_id.to_lowercase()))
+    }
+}
+
+/// An admin chat command changing the policy of the channel it's sent in:
+///
+/// - `!policy` shows the policy
+/// - `!policy mode <default|always|mention-only|never>`
+/// - `!policy allow|disallow <user>` edits the allowlist
+/// - `!policy block|unblock <user>` edits the blocklist
+///
+/// Users may be given as ids or Discord mentions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyCommand {
+    Show,
+    Mode(EngagementMode),
+    Allow(String),
+    Disallow(String),
+    Block(String),
+    Unblock(String),
+}
+
+impl PolicyCommand {
+    /// `None` when `text` isn't a policy command, an error message when it is
+    /// one but can't be understood.
+    pub fn parse(text: &str) -> Option<Result<Self, String>> {
+        let mut words = text.split_whitespace();
+        if !matches!(words.next(), Some("!policy" | "/policy")) {
+            return None;
+        }
+
+        let command = match (words.next(), words.next(), words.next()) {
+            (None | Some("show"), None, _) => Ok(PolicyCommand::Show),
+            (Some("mode"), Some(mode), None) => EngagementMode::from_str(mode)
+                .map(PolicyCommand::Mode)
+                .ok_or_else(|| {
+                    format!("Unknown mode {}, expected default, always, mention-only or never", mode)
+                }),
+            (Some(action), Some(user), None) => {
+                let user = user_id(user);
+                match action {
+                    "allow" => Ok(PolicyCommand::Allow(user)),
+                    "disallow" => Ok(PolicyCommand::Disallow(user)),
+                    "block" => Ok(PolicyCommand::Block(user)),
+                    "unblock" => Ok(PolicyCommand::Unblock(user)),
+                    _ => Err(format!("Unknown policy command {}", action)),
+                }
+            }
+            _ => Err(
+                "Usage: !policy [show | mode <mode> | allow|disallow|block|unblock <user>]".to_string(),
+            ),
+        };
+        Some(command)
+    }
+
+    /// Applies the command to the channel's own policy and describes the
+    /// policy that now applies there.
+    pub async fn run<E: EmbeddingModel>(
+        &self,
+        knowledge: &KnowledgeBase<E>,
+        source: &Source,
+        channel_id: &str,
+    ) -> Result<String, SqliteError> {
+        if *self != PolicyCommand::Show {
+            let mut policy = knowledge
+                .stored_engagement_policy(source, Some(channel_id))
+                .await?;
+            match self {
+                PolicyCommand::Show => {}
+                PolicyCommand::Mode(mode) => policy.mode = *mode,
+                PolicyCommand::Allow(user) => add(&mut policy.allowed_users, user),
+                PolicyCommand::Disallow(user) => policy.allowed_users.retain(|u| u != user),
+                PolicyCommand::Block(user) => add(&mut policy.blocked_users, user),
+                PolicyCommand::Unblock(user) => policy.blocked_users.retain(|u| u != user),
+            }
+            knowledge
+                .set_engagement_policy(source, Some(channel_id), &policy)
+                .await?;
+        }
+
+        let policy = knowledge.engagement_policy(source, channel_id).await?;
+        Ok(describe(&policy))
+    }
+}
+
+fn add(users: &mut Vec<String>, user: &str) {
+    if !users.iter().any(|u| u == user) {
+        users.push(user.to_string());
+    }
+}
+
+/// The id in a Discord mention such as `<@123>` or `<@!123>`, or `user`
+/// itself.
+pub(crate) fn user_id(user: &str) -> String {
+    user.strip_prefix("<@")
+        .and_then(|user| user.strip_suffix('>'))
+        .map(|user| user.trim_start_matches('!'))
+        .unwrap_or(user)
+        .to_string()
+}
+
+fn describe(policy: &EngagementPolicy) -> String {
+    let users = |users: &[String]| {
+        if users.is_empty() {
+            "none".to_string()
+        } else {
+            users.join(", ")
+        }
+    };
+    format!(
+        "Mode: {}\nAllowed: {}\nBlocked: {}",
+        policy.mode.as_str(),
+        if policy.allowed_users.is_empty() {
+            "everyone".to_string()
+        } else {
+            users(&policy.allowed_users)
+        },
+        users(&policy.blocked_users)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::testing::test_knowledge_base;
+
+    #[test]
+    fn test_parse_policy_commands() {
+        assert_eq!(PolicyCommand::parse("hello there"), None);
+        assert_eq!(PolicyCommand::parse("!policy"), Some(Ok(PolicyCommand::Show)));
+        assert_eq!(
+            PolicyCommand::parse("!policy mode mention-only"),
+            Some(Ok(PolicyCommand::Mode(EngagementMode::MentionOnly)))
+        );
+        assert_eq!(
+            PolicyCommand::parse("/policy block <@!1234>"),
+            Some(Ok(PolicyCommand::Block("1234".to_string())))
+        );
+        assert!(matches!(PolicyCommand::parse("!policy mode loud"), Some(Err(_))));
+        assert!(matches!(PolicyCommand::parse("!policy ban 1234"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_policy_file_and_admins() {
+        let file: PolicyFile = toml::from_str(
+            r#"
+            admins = ["discord:1234"]
+
+            [[policy]]
+            source = "discord"
+            mode = "mention-only"
+            block = ["5678"]
+
+            [[policy]]
+            source = "discord"
+            channel = "channel-1"
+            mode = "always"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(file.policies.len(), 2);
+        assert_eq!(file.policies[0].channel, None);
+        assert_eq!(file.policies[1].mode, EngagementMode::Always);
+
+        let admins = file.admins();
+        assert!(admins.contains(&Source::Discord, "1234"));
+        assert!(!admins.contains(&Source::Telegram, "1234"));
+
+        let source_wide = EngagementPolicy {
+            mode: EngagementMode::MentionOnly,
+            allowed_users: vec![],
+            blocked_users: vec!["5678".to_string()],
+        };
+        let channel = EngagementPolicy {
+            blocked_users: vec!["9999".to_string()],
+            ..Default::default()
+        };
+        let merged = source_wide.merge(&channel);
+        assert_eq!(merged.mode, EngagementMode::MentionOnly);
+        assert_eq!(merged.blocked_users, vec!["5678".to_string(), "9999".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_policy_file_keeps_admin_edits() {
+        let knowledge = test_knowledge_base(16).await;
+        let file = |mode: &str| -> PolicyFile {
+            toml::from_str(&format!(
+                r#"
+                [[policy]]
+                source = "discord"
+                mode = "{mode}"
+
+                [[policy]]
+                source = "discord"
+                channel = "channel-1"
+                mode = "{mode}"
+                "#
+            ))
+            .unwrap()
+        };
+        let mode = |channel_id: Option<&'static str>| {
+            let knowledge = &knowledge;
+            async move {
+                knowledge
+                    .stored_engagement_policy(&Source::Discord, channel_id)
+                    .await
+                    .unwrap()
+                    .mode
+            }
+        };
+
+        assert_eq!(file("mention-only").apply(&knowledge).await.unwrap(), 2);
+        let admin_edit = EngagementPolicy {
+            mode: EngagementMode::Always,
+            ..Default::default()
+        };
+        knowledge
+            .set_engagement_policy(&Source::Discord, Some("channel-1"), &admin_edit)
+            .await
+            .unwrap();
+
+        // A changed file still updates what it set, but not the admin's edit
+        assert_eq!(file("never").apply(&knowledge).await.unwrap(), 1);
+        assert_eq!(mode(None).await, EngagementMode::Never);
+        assert_eq!(mode(Some("channel-1")).await, EngagementMode::Always);
+    }
+}
@@ -0,0 +1,209 @@
+use rig::completion::{self, CompletionError, CompletionRequest};
+use rig::providers::{anthropic, openai};
+use tracing::info;
+
+/// `max_tokens` sent to Anthropic when the request doesn't set one, since its
+/// API rejects requests without it.
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provider {
+    /// OpenAI API
+    Openai,
+    /// Anthropic API
+    Anthropic,
+    /// Any server speaking the OpenAI chat completions API, e.g. llama.cpp or Ollama
+    OpenaiCompatible,
+}
+
+impl Provider {
+    /// Model used for generating responses when none is configured.
+    pub fn default_model(&self) -> Option<&'static str> {
+        match self {
+            Provider::Openai => Some(openai::GPT_4O),
+            Provider::Anthropic => Some(anthropic::CLAUDE_3_5_SONNET),
+            Provider::OpenaiCompatible => None,
+        }
+    }
+
+    /// Cheaper model used for attention decisions when none is configured.
+    pub fn default_attention_model(&self) -> Option<&'static str> {
+        match self {
+            Provider::Openai => Some("gpt-4o-mini"),
+            Provider::Anthropic => Some(anthropic::CLAUDE_3_HAIKU),
+            Provider::OpenaiCompatible => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProviderConfig {
+    pub provider: Provider,
+    pub model: String,
+    pub api_key: String,
+    /// Overrides the provider's API URL. Required for [`Provider::OpenaiCompatible`].
+    pub base_url: Option<String>,
+}
+
+impl ProviderConfig {
+    /// Config of the attention classifier, with what isn't given taken from
+    /// this completion config: its provider, the provider's cheaper attention
+    /// model or else the same model, and its base URL when both use the same
+    /// provider.
+    pub fn attention(
+        &self,
+        provider: Option<Provider>,
+        model: Option<String>,
+        base_url: Option<String>,
+        api_key: impl FnOnce(Provider) -> String,
+    ) -> Self {
+        let provider = provider.unwrap_or(self.provider);
+        Self {
+            provider,
+            model: model
+                .or_else(|| provider.default_attention_model().map(String::from))
+                .unwrap_or_else(|| self.model.clone()),
+            api_key: api_key(provider),
+            base_url: base_url.or_else(|| {
+                (provider == self.provider)
+                    .then(|| self.base_url.clone())
+                    .flatten()
+            }),
+        }
+    }
+}
+
+/// Completion model backed by whichever provider was selected at startup, so
+/// the agent and the attention classifier can run on different providers
+/// while sharing a single model type.
+#[derive(Clone)]
+pub enum ProviderModel {
+    OpenAI(openai::CompletionModel),
+    Anthropic(anthropic::completion::CompletionModel),
+}
+
+impl ProviderModel {
+    pub fn new(config: &ProviderConfig) -> anyhow::Result<Self> {
+        info!(
+            provider = ?config.provider,
+            model = %config.model,
+            base_url = ?config.base_url,
+            "Creating completion model"
+        );
+
+        let model = match config.provider {
+            Provider::Openai => {
+                let client = match &config.base_url {
+                    Some(base_url) => openai::Client::from_url(&config.api_key, base_url),
+                    None => openai::Client::new(&config.api_key),
+                };
+                ProviderModel::OpenAI(client.completion_model(&config.model))
+            }
+            Provider::OpenaiCompatible => {
+                let base_url = config.base_url.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("A base URL is required for OpenAI-compatible providers")
+                })?;
+                let client = openai::Client::from_url(&config.api_key, base_url);
+                ProviderModel::OpenAI(client.completion_model(&config.model))
+            }
+            Provider::Anthropic => {
+                let mut builder = anthropic::ClientBuilder::new(&config.api_key);
+                if let Some(base_url) = &config.base_url {
+                    builder = builder.base_url(base_url);
+                }
+                ProviderModel::Anthropic(builder.build().completion_model(&config.model))
+            }
+        };
+
+        Ok(model)
+    }
+}
+
+pub enum ProviderResponse {
+    OpenAI(openai::CompletionResponse),
+    Anthropic(anthropic::completion::CompletionResponse),
+}
+
+impl completion::CompletionModel for ProviderModel {
+    type Response = ProviderResponse;
+
+    async fn completion(
+        &self,
+        mut request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<ProviderResponse>, CompletionError> {
+        match self {
+            ProviderModel::OpenAI(model) => {
+                let response = model.completion(request).await?;
+                Ok(completion::CompletionResponse {
+                    choice: response.choice,
+                    raw_response: ProviderResponse::OpenAI(response.raw_response),
+                })
+            }
+            ProviderModel::Anthropic(model) => {
+                request.max_tokens.get_or_insert(ANTHROPIC_DEFAULT_MAX_TOKENS);
+                let response = model.completion(request).await?;
+                Ok(completion::CompletionResponse {
+                    choice: response.choice,
+                    raw_response: ProviderResponse::Anthropic(response.raw_response),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    fn completion(provider: Provider, base_url: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            provider,
+            model: "llama3".to_string(),
+            api_key: "completion-key".to_string(),
+            base_url: base_url.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_provider_names_and_default_models() {
+        assert_eq!(Provider::from_str("openai", true), Ok(Provider::Openai));
+        assert_eq!(Provider::from_str("anthropic", true), Ok(Provider::Anthropic));
+        assert_eq!(
+            Provider::from_str("openai-compatible", true),
+            Ok(Provider::OpenaiCompatible)
+        );
+        assert!(Provider::from_str("ollama", true).is_err());
+
+        assert_eq!(Provider::Openai.default_model(), Some(openai::GPT_4O));
+        assert_eq!(Provider::Anthropic.default_attention_model(), Some(anthropic::CLAUDE_3_HAIKU));
+        assert_eq!(Provider::OpenaiCompatible.default_model(), None);
+        assert_eq!(Provider::OpenaiCompatible.default_attention_model(), None);
+    }
+
+    #[test]
+    fn test_attention_config_falls_back_to_completion() {
+        let key = |provider: Provider| format!("{:?}-key", provider);
+
+        // Same local server and model unless told otherwise
+        let local = completion(Provider::OpenaiCompatible, Some("http://localhost:11434"));
+        let attention = local.attention(None, None, None, key);
+        assert_eq!(attention.provider, Provider::OpenaiCompatible);
+        assert_eq!(attention.model, "llama3");
+        assert_eq!(attention.api_key, "OpenaiCompatible-key");
+        assert_eq!(attention.base_url.as_deref(), Some("http://localhost:11434"));
+
+        let attention = local.attention(None, Some("phi3".to_string()), None, key);
+        assert_eq!(attention.model, "phi3");
+
+        // Another provider doesn't inherit the base URL
+        let attention = local.attention(Some(Provider::Anthropic), None, None, key);
+        assert_eq!(attention.model, anthropic::CLAUDE_3_HAIKU);
+        assert_eq!(attention.base_url, None);
+
+        let attention = completion(Provider::Openai, Some("https://proxy.example.com"))
+            .attention(None, None, Some("http://localhost:8080".to_string()), key);
+        assert_eq!(attention.model, "gpt-4o-mini");
+        assert_eq!(attention.base_url.as_deref(), Some("http://localhost:8080"));
+    }
+}
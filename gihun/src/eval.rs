@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use gihun_core::attention::{Attention, TopicFilter};
+use gihun_core::character::Character;
+use gihun_core::evaluation::evaluate;
+use rig::completion::CompletionModel;
+
+use crate::kb::snippet;
+
+/// Replays the labelled messages in `dataset` through the attention of
+/// `character` and prints precision, recall and the misjudged messages.
+pub async fn run<M: CompletionModel>(
+    character: &Character,
+    completion_model: M,
+    model_name: &str,
+    topic_filter: Option<impl TopicFilter + 'static>,
+    dataset: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut attention = Attention::from_character(character, completion_model).with_model_name(model_name);
+    if let Some(topic_filter) = topic_filter {
+        attention = attention.with_topic_filter(topic_filter);
+    }
+
+    let report = evaluate(&attention, BufReader::new(File::open(dataset)?)).await?;
+
+    println!("Model:     {}", model_name);
+    println!("{}", report);
+    if !report.mistakes.is_empty() {
+        println!();
+        for mistake in &report.mistakes {
+            println!(
+                "{}  {}  ({})",
+                if mistake.expected { "missed " } else { "unwanted" },
+                if mistake.id.is_empty() { snippet(&mistake.content, 60) } else { mistake.id.clone() },
+                mistake.reason
+            );
+        }
+    }
+
+    Ok(())
+}
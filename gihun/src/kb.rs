@@ -0,0 +1,274 @@
+use std::io::{BufReader, BufWriter, Write};
+
+use clap::Subcommand;
+use gihun_core::knowledge::{
+    stored_embedding_model, DocumentFilter, ExportOptions, KnowledgeBase, RetentionPolicy, Source,
+};
+use rig::embeddings::EmbeddingModel;
+use tokio_rusqlite::Connection;
+
+/// Inspects and manages the knowledge base in `--db-path`.
+#[derive(Subcommand)]
+pub enum KbCommand {
+    /// Documents and messages per source, embedding model and database size
+    Stats,
+    /// Documents most relevant to a query, with their similarity
+    Search {
+        query: String,
+        /// Number of documents to show
+        #[arg(long, short = 'k', default_value_t = 5)]
+        limit: usize,
+        /// Only search documents of this source
+        #[arg(long)]
+        source: Option<String>,
+    },
+    /// Full content and metadata of a document
+    Show { id: String },
+    /// Delete every document of a source
+    Delete {
+        #[arg(long)]
+        source: String,
+    },
+    /// Write documents, messages, accounts and channels as JSON lines
+    Export {
+        /// File to write, standard output when omitted
+        #[arg(long, short)]
+        output: Option<String>,
+        /// Only export documents of this source
+        #[arg(long)]
+        source: Option<String>,
+        /// Include vectors, so importing with the same model skips embedding
+        #[arg(long)]
+        vectors: bool,
+    },
+    /// Load an export, embedding it again if it was made with another model
+    Import { input: String },
+    /// Delete messages older than --retention allows
+    Purge,
+}
+
+/// Deletes stored data on request.
+#[derive(Subcommand)]
+pub enum ForgetCommand {
+    /// Delete every message of a user and their account
+    User {
+        /// Platform of the user: discord, telegram, twitter or x
+        source: String,
+        /// The user's id on the platform
+        id: String,
+    },
+}
+
+pub async fn run<E: EmbeddingModel + 'static>(
+    command: KbCommand,
+    db_path: &str,
+    retention: &RetentionPolicy,
+    embedding_model: E,
+    embedding_model_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let knowledge = open(db_path, embedding_model, embedding_model_name).await?;
+
+    match command {
+        KbCommand::Stats => {
+            let stats = knowledge.stats().await.map_err(|e| format!("{:?}", e))?;
+
+            println!("Database:           {} ({})", db_path, format_size(stats.size_bytes));
+            println!(
+                "Embedding model:    {} ({} dimensions)",
+                stats.embedding_model, stats.embedding_dims
+            );
+            println!("Pending embeddings: {}", stats.pending_embeddings);
+            print_counts("Documents", &stats.documents);
+            print_counts("Messages", &stats.messages);
+        }
+        KbCommand::Search {
+            query,
+            limit,
+            source,
+        } => {
+            let filter = source.map(DocumentFilter::source).unwrap_or_default();
+            let results = knowledge
+                .filtered_document_index(filter)
+                .top_n_with_similarity(&query, limit)
+                .await?;
+
+            if results.is_empty() {
+                println!("No documents found");
+            }
+            for (similarity, id, document) in results {
+                let source_id = document["source_id"].as_str().unwrap_or_default();
+                let content = document["content"].as_str().unwrap_or_default();
+                println!("{:.3}  {}  [{}]", similarity, id, source_id);
+                println!("       {}", snippet(content, 160));
+            }
+        }
+        KbCommand::Show { id } => {
+            let document = knowledge
+                .get_document(&id)
+                .await
+                .map_err(|e| format!("{:?}", e))?
+                .ok_or_else(|| format!("No document with id {}", id))?;
+
+            println!("Id:      {}", document.id);
+            println!("Source:  {}", document.source_id);
+            println!("Created: {}", document.created_at.to_rfc3339());
+            println!("Tags:    {}", document.tags.join(", "));
+            println!();
+            println!("{}", document.content);
+        }
+        KbCommand::Delete { source } => {
+            let deleted = knowledge
+                .delete_documents(&source, "")
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            println!("Deleted {} documents from {}", deleted, source);
+        }
+        KbCommand::Export {
+            output,
+            source,
+            vectors,
+        } => {
+            let writer: BufWriter<Box<dyn Write>> = BufWriter::new(match &output {
+                Some(path) => Box::new(std::fs::File::create(path)?),
+                None => Box::new(std::io::stdout()),
+            });
+            let options = ExportOptions {
+                include_vectors: vectors,
+                source_id: source,
+            };
+            let summary = knowledge.export(writer, options).await?;
+
+            if let Some(path) = output {
+                eprintln!(
+                    "Exported {} documents, {} messages, {} accounts and {} channels to {}",
+                    summary.documents, summary.messages, summary.accounts, summary.channels, path
+                );
+            }
+        }
+        KbCommand::Import { input } => {
+            let mut knowledge = knowledge;
+            let reader = BufReader::new(std::fs::File::open(&input)?);
+            let summary = knowledge.import(reader).await?;
+
+            println!(
+                "Imported {} documents, {} messages, {} accounts and {} channels from {}",
+                summary.documents, summary.messages, summary.accounts, summary.channels, input
+            );
+            if summary.reembedded > 0 {
+                println!(
+                    "{} documents and messages had no vectors for {}, messages are embedded when the bot starts",
+                    summary.reembedded, embedding_model_name
+                );
+            }
+        }
+        KbCommand::Purge => {
+            if retention.is_empty() {
+                return Err("No --retention rules given, every message is kept".into());
+            }
+            let purged = knowledge
+                .purge_expired_messages(retention, chrono::Utc::now())
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            println!("Deleted {} expired messages", purged);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn forget<E: EmbeddingModel + 'static>(
+    command: ForgetCommand,
+    db_path: &str,
+    embedding_model: E,
+    embedding_model_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let knowledge = open(db_path, embedding_model, embedding_model_name).await?;
+
+    match command {
+        ForgetCommand::User { source, id } => {
+            let platform =
+                Source::from_str(&source).ok_or_else(|| format!("Unknown source {}", source))?;
+            let summary = knowledge
+                .forget_user(platform, &id)
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            println!(
+                "Deleted {} messages and {} accounts of {} user {}",
+                summary.messages, summary.accounts, source, id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the knowledge base, refusing databases built with another embedding
+/// model since opening them would rebuild their vectors.
+async fn open<E: EmbeddingModel + 'static>(
+    db_path: &str,
+    embedding_model: E,
+    embedding_model_name: &str,
+) -> Result<KnowledgeBase<E>, Box<dyn std::error::Error>> {
+    let conn = Connection::open(db_path).await?;
+
+    let stored = stored_embedding_model(&conn)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    match stored {
+        Some((Some(name), dims)) if name != embedding_model_name || dims != embedding_model.ndims() => {
+            return Err(format!(
+                "{} was built with {} ({} dimensions), choose the matching --embedding-provider",
+                db_path, name, dims
+            )
+            .into());
+        }
+        Some((None, dims)) => {
+            return Err(format!(
+                "{} was built before its embedding model was recorded ({} dimensions), \
+                run gihun on it once to rebuild the vectors",
+                db_path, dims
+            )
+            .into());
+        }
+        _ => {}
+    }
+
+    Ok(KnowledgeBase::new(conn, embedding_model, embedding_model_name).await?)
+}
+
+fn print_counts(title: &str, counts: &[(String, usize)]) {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    println!("{}: {}", title, total);
+
+    let width = counts.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+    for (name, count) in counts {
+        println!("  {:width$}  {}", name, count, width = width);
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// The first `max_chars` characters of `text` on a single line.
+pub(crate) fn snippet(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max_chars {
+        text
+    } else {
+        format!("{}…", text.chars().take(max_chars).collect::<String>())
+    }
+}
@@ -1,27 +1,81 @@
-use clap::{command, Parser};
+mod eval;
+mod kb;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use gihun_core::loaders::episode::ingest_episodes;
+use gihun_core::loaders::{load_dir_documents, Chunker, LoaderConfig, Tokenizer};
+use gihun_core::loaders::github::{GitFilter, GitLoader};
+use gihun_core::loaders::transcript::TranscriptConfig;
 use gihun_core::loaders::txt::load_txts_from_dir;
+use gihun_core::loaders::web::{WebLoader, WebLoaderConfig};
+use rig::embeddings::EmbeddingModel;
 use rig::providers::{self, openai};
-use gihun_core::attention::{Attention, AttentionConfig};
+use gihun_core::attention::{Attention, EmbeddingTopicFilter};
 use anyhow::Result;
 
 use gihun_core::character;
+use gihun_core::embeddings::HashingEmbeddingModel;
+use gihun_core::evaluation::StaticCompletionModel;
 use gihun_core::init_logging;
-use gihun_core::knowledge::KnowledgeBase;
-use gihun_core::knowledge::Document;
+use gihun_core::policy::{Admins, PolicyFile};
+use gihun_core::knowledge::{
+    parse_age, ContextConfig, EmbeddingWorker, EmbeddingWorkerConfig, KnowledgeBase, LlmReranker,
+    RetentionPolicy, RetentionRule, RetentionWorker, RetentionWorkerConfig, RetrievalMode,
+};
+use gihun_core::knowledge::{Document, DIALOGUE_SOURCE, DOCS_SOURCE, TRANSCRIPT_SOURCE};
+use gihun_core::providers::{Provider, ProviderConfig, ProviderModel};
+use gihun_core::admin::{Admin, TweetTrigger};
+use gihun_core::clients::discord::DiscordClient;
+use gihun_core::clients::telegram::TelegramClient;
 use gihun_core::{agent::Agent, clients::twitter::TwitterClient};
 use sqlite_vec::sqlite3_vec_init;
 use tokio_rusqlite::ffi::sqlite3_auto_extension;
+use tracing::error;
 use tokio_rusqlite::Connection;
-use gihun_core::loaders::pdf::load_pdfs_from_dir;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum EmbeddingProvider {
+    /// OpenAI `text-embedding-3-large`
+    Openai,
+    /// Local feature-hashing embedder, no API key or network needed
+    Hashing,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect and manage the knowledge base instead of running the bot
+    Kb {
+        #[command(subcommand)]
+        command: kb::KbCommand,
+    },
+    /// Delete stored data on request instead of running the bot
+    Forget {
+        #[command(subcommand)]
+        command: kb::ForgetCommand,
+    },
+    /// Replay a JSON lines dataset of messages labelled with whether the bot
+    /// should reply, and report the precision and recall of its attention
+    Eval {
+        dataset: String,
+        /// Answer every classifier request with this relevance instead of
+        /// calling the attention model, to evaluate rules and thresholds alone
+        #[arg(long)]
+        mock_relevance: Option<f32>,
+    },
+}
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to character profile TOML file
     #[arg(long, default_value = "gihun/src/characters/gihun.toml")]
     character: String,
 
-    /// Path to database
+    /// Path to database. The default `:memory:` database is lost on exit, use
+    /// a file to keep the knowledge base, or `kb export` and `kb import`
     #[arg(long, default_value = ":memory:")]
     db_path: String,
 
@@ -33,10 +87,76 @@ struct Args {
     #[arg(long, env = "OPENAI_API_KEY", default_value = "")]
     openai_api_key: String,
 
-    /// GitHub repository URL
-    #[arg(long, default_value = "https://github.com/cartridge-gg/docs")]
-    github_repo: String,
+    /// Anthropic API key (can also be set via ANTHROPIC_API_KEY env var)
+    #[arg(long, env = "ANTHROPIC_API_KEY", default_value = "")]
+    anthropic_api_key: String,
+
+    /// Completion provider used to generate responses
+    #[arg(long, env = "COMPLETION_PROVIDER", value_enum, default_value_t = Provider::Openai)]
+    completion_provider: Provider,
+
+    /// Completion model used to generate responses (defaults per provider)
+    #[arg(long, env = "COMPLETION_MODEL")]
+    completion_model: Option<String>,
+
+    /// Base URL of the completion API, e.g. http://localhost:11434 for Ollama
+    #[arg(long, env = "COMPLETION_BASE_URL")]
+    completion_base_url: Option<String>,
+
+    /// Completion provider used for attention decisions (defaults to --completion-provider)
+    #[arg(long, env = "ATTENTION_PROVIDER", value_enum)]
+    attention_provider: Option<Provider>,
+
+    /// Cheaper model used for attention decisions (defaults per provider)
+    #[arg(long, env = "ATTENTION_MODEL")]
+    attention_model: Option<String>,
+
+    /// Base URL of the attention completion API (defaults to --completion-base-url
+    /// when both use the same provider)
+    #[arg(long, env = "ATTENTION_BASE_URL")]
+    attention_base_url: Option<String>,
+
+    /// GitHub repository URL to ingest into the knowledge base
+    #[arg(long, env = "GITHUB_REPO")]
+    github_repo: Option<String>,
 
+    /// Branch, tag or commit of the GitHub repository to ingest
+    #[arg(long, default_value = gihun_core::loaders::github::DEFAULT_REFERENCE)]
+    github_ref: String,
+
+    /// Only ingest repository files matching these globs (all files by default)
+    #[arg(long, value_delimiter = ',')]
+    github_include: Vec<String>,
+
+    /// Skip repository files matching these globs
+    #[arg(long, value_delimiter = ',')]
+    github_exclude: Vec<String>,
+
+    /// Maximum tokens per document chunk, counted with the embedding model's tokenizer
+    #[arg(long, default_value_t = 512)]
+    chunk_max_tokens: usize,
+
+    /// Tokens repeated between consecutive chunks of a document
+    #[arg(long, default_value_t = 64)]
+    chunk_overlap_tokens: usize,
+
+    /// Directory of Markdown, HTML, JSON/JSONL, EPUB, PDF and text files to add to the knowledge base
+    #[arg(long, env = "DOCS_PATH")]
+    docs_path: Option<String>,
+
+    /// Field holding the text of JSON/JSONL records in --docs-path
+    #[arg(long, default_value = "text")]
+    docs_json_field: String,
+
+    /// Web pages and RSS/Atom feeds to follow for news
+    #[arg(long, env = "WEB_URLS", value_delimiter = ',')]
+    web_url: Vec<String>,
+
+    /// Minutes between refreshes of --web-url
+    #[arg(long, default_value = "60")]
+    web_refresh_mins: u64,
+
+    /// Directory of episode transcripts named like `s02e01.pdf`
     #[arg(long, default_value = "transcripts/")]
     transcript_path: String,
 
@@ -44,12 +164,12 @@ struct Args {
     #[arg(long, default_value = ".repo")]
     github_path: String,
     /// Twitter username
-    #[arg(long, env = "TWITTER_USERNAME")]
-    twitter_username: String,
+    #[arg(long, env = "TWITTER_USERNAME", required = true)]
+    twitter_username: Option<String>,
 
     /// Twitter password
-    #[arg(long, env = "TWITTER_PASSWORD")]
-    twitter_password: String,
+    #[arg(long, env = "TWITTER_PASSWORD", required = true)]
+    twitter_password: Option<String>,
 
     /// Twitter email (optional, for 2FA)
     #[arg(long, env = "TWITTER_EMAIL")]
@@ -66,11 +186,148 @@ struct Args {
     #[arg(long, env = "GALADRIEL_API_KEY")]
     galadriel_api_key: Option<String>,
 
+    /// Embedding model used for documents and messages
+    #[arg(long, value_enum, default_value_t = EmbeddingProvider::Openai)]
+    embedding_provider: EmbeddingProvider,
+
+    /// Vector dimension of the local hashing embedder
+    #[arg(long, default_value_t = gihun_core::embeddings::HASHING_EMBEDDING_DIMS)]
+    hashing_dims: usize,
+
+    /// How documents are retrieved as context for the agent
+    #[arg(long, value_enum, default_value_t = RetrievalMode::Hybrid)]
+    retrieval_mode: RetrievalMode,
+
+    /// Maximum number of messages embedded per request by the background worker
+    #[arg(long, default_value_t = 32)]
+    embedding_batch_size: usize,
+
+    /// Seconds between polls of the message embedding queue
+    #[arg(long, default_value_t = 5)]
+    embedding_interval_secs: u64,
+
+    /// How long messages are kept, as `<source>[:<channel type>]=<age>` with
+    /// `*` for every source and an age in h, d or w, e.g. `discord:direct_message=30d`.
+    /// Messages no rule matches are kept forever
+    #[arg(long, env = "RETENTION", value_delimiter = ',')]
+    retention: Vec<RetentionRule>,
+
+    /// How long logged attention decisions are kept, as an age in h, d or w.
+    /// Decisions about a message are also deleted with it
+    #[arg(long, env = "ATTENTION_LOG_RETENTION", default_value = "90d", value_parser = parse_age)]
+    attention_log_retention: chrono::Duration,
+
+    /// TOML file of per-channel engagement policies and admins, stored at
+    /// startup over the policies it stored before. Channels whose policy an
+    /// admin changed with `!policy` keep the admin's policy
+    #[arg(long, env = "POLICIES_PATH")]
+    policies: Option<String>,
+
+    /// `<source>:<user id>` of users allowed to run admin commands on Discord
+    /// and Telegram, in addition to the `admins` of --policies
+    #[arg(long, env = "ADMINS", value_delimiter = ',')]
+    admins: Vec<String>,
+
+    /// Minutes between purges of messages past their --retention and
+    /// attention decisions past --attention-log-retention
+    #[arg(long, default_value_t = 60)]
+    retention_interval_mins: u64,
+
+    /// Relevance score from 0 to 1 the attention model must give a message
+    /// before the bot joins a conversation uninvited (overrides the character's
+    /// `[attention]` setting)
+    #[arg(long, env = "REPLY_THRESHOLD")]
+    reply_threshold: Option<f32>,
+
+    /// Messages others must send after the bot replies before it joins a
+    /// conversation uninvited again (overrides the character's `[attention]` setting)
+    #[arg(long)]
+    cooldown_messages: Option<i64>,
+
+    /// Minutes the bot stays quiet in a channel after being told to stop,
+    /// unless addressed by name (overrides the character's `[attention]` setting)
+    #[arg(long)]
+    mute_mins: Option<u64>,
+
+    /// Skip the attention model for messages unrelated to the character's
+    /// topics, interests and conversation types, judged by embedding similarity
+    #[arg(long)]
+    topic_filter: bool,
+
+    /// Similarity to the closest topic below which --topic-filter ignores a
+    /// message (overrides the character's `[attention]` setting)
+    #[arg(long)]
+    min_topic_similarity: Option<f64>,
+
+    /// Minimum cosine similarity for a document to be added as context,
+    /// unless it contains the names in the message, or all of its words that
+    /// aren't stop words
+    #[arg(long, default_value_t = ContextConfig::default().min_similarity)]
+    context_min_similarity: f64,
+
+    /// Token budget for documents added as context, counted with the
+    /// completion model's tokenizer
+    #[arg(long, default_value_t = ContextConfig::default().token_budget)]
+    context_token_budget: usize,
+
+    /// Rerank retrieved documents with the attention model before adding them as context
+    #[arg(long)]
+    rerank: bool,
+
+    /// Store the line each dialogue line answers alongside it
+    #[arg(long)]
+    dialogue_context: bool,
+
     /// Telegram bot token
-    #[arg(long, env = "TELEGRAM_BOT_TOKEN")]
+    #[arg(long, env = "TELEGRAM_BOT_TOKEN", default_value = "")]
     telegram_bot_token: String,
 }
 
+/// `[attention]` settings given on the command line, which win over the
+/// character file's, also when it's reloaded.
+#[derive(Clone, Copy)]
+struct AttentionOverrides {
+    reply_threshold: Option<f32>,
+    cooldown_messages: Option<i64>,
+    mute_mins: Option<u64>,
+    min_topic_similarity: Option<f64>,
+}
+
+impl AttentionOverrides {
+    fn apply(&self, character: &mut character::Character) {
+        if let Some(reply_threshold) = self.reply_threshold {
+            character.attention.reply_threshold = reply_threshold;
+        }
+        if let Some(cooldown_messages) = self.cooldown_messages {
+            character.attention.cooldown_messages = cooldown_messages;
+        }
+        if let Some(mute_mins) = self.mute_mins {
+            character.attention.mute_duration = std::time::Duration::from_secs(mute_mins * 60);
+        }
+        if let Some(min_topic_similarity) = self.min_topic_similarity {
+            character.attention.min_topic_similarity = min_topic_similarity;
+        }
+    }
+}
+
+impl Args {
+    fn attention_overrides(&self) -> AttentionOverrides {
+        AttentionOverrides {
+            reply_threshold: self.reply_threshold,
+            cooldown_messages: self.cooldown_messages,
+            mute_mins: self.mute_mins,
+            min_topic_similarity: self.min_topic_similarity,
+        }
+    }
+
+    fn api_key(&self, provider: Provider) -> String {
+        match provider {
+            Provider::Openai | Provider::OpenaiCompatible => self.openai_api_key.clone(),
+            Provider::Anthropic => self.anthropic_api_key.clone(),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     init_logging();
@@ -78,73 +335,268 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let args = Args::parse();
 
-    // let repo = GitLoader::new(args.github_repo, &args.github_path)?;
-
     let character_content =
         std::fs::read_to_string(&args.character).expect("Failed to read character file");
     
     let character: character::Character = toml::from_str(&character_content)
         .map_err(|e| format!("Failed to parse character TOML: {}\nContent: {}", e, character_content))?;
 
-    let oai = providers::openai::Client::new(&args.openai_api_key);
-    let embedding_model = oai.embedding_model(openai::TEXT_EMBEDDING_3_LARGE);
-    let completion_model = oai.completion_model(openai::GPT_4O);
-    let should_respond_completion_model = oai.completion_model(openai::GPT_4O);
-
     // Initialize the `sqlite-vec`extension
     // See: https://alexgarcia.xyz/sqlite-vec/rust.html
     unsafe {
-        sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
+        sqlite3_auto_extension(Some(std::mem::transmute::<
+            *const (),
+            unsafe extern "C" fn(
+                *mut tokio_rusqlite::ffi::sqlite3,
+                *mut *mut std::ffi::c_char,
+                *const tokio_rusqlite::ffi::sqlite3_api_routines,
+            ) -> std::ffi::c_int,
+        >(sqlite3_vec_init as *const ())));
+    }
+
+    match args.embedding_provider {
+        EmbeddingProvider::Openai => {
+            let oai = providers::openai::Client::new(&args.openai_api_key);
+            let embedding_model = oai.embedding_model(openai::TEXT_EMBEDDING_3_LARGE);
+            run(args, character, embedding_model, openai::TEXT_EMBEDDING_3_LARGE).await
+        }
+        EmbeddingProvider::Hashing => {
+            let embedding_model = HashingEmbeddingModel::new(args.hashing_dims);
+            let model_name = embedding_model.model_name();
+            run(args, character, embedding_model, &model_name).await
+        }
+    }
+}
+
+async fn run<E: EmbeddingModel + 'static>(
+    args: Args,
+    mut character: character::Character,
+    embedding_model: E,
+    embedding_model_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let retention = RetentionPolicy::new(args.retention.clone())
+        .with_attention_log_max_age(args.attention_log_retention);
+    match args.command {
+        Some(Command::Kb { command }) => {
+            return kb::run(command, &args.db_path, &retention, embedding_model, embedding_model_name)
+                .await;
+        }
+        Some(Command::Forget { command }) => {
+            return kb::forget(command, &args.db_path, embedding_model, embedding_model_name).await;
+        }
+        Some(Command::Eval { .. }) | None => {}
     }
 
+    let overrides = args.attention_overrides();
+    overrides.apply(&mut character);
+
+    let completion_config = ProviderConfig {
+        provider: args.completion_provider,
+        model: args
+            .completion_model
+            .clone()
+            .or_else(|| args.completion_provider.default_model().map(String::from))
+            .ok_or("--completion-model is required for this provider")?,
+        api_key: args.api_key(args.completion_provider),
+        base_url: args.completion_base_url.clone(),
+    };
+
+    let attention_config = completion_config.attention(
+        args.attention_provider,
+        args.attention_model.clone(),
+        args.attention_base_url.clone(),
+        |provider| args.api_key(provider),
+    );
+
+    if let Some(Command::Eval { dataset, mock_relevance }) = &args.command {
+        let topic_filter = if args.topic_filter {
+            let topics = character.attention_config().topics();
+            Some(EmbeddingTopicFilter::new(embedding_model, topics).await?)
+        } else {
+            None
+        };
+        return match mock_relevance {
+            Some(relevance) => {
+                let model = StaticCompletionModel::relevance(*relevance);
+                eval::run(&character, model, "static", topic_filter, dataset).await
+            }
+            None => {
+                let model = ProviderModel::new(&attention_config)?;
+                eval::run(&character, model, &attention_config.model, topic_filter, dataset).await
+            }
+        };
+    }
+
+    let completion_model = ProviderModel::new(&completion_config)?;
+    let should_respond_completion_model = ProviderModel::new(&attention_config)?;
+
     let conn = Connection::open(args.db_path).await?;
-    let mut knowledge = KnowledgeBase::new(conn.clone(), embedding_model).await?;
+    let topic_embedding_model = embedding_model.clone();
+    let mut knowledge = KnowledgeBase::new(conn.clone(), embedding_model, embedding_model_name)
+        .await?
+        .with_retrieval_mode(args.retrieval_mode);
+
+    let chunker = Chunker::new(
+        Tokenizer::for_model(embedding_model_name),
+        args.chunk_max_tokens,
+        args.chunk_overlap_tokens,
+    );
 
     let dialogue_dir = std::env::current_dir()?.join("./dialogue");
 
-    let knowledge_chunks = load_txts_from_dir(dialogue_dir);
-    
-    let mut documents: Vec<Document> = Vec::new();
-
-    for (i, chunks) in knowledge_chunks.iter().enumerate() {
-        for (key, values) in chunks {
-            for chunk in values{
-                documents.push(Document {
-                    id: format!("{}-{}", i, key), // Combine the index and the key (chunk.0)
-                    source_id: "dialogue".to_string(),
-                    content: chunk.to_string(),
+    let transcript_config =
+        TranscriptConfig::new(character.speakers()).with_context(args.dialogue_context);
+    if dialogue_dir.is_dir() {
+        let (dialogue, report) = load_txts_from_dir(dialogue_dir, &transcript_config, &chunker)?;
+        report.log_summary(DIALOGUE_SOURCE);
+
+        let documents: Vec<Document> = dialogue
+            .into_iter()
+            .flat_map(|(file_name, lines)| {
+                lines.into_iter().enumerate().map(move |(i, content)| Document {
+                    id: format!("{}#{}", file_name, i),
+                    source_id: DIALOGUE_SOURCE.to_string(),
+                    content,
                     created_at: chrono::Utc::now(),
-                });
-                print!("{}", chunk.to_string())
-            }
+                    tags: vec![],
+                })
+            })
+            .collect();
 
+        // Replace rather than add to the dialogue, so lines removed from the
+        // files don't linger
+        knowledge.delete_documents(DIALOGUE_SOURCE, "").await.map_err(|e| format!("{:?}", e))?;
+        if !documents.is_empty() {
+            knowledge.add_documents(documents).await?;
         }
     }
-        
-    knowledge
-        .add_documents(
-           documents
-        )
-        .await?;
 
-    let agent = Agent::new(character, completion_model, knowledge);
+    if let Some(docs_path) = &args.docs_path {
+        let config = LoaderConfig {
+            chunker: chunker.clone(),
+            json_text_field: args.docs_json_field.clone(),
+        };
+        let (documents, report) =
+            load_dir_documents(std::path::Path::new(docs_path), &config, DOCS_SOURCE)?;
+        report.log_summary(DOCS_SOURCE);
+        knowledge.delete_documents(DOCS_SOURCE, "").await.map_err(|e| format!("{:?}", e))?;
+        if !documents.is_empty() {
+            knowledge.add_documents(documents).await?;
+        }
+    }
 
-    let config = AttentionConfig {
-        bot_names: vec![agent.character.name.clone()],
-        ..Default::default()
-    };
-    let attention = Attention::new(config, should_respond_completion_model);
-    // let telegram = TelegramClient::new(agent.clone(), attention.clone(), args.telegram_bot_token);
-    // let discord = DiscordClient::new(agent.clone(), attention.clone());
+    let transcript_dir = std::path::Path::new(&args.transcript_path);
+    if transcript_dir.is_dir() {
+        ingest_episodes(transcript_dir, &mut knowledge, &chunker)
+            .await?
+            .log_summary(TRANSCRIPT_SOURCE);
+    }
+
+    if let Some(github_repo) = &args.github_repo {
+        let filter = GitFilter::new(&args.github_include, &args.github_exclude)?;
+        let repo = GitLoader::new_with_reference(github_repo.clone(), &args.github_path, &args.github_ref)?
+            .with_chunker(chunker.clone());
+        repo.ingest(&mut knowledge, &filter).await?;
+    }
+
+    let mut admin_ids = args.admins.clone();
+    if let Some(path) = &args.policies {
+        let policies = PolicyFile::load(path)?;
+        policies
+            .apply(&knowledge)
+            .await
+            .map_err(|e| format!("Failed to store engagement policies: {:?}", e))?;
+        admin_ids.extend(policies.admins);
+    }
+
+    let embedding_worker = EmbeddingWorker::new(
+        knowledge.clone(),
+        EmbeddingWorkerConfig {
+            batch_size: args.embedding_batch_size,
+            poll_interval: std::time::Duration::from_secs(args.embedding_interval_secs),
+        },
+    );
+    tokio::spawn(async move { embedding_worker.start().await });
+
+    if !retention.is_empty() {
+        let retention_worker = RetentionWorker::new(
+            knowledge.clone(),
+            RetentionWorkerConfig {
+                policy: retention,
+                interval: std::time::Duration::from_secs(args.retention_interval_mins * 60),
+            },
+        );
+        tokio::spawn(async move { retention_worker.start().await });
+    }
+
+    if !args.web_url.is_empty() {
+        let mut web_loader = WebLoader::new(
+            knowledge.clone(),
+            WebLoaderConfig {
+                urls: args.web_url.clone(),
+                refresh_interval: std::time::Duration::from_secs(args.web_refresh_mins * 60),
+                chunker: chunker.clone(),
+            },
+        );
+        tokio::spawn(async move { web_loader.start().await });
+    }
+
+    let mut agent = Agent::new(character, completion_model, knowledge).with_context_config(
+        ContextConfig {
+            min_similarity: args.context_min_similarity,
+            token_budget: args.context_token_budget,
+            tokenizer: Tokenizer::for_completion_model(&completion_config.model),
+            ..Default::default()
+        },
+    );
+    if args.rerank {
+        agent = agent.with_reranker(LlmReranker::new(should_respond_completion_model.clone()));
+    }
+
+    let mut attention = Attention::from_character(&agent.character(), should_respond_completion_model)
+        .with_model_name(&attention_config.model);
+    if args.topic_filter {
+        let topics = attention.config().topics();
+        attention = attention.with_topic_filter(EmbeddingTopicFilter::new(topic_embedding_model, topics).await?);
+    }
+
+    let tweet_trigger = TweetTrigger::default();
+    let character_path = args.character.clone();
+    let admin = Admin::new(agent.clone(), attention.clone(), Admins::new(admin_ids))
+        .with_character_loader(move || {
+            let content = std::fs::read_to_string(&character_path)
+                .map_err(|e| format!("Failed to read {}: {}", character_path, e))?;
+            let mut character: character::Character =
+                toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", character_path, e))?;
+            overrides.apply(&mut character);
+            Ok(character)
+        })
+        .with_tweet_trigger(tweet_trigger.clone());
+
+    if !args.telegram_bot_token.is_empty() {
+        let telegram = TelegramClient::new(agent.clone(), attention.clone(), args.telegram_bot_token.clone())
+            .with_admin(admin.clone());
+        tokio::spawn(async move { telegram.start().await });
+    }
+    if !args.discord_api_token.is_empty() {
+        let discord = DiscordClient::new(agent.clone(), attention.clone()).with_admin(admin.clone());
+        let token = args.discord_api_token.clone();
+        tokio::spawn(async move {
+            if let Err(err) = discord.start(&token).await {
+                error!(?err, "Discord client stopped");
+            }
+        });
+    }
     let twitter = TwitterClient::new(
         agent.clone(),
         attention.clone(),
-        args.twitter_username,
-        args.twitter_password,
+        args.twitter_username.ok_or("--twitter-username is required")?,
+        args.twitter_password.ok_or("--twitter-password is required")?,
         args.twitter_email,
         args.twitter_2fa_code,
         args.twitter_cookie_string,
-    ).await?;
+    ).await?
+    .with_tweet_trigger(tweet_trigger);
     
 
     twitter.start().await;